@@ -0,0 +1,175 @@
+// Aliases live in the [Alias] category of the config:
+/*
+ * [Alias]
+ * st = status
+ * undo = reset 1
+ * ll = !ls -la        (starts with '!', runs through the shell)
+ */
+
+use std::process::Command;
+
+use anyhow::anyhow;
+
+use crate::config::{self, Config};
+
+pub enum Expansion {
+    Args(Vec<String>), // argv with all aliases replaced
+    Shell { command: String, args: Vec<String> },
+}
+
+/// Splits an alias value into words, handles '...', "..." and backslash escapes
+fn split_words(value: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, quote) {
+            ('\'', None) | ('"', None) => {
+                quote = Some(ch);
+                in_word = true;
+            }
+            (ch, Some(open)) if ch == open => quote = None,
+            ('\\', Some('\'')) => word.push(ch),
+            ('\\', _) => {
+                word.push(chars.next().ok_or(anyhow!("Alias ends with a backslash"))?);
+                in_word = true;
+            }
+            (ch, None) if ch.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            _ => {
+                word.push(ch);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Unterminated quote in alias '{}'", value));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Replaces args[1] while it names an alias. Built-in commands can't be overridden
+pub fn expand(args: &[String], config: &Config, builtins: &[String]) -> anyhow::Result<Expansion> {
+    let mut args = args.to_vec();
+    let mut chain: Vec<String> = Vec::new();
+
+    while let Some(name) = args.get(1).cloned() {
+        if name.starts_with('-') || builtins.contains(&name) {
+            break;
+        }
+        let Some(value) = config.get(config::CONFIG_ALIAS_SECTION, &name) else {
+            break;
+        };
+
+        if chain.contains(&name) {
+            chain.push(name);
+            return Err(anyhow!("Recursive alias: {}", chain.join(" -> ")));
+        }
+        chain.push(name.clone());
+
+        if let Some(command) = value.strip_prefix('!') {
+            return Ok(Expansion::Shell {
+                command: command.to_owned(),
+                args: args[2..].to_vec(),
+            });
+        }
+
+        let words = split_words(&value)?;
+        if words.is_empty() {
+            return Err(anyhow!("Alias '{}' is empty", name));
+        }
+        args.splice(1..2, words);
+    }
+    Ok(Expansion::Args(args))
+}
+
+/// Expands aliases from the layered config. The config is only read if args[1] is not a built-in command
+pub fn expand_args(args: Vec<String>, builtins: &[String]) -> anyhow::Result<Expansion> {
+    match args.get(1) {
+        Some(name) if !name.starts_with('-') && !builtins.contains(name) => {
+            expand(&args, &config::load_layered()?, builtins)
+        }
+        _ => Ok(Expansion::Args(args)),
+    }
+}
+
+/// Runs a '!' alias like 'sh -c "<command> \"$@\"" <command> <args>...', returns its exit code
+pub fn run_shell(command: &str, args: &[String]) -> anyhow::Result<i32> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg(command)
+        .args(args)
+        .status()
+        .map_err(|why| anyhow!("Could not run alias '{}': {}", command, why))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtins() -> Vec<String> {
+        ["status", "reset", "commit"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(|arg| arg.to_string()).collect()
+    }
+
+    fn expanded(config: &str, line: &str) -> anyhow::Result<Vec<String>> {
+        let config = Config::parse(config.to_string()).unwrap();
+        match expand(&args(line), &config, &builtins())? {
+            Expansion::Args(args) => Ok(args),
+            Expansion::Shell { command, .. } => Ok(vec![format!("!{}", command)]),
+        }
+    }
+
+    #[test]
+    fn expands_nested_aliases() {
+        let config = "[Alias]\nst = status\ns = st\nundo = reset 1\ncm = commit 'two words'\n";
+        assert_eq!(
+            expanded(config, "gilltter s").unwrap(),
+            args("gilltter status")
+        );
+        assert_eq!(
+            expanded(config, "gilltter undo").unwrap(),
+            args("gilltter reset 1")
+        );
+        assert_eq!(
+            expanded(config, "gilltter cm -x").unwrap(),
+            vec!["gilltter", "commit", "two words", "-x"]
+        );
+        // Built-ins win over aliases
+        let config = "[Alias]\nstatus = reset 1\n";
+        assert_eq!(
+            expanded(config, "gilltter status").unwrap(),
+            args("gilltter status")
+        );
+    }
+
+    #[test]
+    fn detects_recursion() {
+        let config = "[Alias]\na = b\nb = c --flag\nc = a\n";
+        let err = expanded(config, "gilltter a").unwrap_err().to_string();
+        assert_eq!(err, "Recursive alias: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn shell_alias() {
+        let config = "[Alias]\nll = !ls -la\nl = ll\n";
+        assert_eq!(expanded(config, "gilltter l").unwrap(), vec!["!ls -la"]);
+    }
+}
@@ -0,0 +1,424 @@
+// Tar and zip writers for `gilltter archive`.
+// Every entry gets the same time, so archiving a commit twice gives the same bytes.
+// Tar is POSIX ustar: a 512 byte header per entry, the data padded to 512 bytes and two
+// zero blocks at the end. Paths which don't fit the header go into a pax 'x' header before
+// the entry, the commit hash goes into a pax global header like `git archive` does, so
+// `git get-tar-commit-id` can read it.
+// Zip entries are deflated (or stored if that is not smaller), then comes the central
+// directory, and the commit hash is the archive comment. No zip64, so the archive must
+// stay under 4 GiB.
+// Files are 0644, or 0755 if executable, directories 0755 and symbolic links 0777.
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use flate2::{Compression, Crc, write::DeflateEncoder};
+
+use crate::{objects::tree::FileType, utils};
+
+const BLOCK_SIZE: usize = 512;
+// Tar archives are padded to a whole record of 20 blocks
+const RECORD_SIZE: usize = 20 * BLOCK_SIZE;
+const NAME_LEN: usize = 100;
+const LINK_NAME_LEN: usize = 100;
+const PREFIX_LEN: usize = 155;
+const FILE_MODE: u32 = 0o644;
+const EXECUTABLE_MODE: u32 = 0o755;
+const DIRECTORY_MODE: u32 = 0o755;
+const SYMLINK_MODE: u32 = 0o777;
+const UNIX_DIRECTORY_FLAG: u32 = 0o040000;
+const UNIX_FILE_FLAG: u32 = 0o100000;
+const UNIX_SYMLINK_FLAG: u32 = 0o120000;
+
+const ZIP_LOCAL_HEADER: u32 = 0x04034b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP_END_OF_CENTRAL: u32 = 0x06054b50;
+// Made on unix by zip 2.0
+const ZIP_MADE_BY: u16 = (3 << 8) | 20;
+const ZIP_VERSION_NEEDED: u16 = 20;
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+const ZIP_UTF8_FLAG: u16 = 1 << 11;
+const MSDOS_DIRECTORY_FLAG: u32 = 0x10;
+
+pub trait ArchiveWriter {
+    /// Adds a directory, `path` ends with '/'
+    fn add_directory(&mut self, path: &str) -> anyhow::Result<()>;
+    /// Adds a file of the given mode, `data` of a symbolic link is the path it points to
+    fn add_file(&mut self, path: &str, mode: FileType, data: &[u8]) -> anyhow::Result<()>;
+}
+
+// Permissions of a file of the mode
+fn permissions(mode: FileType) -> u32 {
+    match mode {
+        FileType::ExecutableFile => EXECUTABLE_MODE,
+        FileType::SymbolicLink => SYMLINK_MODE,
+        FileType::Directory => DIRECTORY_MODE,
+        FileType::RegularFile => FILE_MODE,
+    }
+}
+
+pub struct TarWriter<W: Write> {
+    output: W,
+    mtime: u64,
+    written: usize,
+}
+
+// Numbers in tar headers are octal, zero padded, and end with NUL
+fn octal_field(field: &mut [u8], value: u64) -> anyhow::Result<()> {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    if digits.len() >= field.len() {
+        return Err(anyhow!("{} does not fit a tar header", value));
+    }
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+    Ok(())
+}
+
+// A pax record is "<length> <key>=<value>\n", where the length counts itself too
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let rest = format!(" {}={}\n", key, value);
+    let mut length = rest.len() + 1;
+    while (length.to_string().len() + rest.len()) != length {
+        length += 1;
+    }
+    format!("{}{}", length, rest).into_bytes()
+}
+
+// The longest start of the text which fits `len` bytes
+fn truncated(text: &str, len: usize) -> &str {
+    let mut end = len.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+// ustar splits long paths into a prefix and a name at a '/'
+fn split_ustar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME_LEN {
+        return Some(("", path));
+    }
+    let trimmed = path.trim_end_matches('/');
+    trimmed
+        .match_indices('/')
+        .map(|(at, _)| at)
+        .find(|at| *at <= PREFIX_LEN && path.len() - at - 1 <= NAME_LEN)
+        .map(|at| (&path[..at], &path[at + 1..]))
+}
+
+impl<W: Write> TarWriter<W> {
+    /// Starts the archive, `commit` is recorded in a pax global header
+    pub fn new(output: W, mtime: u64, commit: Option<&str>) -> anyhow::Result<Self> {
+        let mut writer = Self {
+            output,
+            mtime,
+            written: 0,
+        };
+        if let Some(commit) = commit {
+            let data = pax_record("comment", commit);
+            writer.write_entry("pax_global_header", b'g', FILE_MODE, &data)?;
+        }
+        Ok(writer)
+    }
+
+    fn write_block(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.output.write_all(data)?;
+        let padding = (BLOCK_SIZE - data.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        self.output.write_all(&vec![0; padding])?;
+        self.written += data.len() + padding;
+        Ok(())
+    }
+
+    fn write_header(
+        &mut self,
+        path: &str,
+        kind: u8,
+        mode: u32,
+        size: u64,
+        link: &str,
+    ) -> anyhow::Result<()> {
+        // A path or a link target which doesn't fit is kept in the pax header before,
+        // the header itself gets as much as fits
+        let mut records = Vec::new();
+        let (prefix, name) = match split_ustar_path(path) {
+            Some(split) => split,
+            None => {
+                records.extend(pax_record("path", path));
+                ("", truncated(path, NAME_LEN))
+            }
+        };
+        if link.len() > LINK_NAME_LEN {
+            records.extend(pax_record("linkpath", link));
+        }
+        let link = truncated(link, LINK_NAME_LEN);
+        if !records.is_empty() {
+            self.write_header("pax_header", b'x', FILE_MODE, records.len() as u64, "")?;
+            self.write_block(&records)?;
+        }
+
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal_field(&mut header[100..108], mode as u64)?;
+        octal_field(&mut header[108..116], 0)?; // uid
+        octal_field(&mut header[116..124], 0)?; // gid
+        octal_field(&mut header[124..136], size)?;
+        octal_field(&mut header[136..148], self.mtime)?;
+        header[156] = kind;
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[265..269].copy_from_slice(b"root");
+        header[297..301].copy_from_slice(b"root");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        // The checksum is counted with its own field as spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+        octal_field(&mut header[148..155], checksum as u64)?;
+        header[155] = b' ';
+        self.write_block(&header)
+    }
+
+    fn write_entry(&mut self, path: &str, kind: u8, mode: u32, data: &[u8]) -> anyhow::Result<()> {
+        self.write_header(path, kind, mode, data.len() as u64, "")?;
+        self.write_block(data)
+    }
+
+    /// Writes the end of the archive and gives the output back
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        self.write_block(&[0; 2 * BLOCK_SIZE])?;
+        let padding = (RECORD_SIZE - self.written % RECORD_SIZE) % RECORD_SIZE;
+        self.write_block(&vec![0; padding])?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> ArchiveWriter for TarWriter<W> {
+    fn add_directory(&mut self, path: &str) -> anyhow::Result<()> {
+        self.write_entry(path, b'5', DIRECTORY_MODE, &[])
+    }
+
+    fn add_file(&mut self, path: &str, mode: FileType, data: &[u8]) -> anyhow::Result<()> {
+        if mode == FileType::SymbolicLink {
+            let link = String::from_utf8_lossy(data);
+            return self.write_header(path, b'2', SYMLINK_MODE, 0, &link);
+        }
+        self.write_entry(path, b'0', permissions(mode), data)
+    }
+}
+
+pub struct ZipWriter<W: Write> {
+    output: W,
+    comment: Vec<u8>,
+    dos_time: u16,
+    dos_date: u16,
+    offset: u64,
+    central_directory: Vec<u8>,
+    entries: usize,
+}
+
+// MS-DOS time and date: 2 second precision, years from 1980 on
+fn dos_time(secs_since_epoch: u64) -> (u16, u16) {
+    let (year, month, day, hour, minute, second) = utils::civil_time(secs_since_epoch);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = (hour << 11) | (minute << 5) | (second / 2);
+    let date = (((year - 1980) as u64).min(127) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+fn to_u32(value: u64) -> anyhow::Result<u32> {
+    u32::try_from(value).map_err(|_| anyhow!("The archive is too big for zip"))
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Starts the archive, `commit` becomes its comment
+    pub fn new(output: W, mtime: u64, commit: Option<&str>) -> Self {
+        let (dos_time, dos_date) = dos_time(mtime);
+        Self {
+            output,
+            comment: commit.unwrap_or_default().as_bytes().to_vec(),
+            dos_time,
+            dos_date,
+            offset: 0,
+            central_directory: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    fn write_entry(&mut self, path: &str, data: &[u8], attributes: u32) -> anyhow::Result<()> {
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+        let (method, stored) = if deflated.len() < data.len() {
+            (ZIP_DEFLATED, deflated.as_slice())
+        } else {
+            (ZIP_STORED, data)
+        };
+        let flags = if path.is_ascii() { 0 } else { ZIP_UTF8_FLAG };
+        let name_len = u16::try_from(path.len()).map_err(|_| anyhow!("'{}' is too long", path))?;
+
+        // Fields which the local header and the central directory share
+        let mut common = Vec::new();
+        common.extend(ZIP_VERSION_NEEDED.to_le_bytes());
+        common.extend(flags.to_le_bytes());
+        common.extend(method.to_le_bytes());
+        common.extend(self.dos_time.to_le_bytes());
+        common.extend(self.dos_date.to_le_bytes());
+        common.extend(crc.sum().to_le_bytes());
+        common.extend(to_u32(stored.len() as u64)?.to_le_bytes());
+        common.extend(to_u32(data.len() as u64)?.to_le_bytes());
+        common.extend(name_len.to_le_bytes());
+        common.extend(0u16.to_le_bytes()); // extra field length
+
+        let mut local = Vec::new();
+        local.extend(ZIP_LOCAL_HEADER.to_le_bytes());
+        local.extend(&common);
+        local.extend(path.as_bytes());
+        self.output.write_all(&local)?;
+        self.output.write_all(stored)?;
+
+        let central = &mut self.central_directory;
+        central.extend(ZIP_CENTRAL_HEADER.to_le_bytes());
+        central.extend(ZIP_MADE_BY.to_le_bytes());
+        central.extend(&common);
+        central.extend(0u16.to_le_bytes()); // comment length
+        central.extend(0u16.to_le_bytes()); // disk number
+        central.extend(0u16.to_le_bytes()); // internal attributes
+        central.extend(attributes.to_le_bytes());
+        central.extend(to_u32(self.offset)?.to_le_bytes());
+        central.extend(path.as_bytes());
+
+        self.offset += (local.len() + stored.len()) as u64;
+        self.entries += 1;
+        Ok(())
+    }
+
+    /// Writes the central directory and gives the output back
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        let entries = u16::try_from(self.entries).map_err(|_| anyhow!("Too many files for zip"))?;
+        let mut end = Vec::new();
+        end.extend(ZIP_END_OF_CENTRAL.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // this disk
+        end.extend(0u16.to_le_bytes()); // disk of the central directory
+        end.extend(entries.to_le_bytes());
+        end.extend(entries.to_le_bytes());
+        end.extend(to_u32(self.central_directory.len() as u64)?.to_le_bytes());
+        end.extend(to_u32(self.offset)?.to_le_bytes());
+        end.extend((self.comment.len() as u16).to_le_bytes());
+        end.extend(&self.comment);
+
+        self.output.write_all(&self.central_directory)?;
+        self.output.write_all(&end)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> ArchiveWriter for ZipWriter<W> {
+    fn add_directory(&mut self, path: &str) -> anyhow::Result<()> {
+        let attributes = ((UNIX_DIRECTORY_FLAG | DIRECTORY_MODE) << 16) | MSDOS_DIRECTORY_FLAG;
+        self.write_entry(path, &[], attributes)
+    }
+
+    fn add_file(&mut self, path: &str, mode: FileType, data: &[u8]) -> anyhow::Result<()> {
+        let flag = match mode {
+            FileType::SymbolicLink => UNIX_SYMLINK_FLAG,
+            _ => UNIX_FILE_FLAG,
+        };
+        self.write_entry(path, data, (flag | permissions(mode)) << 16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tar_headers_hold_checksums_and_long_paths() {
+        let long_path = format!("{}/file.txt", "d".repeat(120));
+        let mut tar = TarWriter::new(Vec::new(), 1_000_000, None).unwrap();
+        tar.add_file("a.txt", FileType::RegularFile, b"hello")
+            .unwrap();
+        tar.add_file(&long_path, FileType::RegularFile, b"x")
+            .unwrap();
+        let data = tar.finish().unwrap();
+        assert_eq!(data.len() % RECORD_SIZE, 0);
+
+        let header = &data[..BLOCK_SIZE];
+        assert_eq!(&header[..6], b"a.txt\0");
+        assert_eq!(&header[124..136], b"00000000005\0");
+        assert_eq!(&header[136..148], b"00003641100\0");
+        let mut unsummed = header.to_vec();
+        unsummed[148..156].copy_from_slice(b"        ");
+        let checksum: u32 = unsummed.iter().map(|byte| *byte as u32).sum();
+        assert_eq!(&header[148..156], format!("{:06o}\0 ", checksum).as_bytes());
+
+        // Split into the ustar prefix and name
+        let header = &data[2 * BLOCK_SIZE..3 * BLOCK_SIZE];
+        assert_eq!(&header[..9], b"file.txt\0");
+        assert_eq!(&header[345..465], "d".repeat(120).as_bytes());
+    }
+
+    #[test]
+    fn pax_record_length_counts_itself() {
+        assert_eq!(pax_record("path", "a"), b"9 path=a\n");
+        // 98 bytes after the length, which takes 3 digits then
+        let record = pax_record("path", &"a".repeat(91));
+        assert_eq!(record.len(), 101);
+        assert!(record.starts_with(b"101 path="));
+    }
+
+    #[test]
+    fn zip_lists_every_entry() {
+        let mut zip = ZipWriter::new(Vec::new(), 1_700_000_000, Some("abc"));
+        zip.add_directory("dir/").unwrap();
+        zip.add_file("dir/a.txt", FileType::RegularFile, &b"aaaa".repeat(100))
+            .unwrap();
+        let data = zip.finish().unwrap();
+
+        let end = &data[data.len() - 25..];
+        assert_eq!(&end[..4], &ZIP_END_OF_CENTRAL.to_le_bytes());
+        assert_eq!(&end[8..10], &2u16.to_le_bytes());
+        assert_eq!(&end[22..], b"abc");
+        // 2023-11-14 22:13:20
+        assert_eq!(
+            dos_time(1_700_000_000),
+            ((22 << 11) | (13 << 5) | 10, (43 << 9) | (11 << 5) | 14)
+        );
+    }
+
+    #[test]
+    fn modes_and_symbolic_links_are_kept() {
+        let mut tar = TarWriter::new(Vec::new(), 0, None).unwrap();
+        tar.add_file("run.sh", FileType::ExecutableFile, b"#!/bin/sh\n")
+            .unwrap();
+        tar.add_file("link", FileType::SymbolicLink, b"run.sh")
+            .unwrap();
+        let data = tar.finish().unwrap();
+        assert_eq!(&data[100..108], b"0000755\0");
+        let header = &data[2 * BLOCK_SIZE..3 * BLOCK_SIZE];
+        assert_eq!(&header[..5], b"link\0");
+        assert_eq!(&header[100..108], b"0000777\0");
+        assert_eq!(&header[124..136], b"00000000000\0");
+        assert_eq!(header[156], b'2');
+        assert_eq!(&header[157..164], b"run.sh\0");
+
+        let mut zip = ZipWriter::new(Vec::new(), 0, None);
+        zip.add_file("link", FileType::SymbolicLink, b"run.sh")
+            .unwrap();
+        let data = zip.finish().unwrap();
+        let central = data.len() - 22 - (46 + 4);
+        assert_eq!(
+            &data[central..central + 4],
+            &ZIP_CENTRAL_HEADER.to_le_bytes()
+        );
+        let attributes = &data[central + 38..central + 42];
+        assert_eq!(attributes, &(0o120777u32 << 16).to_le_bytes());
+    }
+}
@@ -1,6 +1,6 @@
-use std::fs::{self, File};
-use std::io::{ErrorKind, Read};
-use std::path::Path;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
 use crate::objects::ObjectDump;
 use crate::objects::blob::Blob;
@@ -15,6 +15,22 @@ pub const GILLTER_BRANCHES_DIR: &'static str = "branches";
 pub const GILLTER_CONFIG_FILE: &'static str = "config";
 pub const GILLTTER_INDEX_FILE: &'static str = "index";
 pub const GILLTTER_IGNORE_FILE: &'static str = ".gignore";
+pub const GILLTTER_INFO_DIR: &'static str = "info";
+pub const GILLTTER_EXCLUDE_FILE: &'static str = "exclude"; // .gilltter/info/exclude, per-repo ignore rules
+pub const GILLTTER_CHERRY_PICK_DIR: &'static str = "cherry-pick"; // state of a stopped cherry-pick
+pub const GILLTTER_REBASE_DIR: &'static str = "rebase"; // state of a rebase in progress
+pub const GILLTTER_TAGS_DIR: &'static str = "tags"; // tags/<name> holds a tag object or commit hash
+pub const GILLTTER_STASH_FILE: &'static str = "stash"; // stash commits, newest first
+pub const GILLTTER_REMOTES_DIR: &'static str = "remotes"; // remotes/<remote>/<branch>, branches of remotes as last fetched
+pub const GILLTTER_BISECT_DIR: &'static str = "bisect"; // state of a bisect in progress
+pub const GILLTTER_SHALLOW_FILE: &'static str = "shallow"; // commits stored without their parents, one per line
+pub const GILLTTER_GIT_MAP_FILE: &'static str = "git-map"; // "<git commit> <gilltter commit>" lines of import-git
+pub const GILLTTER_HOOKS_DIR: &'static str = "hooks"; // executables run at points of commands, see hooks.rs
+pub const GILLTTER_COMMIT_MESSAGE_FILE: &'static str = "commit-message"; // message given to the commit-msg hook
+
+pub fn object_path(sha: &str) -> PathBuf {
+    Path::new(GILLTTER_PATH).join(GILLTER_OBJECTS_DIR).join(sha)
+}
 
 pub fn create_gilltter_project() -> anyhow::Result<()> {
     if !fs::exists(GILLTTER_PATH)?
@@ -56,9 +72,7 @@ pub(crate) fn gilltter_init() -> anyhow::Result<()> {
 }
 
 pub(crate) fn gilltter_add(filepath: &Path) -> anyhow::Result<String> {
-    let mut file = File::open(filepath)?;
-    let mut contents = Vec::new();
-    file.read_to_end(&mut contents)?;
+    let contents = utils::read_file_data(filepath)?;
 
     let mut blob = Blob::new();
     blob.set_data(&contents);
@@ -0,0 +1,183 @@
+// Every line of a file is blamed on the commit which introduced it. Starting from the
+// given commit, the lines are followed through first parents: lines the parent's version
+// of the file has too are passed on to the parent, the rest is blamed on the commit.
+// Commits can be ignored (e.g. reformatting ones): changed lines of those are passed on
+// to the parent's line at the same place of the changed hunk, if there is one.
+
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    diff,
+    objects::{ObjectPump, blob::Blob, commit::read_commit, tree},
+    refs,
+};
+
+pub struct BlameLine {
+    pub number: usize, // 1-based, in the blamed version of the file
+    pub commit: String,
+    pub content: Vec<u8>,
+}
+
+// Hash of the file in the commit, None if it is not there
+fn file_in_commit(commit_sha: &str, path: &Path) -> anyhow::Result<Option<String>> {
+    let tree_sha = read_commit(commit_sha)?
+        .get_tree_sha()
+        .ok_or(anyhow!("Commit {} has no tree", commit_sha))?;
+    tree::find_path(&tree_sha, path)
+}
+
+fn read_blob(sha: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(Blob::from_file(&object_path(sha))?.get_data())
+}
+
+/// Splits `pending` lines of the child (child line, final line) into those found in
+/// the parent, as (parent line, final line), and the final lines the child introduced
+pub fn pass_to_parent(
+    parent: &[&[u8]],
+    child: &[&[u8]],
+    pending: &[(usize, usize)],
+    ignored: bool,
+) -> (Vec<(usize, usize)>, Vec<usize>) {
+    let mut child_to_parent = vec![None; child.len()];
+    for (parent_line, child_line) in diff::match_lines(parent, child).into_iter().enumerate() {
+        if let Some(child_line) = child_line {
+            child_to_parent[child_line] = Some(parent_line);
+        }
+    }
+
+    let mut passed = Vec::new();
+    let mut introduced = Vec::new();
+    for &(child_line, final_line) in pending {
+        let parent_line = child_to_parent[child_line].or_else(|| {
+            if !ignored {
+                return None;
+            }
+            // The hunk starts after the last matched line, the guess is the parent line
+            // as far into the hunk as the child line is, if the parent changed it too
+            let (hunk_child_start, hunk_parent_start) = child_to_parent[..child_line]
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(child, parent)| parent.map(|parent| (child + 1, parent + 1)))
+                .unwrap_or((0, 0));
+            let hunk_parent_end = child_to_parent[child_line..]
+                .iter()
+                .find_map(|parent| *parent)
+                .unwrap_or(parent.len());
+            let guess = hunk_parent_start + (child_line - hunk_child_start);
+            (guess < hunk_parent_end).then_some(guess)
+        });
+        match parent_line {
+            Some(parent_line) => passed.push((parent_line, final_line)),
+            None => introduced.push(final_line),
+        }
+    }
+    (passed, introduced)
+}
+
+/// Blames the lines `first..=last` (1-based, up to the end if `last` is None)
+/// of the file as it is in the commit
+pub fn blame(
+    commit_sha: &str,
+    path: &Path,
+    first: usize,
+    last: Option<usize>,
+    ignored: &BTreeSet<String>,
+) -> anyhow::Result<Vec<BlameLine>> {
+    let blob_sha = file_in_commit(commit_sha, path)?.ok_or(anyhow!(
+        "There is no '{}' in {}",
+        path.to_string_lossy(),
+        refs::short_hash(commit_sha)
+    ))?;
+    let data = read_blob(&blob_sha)?;
+    let lines = diff::split_lines(&data);
+    if let Some(last) = last
+        && last < first
+    {
+        return Err(anyhow!("Line range {},{} is empty", first, last));
+    }
+    if first == 0 || first > lines.len() {
+        return Err(anyhow!(
+            "Line {} is not in the file, it has {} lines",
+            first,
+            lines.len()
+        ));
+    }
+    let last = last.unwrap_or(lines.len()).min(lines.len());
+
+    let mut blamed: Vec<Option<String>> = vec![None; lines.len()];
+    let mut pending: Vec<(usize, usize)> = (first - 1..last).map(|line| (line, line)).collect();
+    let chain = refs::first_parent_chain(commit_sha)?;
+    let (mut child_sha, mut child_data) = (blob_sha, data.clone());
+    for (pos, commit) in chain.iter().enumerate() {
+        let parent_blob = match chain.get(pos + 1) {
+            Some(parent) => file_in_commit(parent, path)?,
+            None => None,
+        };
+        let Some(parent_sha) = parent_blob else {
+            for &(_, final_line) in &pending {
+                blamed[final_line] = Some(commit.clone());
+            }
+            break;
+        };
+        if parent_sha == child_sha {
+            continue;
+        }
+
+        let parent_data = read_blob(&parent_sha)?;
+        let (passed, introduced) = pass_to_parent(
+            &diff::split_lines(&parent_data),
+            &diff::split_lines(&child_data),
+            &pending,
+            ignored.contains(commit),
+        );
+        for final_line in introduced {
+            blamed[final_line] = Some(commit.clone());
+        }
+        pending = passed;
+        if pending.is_empty() {
+            break;
+        }
+        (child_sha, child_data) = (parent_sha, parent_data);
+    }
+
+    Ok((first - 1..last)
+        .map(|line| BlameLine {
+            number: line + 1,
+            commit: blamed[line].clone().unwrap_or_default(),
+            content: lines[line].to_vec(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        diff::split_lines(text.as_bytes())
+    }
+
+    #[test]
+    fn added_lines_are_introduced() {
+        let parent = lines("a\nb\nc\n");
+        let child = lines("a\nx\nb\nc\n");
+        let pending: Vec<(usize, usize)> = (0..4).map(|line| (line, line)).collect();
+        let (passed, introduced) = pass_to_parent(&parent, &child, &pending, false);
+        assert_eq!(passed, vec![(0, 0), (1, 2), (2, 3)]);
+        assert_eq!(introduced, vec![1]);
+    }
+
+    #[test]
+    fn ignored_commit_passes_changed_lines_on() {
+        let parent = lines("a\nfoo( 1 )\nbar( 2 )\nc\n");
+        let child = lines("a\nfoo(1)\nbar(2)\nbaz(3)\nc\n");
+        let pending: Vec<(usize, usize)> = (0..5).map(|line| (line, line)).collect();
+        let (passed, introduced) = pass_to_parent(&parent, &child, &pending, true);
+        assert_eq!(passed, vec![(0, 0), (1, 1), (2, 2), (3, 4)]);
+        assert_eq!(introduced, vec![3]);
+    }
+}
@@ -4,14 +4,13 @@ use anyhow::anyhow;
 
 use crate::{
     base::{self, GILLTTER_IGNORE_FILE, GILLTTER_INDEX_FILE, GILLTTER_PATH},
-    ignore::{self, should_ignore},
+    ignore::IgnoreMatcher,
     index::index::{Index, IndexEntry, IndexType},
     objects::{ObjectDump, ObjectPump},
 };
 
 pub fn add(filepath: &Path) -> anyhow::Result<()> {
-    let ignore_files = ignore::gilltter_get_ignorefile()?;
-    if should_ignore(filepath.as_os_str(), &ignore_files)? {
+    if IgnoreMatcher::new()?.is_ignored(filepath, filepath.is_dir())? {
         return Err(anyhow!("This file is ignored by {}", GILLTTER_IGNORE_FILE));
     }
 
@@ -36,16 +35,12 @@ pub fn add(filepath: &Path) -> anyhow::Result<()> {
 
     let file_sha1 = base::gilltter_add(filepath)?;
 
-    let add_file_metadata = std::fs::metadata(filepath)?;
+    let add_file_metadata = std::fs::symlink_metadata(filepath)?;
     let entry: IndexEntry = IndexEntry::new(
         add_file_metadata.ctime(),
         add_file_metadata.mtime(),
         add_file_metadata.size(),
-        if add_file_metadata.is_symlink() {
-            IndexType::SymbolicLink
-        } else {
-            IndexType::RegularFile
-        },
+        IndexType::of_metadata(&add_file_metadata),
         filepath.to_owned(),
         file_sha1,
     );
@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use flate2::{Compression, write::GzEncoder};
+
+use crate::{
+    archive::{ArchiveWriter, TarWriter, ZipWriter},
+    base::object_path,
+    objects::{
+        ObjectPump,
+        blob::Blob,
+        commit,
+        tree::{self, FileType},
+    },
+    refs,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ArchiveFormat {
+    Tar,
+    #[value(name = "tar.gz", alias = "tgz")]
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    // Guessed from the name of the output file, tar if the name tells nothing
+    fn of_output(output: Option<&Path>) -> Self {
+        let name = output
+            .and_then(|output| output.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.ends_with(".zip") {
+            Self::Zip
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Self::TarGz
+        } else {
+            Self::Tar
+        }
+    }
+}
+
+// Adds the files under the prefix, each directory is added before what is in it
+fn add_files(
+    writer: &mut impl ArchiveWriter,
+    files: &BTreeMap<PathBuf, (FileType, String)>,
+    prefix: &str,
+) -> anyhow::Result<()> {
+    let mut directories = BTreeSet::new();
+    for (path, (mode, sha)) in files {
+        let path = format!("{}{}", prefix, path.to_string_lossy());
+        for (at, _) in path.match_indices('/') {
+            let directory = &path[..at + 1];
+            if directories.insert(directory.to_string()) {
+                writer.add_directory(directory)?;
+            }
+        }
+        writer.add_file(
+            &path,
+            *mode,
+            &Blob::from_file(&object_path(sha))?.get_data(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the files of a commit (only those under `paths` if there are any) as an archive
+/// to the output file or stdout. Entries are dated with the commit, so the same commit
+/// always gives the same archive
+pub fn archive(
+    revision: &str,
+    format: Option<ArchiveFormat>,
+    prefix: &str,
+    output: Option<&Path>,
+    paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    let sha = refs::peel(&refs::resolve_revision(revision)?)?;
+    let commit = commit::read_commit(&sha)?;
+    let tree_sha = commit
+        .get_tree_sha()
+        .ok_or(anyhow!("Commit {} has no tree", sha))?;
+    let mut files = tree::read_tree_entries(&tree_sha)?;
+    if !paths.is_empty() {
+        for path in paths {
+            if !files.keys().any(|file| file.starts_with(path)) {
+                return Err(anyhow!(
+                    "'{}' did not match any files",
+                    path.to_string_lossy()
+                ));
+            }
+        }
+        files.retain(|file, _| paths.iter().any(|path| file.starts_with(path)));
+    }
+
+    let format = format.unwrap_or(ArchiveFormat::of_output(output));
+    let mtime = commit.get_secs_since_epoch().unwrap_or_default();
+    let output: Box<dyn Write> = match output {
+        Some(output) => Box::new(File::create(output)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let output = BufWriter::new(output);
+    match format {
+        ArchiveFormat::Tar => {
+            let mut tar = TarWriter::new(output, mtime, Some(&sha))?;
+            add_files(&mut tar, &files, prefix)?;
+            tar.finish()?;
+        }
+        ArchiveFormat::TarGz => {
+            let gzip = GzEncoder::new(output, Compression::default());
+            let mut tar = TarWriter::new(gzip, mtime, Some(&sha))?;
+            add_files(&mut tar, &files, prefix)?;
+            tar.finish()?.finish()?.flush()?;
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = ZipWriter::new(output, mtime, Some(&sha));
+            add_files(&mut zip, &files, prefix)?;
+            zip.finish()?;
+        }
+    }
+    Ok(())
+}
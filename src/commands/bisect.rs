@@ -0,0 +1,365 @@
+// A bisect in progress keeps its state in .gilltter/bisect/:
+/*
+ * start  what 'head' held before the bisect, for reset
+ * bad    the bad commit, empty if not known yet
+ * good   good commits, one per line
+ * skip   commits which can't be tested, one per line
+ */
+// HEAD is detached at the commit being tested. History has only first parents,
+// so the commits left are the ones between the bad commit and the closest good one.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTTER_BISECT_DIR, GILLTTER_PATH},
+    commands::reset::{self, ResetMode},
+    hooks,
+    objects::commit::read_commit,
+    refs,
+};
+
+const STATE_START_FILE: &'static str = "start";
+const STATE_BAD_FILE: &'static str = "bad";
+const STATE_GOOD_FILE: &'static str = "good";
+const STATE_SKIP_FILE: &'static str = "skip";
+
+// bisect run: the script exits with this when the commit can't be tested
+const SKIP_EXIT_CODE: i32 = 125;
+
+fn state_dir() -> PathBuf {
+    Path::new(GILLTTER_PATH).join(GILLTTER_BISECT_DIR)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mark {
+    Good,
+    Bad,
+    Skip,
+}
+
+struct State {
+    start: String,
+    bad: Option<String>,
+    good: Vec<String>,
+    skip: Vec<String>,
+}
+
+impl State {
+    fn load() -> anyhow::Result<Self> {
+        let dir = state_dir();
+        if !dir.is_dir() {
+            return Err(anyhow!("There is no bisect in progress, use bisect start"));
+        }
+        let read = |name: &str| -> anyhow::Result<String> {
+            fs::read_to_string(dir.join(name))
+                .map_err(|why| anyhow!("Broken bisect state, could not read '{}': {}", name, why))
+        };
+        let read_list = |name: &str| -> anyhow::Result<Vec<String>> {
+            Ok(read(name)?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string())
+                .collect())
+        };
+        let bad = read(STATE_BAD_FILE)?.trim().to_string();
+
+        Ok(Self {
+            start: read(STATE_START_FILE)?.trim().to_string(),
+            bad: (!bad.is_empty()).then_some(bad),
+            good: read_list(STATE_GOOD_FILE)?,
+            skip: read_list(STATE_SKIP_FILE)?,
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let dir = state_dir();
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(STATE_START_FILE), &self.start)?;
+        fs::write(
+            dir.join(STATE_BAD_FILE),
+            self.bad.as_deref().unwrap_or_default(),
+        )?;
+        fs::write(dir.join(STATE_GOOD_FILE), self.good.join("\n"))?;
+        fs::write(dir.join(STATE_SKIP_FILE), self.skip.join("\n"))?;
+        Ok(())
+    }
+
+    fn mark(&mut self, mark: Mark, sha: String) {
+        match mark {
+            Mark::Bad => self.bad = Some(sha),
+            Mark::Good if !self.good.contains(&sha) => self.good.push(sha),
+            Mark::Skip if !self.skip.contains(&sha) => self.skip.push(sha),
+            _ => {}
+        }
+    }
+}
+
+enum Outcome {
+    Waiting,
+    Testing,
+    Done,
+}
+
+fn subject(sha: &str) -> anyhow::Result<String> {
+    let message = read_commit(sha)?.get_message().unwrap_or_default();
+    Ok(message.lines().next().unwrap_or_default().to_string())
+}
+
+// Detaches HEAD and checks the commit out
+fn checkout(sha: &str) -> anyhow::Result<()> {
+    let old = refs::head_commit()?;
+    if let Some(head) = &old {
+        refs::write_head(head)?;
+    }
+    reset::reset(Some(sha), ResetMode::Hard, &[])?;
+    hooks::post_checkout(old.as_deref(), sha);
+    Ok(())
+}
+
+// What is left between the good commits and the bad one
+#[derive(Debug, PartialEq)]
+enum Selection<'a> {
+    FirstBad,                           // the bad commit is right after a good one
+    OnlySkipped(&'a [String]),          // every commit which could be the first bad one is skipped
+    Test { sha: &'a str, left: usize }, // test this one, `left` remain after it
+}
+
+// Picks the commit to test from the first parent chain of the bad commit (newest first)
+fn select<'a>(
+    chain: &'a [String],
+    good: &[String],
+    skip: &[String],
+) -> anyhow::Result<Selection<'a>> {
+    let good_pos = chain
+        .iter()
+        .position(|sha| good.contains(sha))
+        .ok_or(anyhow!(
+            "None of the good commits is an ancestor of the bad one {}",
+            chain.first().map_or("", |bad| refs::short_hash(bad))
+        ))?;
+    if good_pos == 0 {
+        return Err(anyhow!(
+            "{} is marked both good and bad",
+            refs::short_hash(&chain[0])
+        ));
+    }
+    // Newest first, without the bad commit itself
+    let candidates = &chain[1..good_pos];
+    let testable: Vec<usize> = (0..candidates.len())
+        .filter(|pos| !skip.contains(&candidates[*pos]))
+        .collect();
+
+    if candidates.is_empty() {
+        return Ok(Selection::FirstBad);
+    }
+    if testable.is_empty() {
+        return Ok(Selection::OnlySkipped(candidates));
+    }
+    // Skipped commits are stepped over by taking the closest testable one to the middle
+    let middle = candidates.len() / 2;
+    let pos = *testable
+        .iter()
+        .min_by_key(|pos| pos.abs_diff(middle))
+        .unwrap();
+    Ok(Selection::Test {
+        sha: &candidates[pos],
+        left: testable.len() / 2,
+    })
+}
+
+// Checks out the middle of the commits left, or tells which commit is the first bad one
+fn next_step(state: &State) -> anyhow::Result<Outcome> {
+    let Some(bad) = &state.bad else {
+        println!("Waiting for a bad commit, use bisect bad");
+        return Ok(Outcome::Waiting);
+    };
+    if state.good.is_empty() {
+        println!("Waiting for good commits, use bisect good");
+        return Ok(Outcome::Waiting);
+    }
+
+    let chain = refs::first_parent_chain(bad)?;
+    match select(&chain, &state.good, &state.skip)? {
+        Selection::FirstBad => {
+            println!("{} is the first bad commit", bad);
+            println!("    {}", subject(bad)?);
+            Ok(Outcome::Done)
+        }
+        Selection::OnlySkipped(candidates) => {
+            println!("There are only skipped commits left to test.");
+            println!("The first bad commit could be any of:");
+            for sha in candidates.iter().chain(std::iter::once(bad)) {
+                println!("{} {}", refs::short_hash(sha), subject(sha)?);
+            }
+            Ok(Outcome::Done)
+        }
+        Selection::Test { sha, left } => {
+            checkout(sha)?;
+            let steps = (left + 1).ilog2();
+            println!(
+                "Bisecting: {} revision{} left to test after this (roughly {} step{})",
+                left,
+                if left == 1 { "" } else { "s" },
+                steps,
+                if steps == 1 { "" } else { "s" }
+            );
+            println!("[{}] {}", sha, subject(sha)?);
+            Ok(Outcome::Testing)
+        }
+    }
+}
+
+/// Starts bisecting, optionally with the bad revision and good ones known already
+pub fn start(bad: Option<&str>, good: &[String]) -> anyhow::Result<()> {
+    if state_dir().exists() {
+        return Err(anyhow!(
+            "A bisect is in progress already, use bisect reset first"
+        ));
+    }
+    reset::ensure_clean()?;
+
+    let mut state = State {
+        start: refs::read_head()?,
+        bad: None,
+        good: Vec::new(),
+        skip: Vec::new(),
+    };
+    if let Some(bad) = bad {
+        state.mark(Mark::Bad, refs::resolve_revision(bad)?);
+    }
+    for revision in good {
+        state.mark(Mark::Good, refs::resolve_revision(revision)?);
+    }
+    state.save()?;
+    next_step(&state)?;
+    Ok(())
+}
+
+/// Marks the revisions (HEAD if there are none) and checks out the next commit to test
+pub fn mark(mark: Mark, revisions: &[String]) -> anyhow::Result<()> {
+    let mut state = State::load()?;
+    if revisions.is_empty() {
+        let head = refs::head_commit()?.ok_or(anyhow!("HEAD does not point to a commit"))?;
+        state.mark(mark, head);
+    }
+    for revision in revisions {
+        state.mark(mark, refs::resolve_revision(revision)?);
+    }
+    state.save()?;
+    next_step(&state)?;
+    Ok(())
+}
+
+/// Ends the bisect, HEAD goes back to where it was before it
+pub fn reset() -> anyhow::Result<()> {
+    let state = State::load()?;
+    let old = refs::head_commit()?;
+    // The index still matches the tested commit, so the hard reset replaces its files
+    refs::write_head(&state.start)?;
+    reset::reset(None, ResetMode::Hard, &[])?;
+    fs::remove_dir_all(state_dir())?;
+    if let Some(new) = refs::head_commit()? {
+        hooks::post_checkout(old.as_deref(), &new);
+    }
+    Ok(())
+}
+
+/// Runs the command on every commit to test until the first bad commit is found:
+/// exit code 0 means good, 125 skip, anything else up to 127 bad
+pub fn run(command: &[String]) -> anyhow::Result<()> {
+    let (program, args) = command.split_first().ok_or(anyhow!("Nothing to run"))?;
+    loop {
+        let mut state = State::load()?;
+        if state.bad.is_none() || state.good.is_empty() {
+            return Err(anyhow!(
+                "bisect run needs a bad and a good commit, mark them first"
+            ));
+        }
+        let head = refs::head_commit()?.ok_or(anyhow!("HEAD does not point to a commit"))?;
+
+        println!("running {}", command.join(" "));
+        let status = Command::new(program)
+            .args(args)
+            .status()
+            .map_err(|why| anyhow!("Could not run '{}': {}", program, why))?;
+        let mark = match status.code() {
+            Some(0) => Mark::Good,
+            Some(SKIP_EXIT_CODE) => Mark::Skip,
+            Some(code) if (1..128).contains(&code) => Mark::Bad,
+            _ => {
+                return Err(anyhow!(
+                    "'{}' failed with {}, stopping bisect run",
+                    program,
+                    status
+                ));
+            }
+        };
+        state.mark(mark, head);
+        state.save()?;
+        if let Outcome::Done | Outcome::Waiting = next_step(&state)? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Commits 9 (the bad one) down to 0, newest first
+    fn chain() -> Vec<String> {
+        (0..10).rev().map(|n| n.to_string()).collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn the_middle_is_tested() {
+        let chain = chain();
+        let selection = select(&chain, &names(&["0"]), &[]).unwrap();
+        assert_eq!(selection, Selection::Test { sha: "4", left: 4 });
+        // The closest one to the middle which is not skipped
+        let selection = select(&chain, &names(&["0"]), &names(&["4"])).unwrap();
+        assert_eq!(selection, Selection::Test { sha: "5", left: 3 });
+        // The newest good commit bounds the range
+        let selection = select(&chain, &names(&["0", "6"]), &[]).unwrap();
+        assert_eq!(selection, Selection::Test { sha: "7", left: 1 });
+    }
+
+    #[test]
+    fn good_commit_right_before_the_bad_one() {
+        let chain = chain();
+        assert_eq!(
+            select(&chain, &names(&["8"]), &[]).unwrap(),
+            Selection::FirstBad
+        );
+    }
+
+    #[test]
+    fn bad_commit_marked_good() {
+        let chain = chain();
+        assert!(select(&chain, &names(&["9"]), &[]).is_err());
+        assert!(select(&chain, &names(&["0", "9"]), &[]).is_err());
+    }
+
+    #[test]
+    fn only_skipped_commits_left() {
+        let chain = chain();
+        let selection = select(&chain, &names(&["6"]), &names(&["7", "8"])).unwrap();
+        assert_eq!(selection, Selection::OnlySkipped(&chain[1..3]));
+    }
+
+    #[test]
+    fn good_commit_must_be_an_ancestor() {
+        let chain = chain();
+        assert!(select(&chain, &names(&["elsewhere"]), &[]).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use anyhow::anyhow;
+
+use crate::{blame, objects::commit::read_commit, refs, utils};
+
+// "<start>,<end>", "<start>,+<count>", "<start>," or ",<end>", 1-based and inclusive
+fn parse_range(range: &str) -> anyhow::Result<(usize, Option<usize>)> {
+    let bad_range = || anyhow!("'{}' is not a line range like 10,20 or 10,+5", range);
+    let (start, end) = range.split_once(',').ok_or_else(bad_range)?;
+    let start = match start {
+        "" => 1,
+        start => start.parse().map_err(|_| bad_range())?,
+    };
+    let end = match end {
+        "" => None,
+        end => Some(match end.strip_prefix('+') {
+            Some(count) => start + count.parse::<usize>().map_err(|_| bad_range())?.max(1) - 1,
+            None => end.parse().map_err(|_| bad_range())?,
+        }),
+    };
+    Ok((start, end))
+}
+
+// One revision per line, empty lines and lines starting with '#' are skipped
+fn read_ignored_revisions(path: &Path) -> anyhow::Result<BTreeSet<String>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|why| anyhow!("Could not read '{}': {}", path.to_string_lossy(), why))?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(refs::resolve_revision)
+        .collect()
+}
+
+/// Prints every line of the file (or of the range) with the commit, author and time
+/// which introduced it, like "<hash> (<author> <date> <line>) <content>"
+pub fn blame(
+    path: &Path,
+    revision: Option<&str>,
+    range: Option<&str>,
+    ignore_revs_file: Option<&Path>,
+) -> anyhow::Result<()> {
+    let commit = refs::resolve_revision(revision.unwrap_or("HEAD"))?;
+    let (first, last) = range.map(parse_range).transpose()?.unwrap_or((1, None));
+    let ignored = ignore_revs_file
+        .map(read_ignored_revisions)
+        .transpose()?
+        .unwrap_or_default();
+    let lines = blame::blame(&commit, path, first, last, &ignored)?;
+
+    let mut authors = BTreeMap::new();
+    for line in &lines {
+        if !authors.contains_key(&line.commit) {
+            let commit = read_commit(&line.commit)?;
+            let author = commit.get_username().unwrap_or_default();
+            let date = utils::format_timestamp(commit.get_secs_since_epoch().unwrap_or_default());
+            authors.insert(line.commit.clone(), (author, date));
+        }
+    }
+    let author_width = authors
+        .values()
+        .map(|(author, _)| author.chars().count())
+        .max()
+        .unwrap_or_default();
+    let number_width = lines.last().map_or(1, |line| line.number.to_string().len());
+
+    for line in &lines {
+        let (author, date) = &authors[&line.commit];
+        let content = String::from_utf8_lossy(&line.content);
+        println!(
+            "{} ({:<author_width$} {} {:>number_width$}) {}",
+            refs::short_hash(&line.commit),
+            author,
+            date,
+            line.number,
+            content.strip_suffix('\n').unwrap_or(&content),
+        );
+    }
+    Ok(())
+}
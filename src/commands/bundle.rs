@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTER_BRANCHES_DIR, GILLTTER_PATH, GILLTTER_TAGS_DIR, object_path},
+    objects::commit,
+    refs,
+    remote::{
+        self, HEAD_REF,
+        bundle::{self, Bundle},
+    },
+};
+
+// Commit a revision names, tags are peeled
+fn resolve_commit(revision: &str) -> anyhow::Result<String> {
+    let sha = refs::peel(&refs::resolve_revision(revision)?)?;
+    commit::read_commit(&sha)?; // make sure it is a commit
+    Ok(sha)
+}
+
+// Ref the bundle carries for a name: "branches/<name>", "tags/<name>" or "HEAD"
+fn bundled_ref(name: &str) -> anyhow::Result<(String, String)> {
+    if name == HEAD_REF {
+        let sha = refs::head_commit()?.ok_or(anyhow!("HEAD has no commits yet"))?;
+        return Ok((HEAD_REF.to_string(), sha));
+    }
+    if let Some(sha) = refs::branch_commit(name)? {
+        return Ok((format!("{}/{}", GILLTER_BRANCHES_DIR, name), sha));
+    }
+    if let Some(sha) = refs::tag_object(name)? {
+        return Ok((format!("{}/{}", GILLTTER_TAGS_DIR, name), sha));
+    }
+    Err(anyhow!(
+        "'{}' is not a branch, a tag or HEAD, a bundle only carries those",
+        name
+    ))
+}
+
+/// Writes the refs and the objects they need into a bundle. Revisions are branches, tags
+/// or HEAD, `<from>..<to>` and `^<from>` leave out what `from` has, the receiver must have
+/// `from` then. With `all` every branch and tag goes in, and HEAD
+pub fn create<'a>(file: &Path, revisions: &'a [String], all: bool) -> anyhow::Result<()> {
+    let mut bundled = BTreeMap::new();
+    let mut prerequisites = Vec::new();
+    if all {
+        for (name, value) in remote::list_refs(Path::new(GILLTTER_PATH))? {
+            if !value.is_empty() {
+                bundled.insert(name, value);
+            }
+        }
+    }
+    // "..main" is "HEAD..main"
+    let or_head = |name: &'a str| if name.is_empty() { HEAD_REF } else { name };
+    for revision in revisions {
+        let name = match (revision.split_once(".."), revision.strip_prefix('^')) {
+            (Some((from, to)), _) => {
+                prerequisites.push(resolve_commit(or_head(from))?);
+                or_head(to)
+            }
+            (None, Some(excluded)) => {
+                prerequisites.push(resolve_commit(excluded)?);
+                continue;
+            }
+            (None, None) => revision.as_str(),
+        };
+        let (ref_name, sha) = bundled_ref(name)?;
+        bundled.insert(ref_name, sha);
+    }
+    if bundled.is_empty() {
+        return Err(anyhow!("Nothing to bundle, name a branch or a tag"));
+    }
+    prerequisites.sort();
+    prerequisites.dedup();
+
+    let wants: Vec<String> = bundled
+        .values()
+        .filter(|value| !value.starts_with(refs::HEAD_REF_PREFIX))
+        .cloned()
+        .collect();
+    let known = remote::known_objects(&prerequisites, remote::read_local, |sha| {
+        object_path(sha).exists()
+    })?;
+    let objects = remote::objects_to_push(&wants, |sha| known.contains(sha))?;
+    bundle::write_bundle(file, &bundled, &prerequisites, &objects)?;
+    println!(
+        "Bundled {} refs and {} objects into {}",
+        bundled.len(),
+        objects.len(),
+        file.to_string_lossy()
+    );
+    Ok(())
+}
+
+fn print_refs(bundle: &Bundle) {
+    for (name, value) in &bundle.refs {
+        println!("{} {}", value, name);
+    }
+}
+
+/// Prints what the bundle holds and fails if this repository lacks a commit it needs
+pub fn verify(file: &Path) -> anyhow::Result<()> {
+    let bundle = Bundle::open(file)?;
+    println!("The bundle holds {} refs:", bundle.refs.len());
+    print_refs(&bundle);
+    if bundle.prerequisites.is_empty() {
+        println!("The bundle records a complete history");
+    } else {
+        println!("The bundle needs {} commits:", bundle.prerequisites.len());
+        for sha in &bundle.prerequisites {
+            println!("{}", sha);
+        }
+    }
+    let missing = bundle.missing_prerequisites();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "This repository lacks {}",
+            missing
+                .iter()
+                .map(|sha| refs::short_hash(sha))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    println!("{} is okay", file.to_string_lossy());
+    Ok(())
+}
+
+/// Stores the objects of the bundle and prints its refs, refs here are left as they are
+pub fn unbundle(file: &Path) -> anyhow::Result<()> {
+    let bundle = Bundle::open(file)?;
+    let objects = bundle.unbundle()?;
+    println!("Stored {} objects", objects.len());
+    print_refs(&bundle);
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::io::Write;
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    objects::{ObjectPump, commit::Commit, read_object, tag::Tag},
+    refs,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum CatFileMode {
+    Type,   // -t
+    Size,   // -s
+    Pretty, // -p
+}
+
+// Tree lines are stored as "<mode> <path> <hash>", printed as "<mode> <type> <hash>\t<path>"
+fn print_tree(data: &[u8]) -> anyhow::Result<()> {
+    for line in String::from_utf8_lossy(data).lines() {
+        let (mode, rest) = line
+            .split_once(' ')
+            .ok_or(anyhow!("Broken tree line '{}'", line))?;
+        let (path, sha) = rest
+            .rsplit_once(' ')
+            .ok_or(anyhow!("Broken tree line '{}'", line))?;
+        let obj_type = if mode == "040000" { "tree" } else { "blob" };
+        println!("{} {} {}\t{}", mode, obj_type, sha, path);
+    }
+    Ok(())
+}
+
+fn print_commit(commit: &Commit) {
+    println!("tree {}", commit.get_tree_sha().unwrap_or_default());
+    if let Some(parent) = commit.get_parent_commit_sha() {
+        println!("parent {}", parent);
+    }
+    println!(
+        "author {} <{}> {}",
+        commit.get_username().unwrap_or_default(),
+        commit.get_email().unwrap_or_default(),
+        commit.get_secs_since_epoch().unwrap_or_default()
+    );
+    println!();
+    println!("{}", commit.get_message().unwrap_or_default());
+}
+
+fn print_tag(tag: &Tag) {
+    println!("object {}", tag.get_target_sha().unwrap_or_default());
+    println!("type {}", tag.get_target_type().unwrap_or_default());
+    println!("tag {}", tag.get_name().unwrap_or_default());
+    println!(
+        "tagger {} <{}> {}",
+        tag.get_username().unwrap_or_default(),
+        tag.get_email().unwrap_or_default(),
+        tag.get_secs_since_epoch().unwrap_or_default()
+    );
+    println!();
+    println!("{}", tag.get_message().unwrap_or_default());
+}
+
+/// Prints the object in a readable form, blobs as they are
+pub fn pretty_print(sha: &str) -> anyhow::Result<()> {
+    let (obj_type, data) = read_object(sha)?;
+    match obj_type.as_str() {
+        "blob" => std::io::stdout().write_all(&data)?,
+        "tree" => print_tree(&data)?,
+        "commit" => print_commit(&Commit::from_file(&object_path(sha))?),
+        "tag" => print_tag(&Tag::from_file(&object_path(sha))?),
+        _ => return Err(anyhow!("Unknown object type '{}'", obj_type)),
+    }
+    Ok(())
+}
+
+/// Prints type, size or contents of any object: hash, revision, tag or <revision>:<path>
+pub fn cat_file(object: &str, mode: CatFileMode) -> anyhow::Result<()> {
+    let sha = refs::resolve_object(object)?;
+    match mode {
+        CatFileMode::Type => println!("{}", read_object(&sha)?.0),
+        CatFileMode::Size => println!("{}", read_object(&sha)?.1.len()),
+        CatFileMode::Pretty => pretty_print(&sha)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+use crate::ignore::IgnoreMatcher;
+
+pub fn check_ignore(paths: &[PathBuf], verbose: bool) -> anyhow::Result<()> {
+    let mut matcher = IgnoreMatcher::new()?;
+    for path in paths {
+        // Only ignored paths are printed, a path re-included with '!' is not
+        let pattern = match matcher.matching_pattern(path, path.is_dir())? {
+            Some(pattern) if !pattern.is_negated() => pattern,
+            _ => continue,
+        };
+
+        if verbose {
+            println!("{}\t{}", pattern.describe(), path.to_string_lossy());
+        } else {
+            println!("{}", path.to_string_lossy());
+        }
+    }
+    Ok(())
+}
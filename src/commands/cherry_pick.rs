@@ -0,0 +1,365 @@
+// A cherry-pick stopped by conflicts keeps its state in .gilltter/cherry-pick/:
+/*
+ * head       commit HEAD pointed to before the cherry-pick, for --abort
+ * todo       commits left to pick, one per line, the first one is being resolved
+ * conflicts  paths which had conflicts, one per line
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTTER_CHERRY_PICK_DIR, GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    commands::{
+        commit::commit_as,
+        reset::{self, ResetMode},
+    },
+    index::index::Index,
+    merge::{self, TreeMerge},
+    objects::{
+        ObjectPump,
+        commit::{Commit, read_commit, read_commit_entries},
+        tree::FileType,
+    },
+    refs, worktree,
+};
+
+const STATE_HEAD_FILE: &'static str = "head";
+const STATE_TODO_FILE: &'static str = "todo";
+const STATE_CONFLICTS_FILE: &'static str = "conflicts";
+
+fn state_dir() -> PathBuf {
+    Path::new(GILLTTER_PATH).join(GILLTTER_CHERRY_PICK_DIR)
+}
+
+fn first_line(message: &str) -> &str {
+    message.lines().next().unwrap_or_default()
+}
+
+struct State {
+    orig_head: String,
+    todo: Vec<String>,
+    conflicts: Vec<PathBuf>,
+}
+
+impl State {
+    fn load() -> anyhow::Result<Self> {
+        let dir = state_dir();
+        if !dir.is_dir() {
+            return Err(anyhow!("There is no cherry-pick in progress"));
+        }
+        let read_lines = |name: &str| -> anyhow::Result<Vec<String>> {
+            Ok(fs::read_to_string(dir.join(name))?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string())
+                .collect())
+        };
+        Ok(Self {
+            orig_head: fs::read_to_string(dir.join(STATE_HEAD_FILE))?
+                .trim()
+                .to_string(),
+            todo: read_lines(STATE_TODO_FILE)?,
+            conflicts: read_lines(STATE_CONFLICTS_FILE)?
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let dir = state_dir();
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(STATE_HEAD_FILE), &self.orig_head)?;
+        fs::write(dir.join(STATE_TODO_FILE), self.todo.join("\n"))?;
+        let conflicts: Vec<String> = self
+            .conflicts
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        fs::write(dir.join(STATE_CONFLICTS_FILE), conflicts.join("\n"))?;
+        Ok(())
+    }
+
+    fn remove() -> anyhow::Result<()> {
+        fs::remove_dir_all(state_dir())?;
+        Ok(())
+    }
+}
+
+fn head_entries() -> anyhow::Result<BTreeMap<PathBuf, (FileType, String)>> {
+    match refs::head_commit()? {
+        Some(head) => read_commit_entries(&head),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Merges the changes the commit made to its parent into the index and the work tree
+pub fn apply_changes(sha: &str, commit: &Commit) -> anyhow::Result<TreeMerge> {
+    let parent_entries = match refs::history_parent(sha, commit, &refs::shallow_commits()) {
+        Some(parent) => read_commit_entries(&parent)?,
+        None => BTreeMap::new(),
+    };
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    let merged = merge::merge_trees(
+        &parent_entries,
+        &index.entries(),
+        &read_commit_entries(sha)?,
+        "HEAD",
+        &format!(
+            "{} ({})",
+            refs::short_hash(sha),
+            first_line(&commit.get_message().unwrap_or_default())
+        ),
+    )?;
+    merge::apply(&merged, &index)?;
+    Ok(merged)
+}
+
+// Commits whatever is in the index as the picked commit, unless it changes nothing
+fn commit_picked(
+    sha: &str,
+    picked: &Commit,
+    files: &BTreeMap<PathBuf, (FileType, String)>,
+) -> anyhow::Result<()> {
+    let message = picked.get_message().unwrap_or_default();
+    if *files == head_entries()? {
+        println!(
+            "Skipping {} ({}), it changes nothing",
+            refs::short_hash(sha),
+            first_line(&message)
+        );
+        return Ok(());
+    }
+    if files.is_empty() {
+        return Err(anyhow!(
+            "Picking {} would leave nothing to commit",
+            refs::short_hash(sha)
+        ));
+    }
+    let commit_hash = commit_as(&Index::from_checkout(files), message.clone(), Some(picked))?;
+    println!(
+        "[{}] {}",
+        refs::short_hash(&commit_hash),
+        first_line(&message)
+    );
+    Ok(())
+}
+
+// Picks the commits one by one, stops and saves the state at the first conflict
+fn pick_all(orig_head: &str, commits: &[String]) -> anyhow::Result<()> {
+    for (done, sha) in commits.iter().enumerate() {
+        let picked = read_commit(sha)?;
+        let merged = apply_changes(sha, &picked)?;
+        if merged.conflicts.is_empty() {
+            commit_picked(sha, &picked, &merged.files)?;
+            continue;
+        }
+
+        for (path, kind) in &merged.conflicts {
+            println!("CONFLICT ({}): {}", kind, path.to_string_lossy());
+        }
+        State {
+            orig_head: orig_head.to_string(),
+            todo: commits[done..].to_vec(),
+            conflicts: merged.conflicts.into_iter().map(|(path, _)| path).collect(),
+        }
+        .save()?;
+        return Err(anyhow!(
+            "Could not pick {}, fix the conflicts, add the files and run 'gilltter cherry-pick --continue'",
+            refs::short_hash(sha)
+        ));
+    }
+    Ok(())
+}
+
+/// Replays the changes of the commits on top of HEAD, keeping their authors and messages
+pub fn cherry_pick(revisions: &[String]) -> anyhow::Result<()> {
+    if state_dir().exists() {
+        return Err(anyhow!(
+            "A cherry-pick is in progress, use --continue or --abort"
+        ));
+    }
+    let commits = refs::resolve_commits(revisions)?;
+    let orig_head =
+        refs::head_commit()?.ok_or(anyhow!("There are no commits to cherry-pick onto"))?;
+
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    if index.entries() != read_commit_entries(&orig_head)? {
+        return Err(anyhow!("The index has staged changes, commit them first"));
+    }
+    pick_all(&orig_head, &commits)
+}
+
+/// Commits the resolved commit and picks the rest
+pub fn cherry_pick_continue() -> anyhow::Result<()> {
+    let state = State::load()?;
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    let files = index.entries();
+    for path in &state.conflicts {
+        if worktree::file_hash(path).as_ref() != files.get(path).map(|(_, sha)| sha) {
+            return Err(anyhow!(
+                "'{}' had conflicts and is not added yet",
+                path.to_string_lossy()
+            ));
+        }
+    }
+
+    let Some((current, rest)) = state.todo.split_first() else {
+        return State::remove();
+    };
+    commit_picked(current, &read_commit(current)?, &files)?;
+    // The rest may stop again and save a new state
+    State::remove()?;
+    pick_all(&state.orig_head, rest)
+}
+
+/// Goes back to where HEAD was before the cherry-pick
+pub fn cherry_pick_abort() -> anyhow::Result<()> {
+    let state = State::load()?;
+    reset::reset(Some(&state.orig_head), ResetMode::Hard, &[])?;
+    State::remove()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::commit::create_commit, objects::commit::read_commit_files,
+        test_utils::TempRepository,
+    };
+
+    fn index() -> Index {
+        Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE)).unwrap()
+    }
+
+    // A commit by someone else on top of `parent` with `path` set to `contents`,
+    // HEAD, the index and the work tree are left as `parent` has them
+    fn side_commit(parent: &str, path: &str, contents: &str) -> String {
+        let mut author = Commit::new();
+        author
+            .set_username("other")
+            .set_email("other@example.com")
+            .set_secs_since_epoch(Some(1_000_000));
+        fs::write(path, contents).unwrap();
+        crate::commands::add::add(Path::new(path)).unwrap();
+        let sha = create_commit(
+            &index(),
+            "side".to_string(),
+            Some(parent.to_string()),
+            Some(&author),
+        )
+        .unwrap();
+        reset::reset(Some(parent), ResetMode::Hard, &[]).unwrap();
+        sha
+    }
+
+    #[test]
+    fn picks_keep_the_author_and_time() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        let side = side_commit(&first, "b.txt", "side\n");
+        repository.add("c.txt", "main\n");
+        let second = repository.commit("second");
+
+        cherry_pick(&[side]).unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        let picked = read_commit(&head).unwrap();
+        assert_eq!(picked.get_parent_commit_sha(), Some(second));
+        assert_eq!(picked.get_username().as_deref(), Some("other"));
+        assert_eq!(picked.get_email().as_deref(), Some("other@example.com"));
+        assert_eq!(picked.get_secs_since_epoch(), Some(1_000_000));
+        assert_eq!(picked.get_message().unwrap().trim(), "side");
+        assert_eq!(fs::read_to_string("b.txt").unwrap(), "side\n");
+        assert_eq!(fs::read_to_string("c.txt").unwrap(), "main\n");
+        assert!(!state_dir().exists());
+    }
+
+    #[test]
+    fn conflicts_save_the_state_for_continue() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        let side = side_commit(&first, "a.txt", "side\n");
+        repository.add("a.txt", "main\n");
+        let second = repository.commit("second");
+
+        assert!(cherry_pick(std::slice::from_ref(&side)).is_err());
+        assert_eq!(refs::head_commit().unwrap(), Some(second.clone()));
+        let dir = state_dir();
+        assert_eq!(
+            fs::read_to_string(dir.join(STATE_HEAD_FILE)).unwrap(),
+            second
+        );
+        assert_eq!(fs::read_to_string(dir.join(STATE_TODO_FILE)).unwrap(), side);
+        assert_eq!(
+            fs::read_to_string(dir.join(STATE_CONFLICTS_FILE)).unwrap(),
+            "a.txt"
+        );
+        assert!(
+            fs::read_to_string("a.txt")
+                .unwrap()
+                .contains("<<<<<<< HEAD\nmain\n")
+        );
+
+        // Not resolved yet
+        assert!(cherry_pick_continue().is_err());
+        repository.add("a.txt", "both\n");
+        cherry_pick_continue().unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        let picked = read_commit(&head).unwrap();
+        assert_eq!(picked.get_parent_commit_sha(), Some(second));
+        assert_eq!(picked.get_username().as_deref(), Some("other"));
+        assert_eq!(read_commit_files(&head).unwrap(), index().files());
+        assert!(!state_dir().exists());
+    }
+
+    #[test]
+    fn abort_restores_head_and_files() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        let side = side_commit(&first, "a.txt", "side\n");
+        repository.add("a.txt", "main\n");
+        let second = repository.commit("second");
+
+        assert!(cherry_pick(&[side]).is_err());
+        cherry_pick_abort().unwrap();
+        assert_eq!(refs::head_commit().unwrap(), Some(second.clone()));
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "main\n");
+        assert_eq!(index().files(), read_commit_files(&second).unwrap());
+        assert!(!state_dir().exists());
+        assert!(cherry_pick_abort().is_err());
+    }
+
+    #[test]
+    fn picks_carry_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repository = TempRepository::new();
+        repository.add("run.sh", "#!/bin/sh\n");
+        let first = repository.commit("first");
+        fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+        repository.add("run.sh", "#!/bin/sh\n");
+        let chmod = repository.commit("chmod");
+        reset::reset(Some(&first), ResetMode::Hard, &[]).unwrap();
+        let mode = || fs::metadata("run.sh").unwrap().permissions().mode() & 0o111;
+        assert_eq!(mode(), 0);
+        repository.add("a.txt", "a\n");
+        repository.commit("second");
+
+        cherry_pick(&[chmod]).unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        assert_eq!(
+            read_commit_entries(&head).unwrap()[Path::new("run.sh")].0,
+            FileType::ExecutableFile
+        );
+        assert_eq!(mode(), 0o111);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{self, GILLTER_BRANCHES_DIR, GILLTTER_PATH},
+    commands::{
+        fetch::fetch_remote,
+        reset::{self, ResetMode},
+    },
+    config::{Config, ConfigScope},
+    hooks,
+    objects::ObjectDump,
+    refs,
+    remote::{
+        self, BLOB_NONE_FILTER, CONFIG_BRANCH_SECTION, CONFIG_FILTER_FIELD, CONFIG_MERGE_FIELD,
+        CONFIG_REMOTE_FIELD, CONFIG_REMOTE_SECTION, CONFIG_URL_FIELD, DEFAULT_REMOTE, HEAD_REF,
+    },
+};
+
+const DEFAULT_BRANCH: &'static str = "main";
+const BUNDLE_EXTENSION: &'static str = "bundle";
+
+// "/srv/project", "/srv/project/.gilltter" and "/srv/project.bundle" are all cloned into "project"
+fn directory_name(url: &str) -> anyhow::Result<PathBuf> {
+    let path = Path::new(url);
+    let path = match path.file_name() {
+        Some(name) if name == GILLTTER_PATH => path.parent().unwrap_or(path),
+        _ => path,
+    };
+    let name = match path.extension() {
+        Some(extension) if extension == BUNDLE_EXTENSION => path.file_stem(),
+        _ => path.file_name(),
+    };
+    name.map(PathBuf::from)
+        .ok_or(anyhow!("Could not guess a directory name for '{}'", url))
+}
+
+// Branch the remote's HEAD is on, main, or the first branch there is
+fn default_branch(remote_refs: &BTreeMap<String, String>) -> Option<String> {
+    let branch_ref = |name: &str| format!("{}/{}", GILLTER_BRANCHES_DIR, name);
+    let head_branch = remote_refs
+        .get(HEAD_REF)
+        .and_then(|head| head.strip_prefix(refs::HEAD_REF_PREFIX))
+        .and_then(|target| target.strip_prefix(GILLTER_BRANCHES_DIR))
+        .and_then(|name| name.strip_prefix('/'));
+    head_branch
+        .into_iter()
+        .chain([DEFAULT_BRANCH])
+        .find(|name| remote_refs.contains_key(&branch_ref(name)))
+        .map(|name| name.to_string())
+        .or_else(|| {
+            remote_refs
+                .keys()
+                .find_map(|name| name.strip_prefix(&branch_ref("")))
+                .map(|name| name.to_string())
+        })
+}
+
+/// Copies the repository into a new directory, sets it up as the "origin" remote
+/// and checks out its default branch, which then tracks the one of origin
+pub fn clone(
+    url: &str,
+    directory: Option<&Path>,
+    depth: Option<usize>,
+    filter: Option<&str>,
+) -> anyhow::Result<()> {
+    if depth == Some(0) {
+        return Err(anyhow!("The depth should be at least 1"));
+    }
+    if let Some(filter) = filter
+        && filter != BLOB_NONE_FILTER
+    {
+        return Err(anyhow!(
+            "Unknown filter '{}', only {} is supported",
+            filter,
+            BLOB_NONE_FILTER
+        ));
+    }
+    let url = remote::normalize_url(url)?;
+    let directory = match directory {
+        Some(directory) => directory.to_path_buf(),
+        None => directory_name(&url)?,
+    };
+    if directory.exists() && directory.read_dir()?.next().is_some() {
+        return Err(anyhow!(
+            "'{}' already exists and is not empty",
+            directory.to_string_lossy()
+        ));
+    }
+    std::fs::create_dir_all(&directory)?;
+    std::env::set_current_dir(&directory)?;
+    println!("Cloning into '{}'", directory.to_string_lossy());
+    base::gilltter_init()?;
+
+    let config_path = ConfigScope::Local
+        .path()
+        .ok_or(anyhow!("No local config"))?;
+    let mut config = Config::load(&config_path)?;
+    let remote_section = format!("{}.{}", CONFIG_REMOTE_SECTION, DEFAULT_REMOTE);
+    config.set(&remote_section, CONFIG_URL_FIELD, &url);
+    if let Some(filter) = filter {
+        config.set(&remote_section, CONFIG_FILTER_FIELD, filter);
+    }
+    config.dump_to_file()?;
+
+    let remote_refs = fetch_remote(DEFAULT_REMOTE, depth)?;
+    match default_branch(&remote_refs) {
+        Some(branch) => {
+            let sha = &remote_refs[&format!("{}/{}", GILLTER_BRANCHES_DIR, branch)];
+            refs::update_branch(&branch, sha)?;
+            refs::write_head(&format!(
+                "{}{}/{}",
+                refs::HEAD_REF_PREFIX,
+                GILLTER_BRANCHES_DIR,
+                branch
+            ))?;
+            let section = format!("{}.{}", CONFIG_BRANCH_SECTION, branch);
+            config.set(&section, CONFIG_REMOTE_FIELD, DEFAULT_REMOTE);
+            config.set(&section, CONFIG_MERGE_FIELD, &branch);
+            config.dump_to_file()?;
+        }
+        None => match remote_refs.get(HEAD_REF) {
+            // No branches, but HEAD is detached at a commit
+            Some(head) if !head.is_empty() && !head.starts_with(refs::HEAD_REF_PREFIX) => {
+                refs::write_head(head)?
+            }
+            _ => {
+                println!("The repository is empty");
+                return Ok(());
+            }
+        },
+    }
+    reset::reset(None, ResetMode::Hard, &[])?;
+    if let Some(head) = refs::head_commit()? {
+        hooks::post_checkout(None, &head);
+    }
+    Ok(())
+}
@@ -1,118 +1,164 @@
+use std::{fs, path::Path};
+
 use anyhow::anyhow;
-use std::{
-    fs::OpenOptions,
-    io::{Read, Seek, SeekFrom, Write},
-    path::Path,
-};
 
 use crate::{
-    base::{GILLTER_CONFIG_FILE, GILLTER_HEAD_FILE, GILLTTER_PATH},
-    config::{self, Config},
+    base::{GILLTTER_COMMIT_MESSAGE_FILE, GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    commands::write_tree,
+    config, hooks,
     index::index::Index,
-    objects::{
-        ObjectDump, ObjectPump,
-        commit::Commit,
-        tree::{self, Tree, TreeObject},
-    },
+    objects::{ObjectDump, ObjectPump, commit::Commit},
+    refs,
 };
 
-pub fn commit(index: &Index, message: String) -> anyhow::Result<String> {
-    assert!(!index.indices.is_empty());
-    let mut base_tree = Tree::new();
-    // Create a base tree, which all other objects are added to
-
-    for entry in index.indices.iter() {
-        let name = entry.filename.to_string_lossy().to_string();
-        let paths: Vec<&str> = name.split('/').collect();
-
-        // If it is only a file in a root dir, just add it...
-        if paths.len() == 1 {
-            base_tree.add_object(
-                paths.first().unwrap(),
-                TreeObject::Blob(entry.sha1_hash.clone()),
-            );
-            continue;
-        }
-
-        // Make sure src tree exists
-        base_tree
-            .add_object_if_not_exists(paths.first().unwrap(), || TreeObject::Tree(Tree::new()));
-
-        // Current reference to tree so we can like traverse it
-        let mut this_tree: &mut TreeObject =
-            base_tree.get_object_mut(paths.first().unwrap()).unwrap();
-
-        let dirs = &paths[1..paths.len() - 1];
-        // Iterate until 1 before last, because the last one is a file
-        for dir in dirs {
-            let next = match this_tree {
-                TreeObject::Tree(tree) => {
-                    tree.add_object_if_not_exists(dir, || TreeObject::Tree(Tree::new()));
-                    tree.get_object_mut(dir).unwrap()
-                }
-                _ => panic!("expected tree, found blob"),
-            };
-            this_tree = next;
-        }
-
-        if let TreeObject::Tree(tree) = this_tree {
-            // println!("Dirs: {:?}, Tree: {:#?}, file: {}, exists: {}", dirs, tree.objects, paths.last().unwrap(), tree.object_exists(paths.last().unwrap()));
-            tree.add_object(
-                paths.last().unwrap(),
-                TreeObject::Blob(entry.sha1_hash.clone()),
-            );
-        }
-    }
-    // Create commit object
-    let config = Config::from_file(&Path::new(GILLTTER_PATH).join(GILLTER_CONFIG_FILE))?;
+// Author of new commits, from General.Username and General.Email
+pub fn configured_user() -> anyhow::Result<(String, String)> {
+    let config = config::load_layered()?;
     let username = config
         .get(
             config::CONFIG_GENERAL_SECTION,
             config::CONFIG_USERNAME_FIELD,
         )
-        .ok_or(anyhow!("Username should be set in config file"))?;
+        .ok_or(anyhow!(
+            "Username should be set, try: gilltter config --global set General.Username <name>"
+        ))?;
     let email = config
         .get(config::CONFIG_GENERAL_SECTION, config::CONFIG_EMAIL_FIELD)
-        .ok_or(anyhow!("Email should be set in config file"))?;
-
-    let base_tree_hash = base_tree.dump_to_file()?;
+        .ok_or(anyhow!(
+            "Email should be set, try: gilltter config --global set General.Email <email>"
+        ))?;
+    Ok((username, email))
+}
 
-    let base_tree_objects = base_tree.get_objects();
-    for object in base_tree_objects.values() {
-        if let TreeObject::Tree(tree) = object {
-            // Dump all subtrees
-            tree::dump_tree_recursive(tree)?;
-        }
+// Lets the commit-msg hook change the message in a file
+fn run_commit_msg_hook(message: String) -> anyhow::Result<String> {
+    let path = Path::new(GILLTTER_PATH).join(GILLTTER_COMMIT_MESSAGE_FILE);
+    // Ends with a newline like any text file, so lines the hook appends stay lines
+    fs::write(&path, format!("{}\n", message.trim_end_matches('\n')))?;
+    hooks::run_checked(hooks::COMMIT_MSG_HOOK, &[&path.to_string_lossy()], None)?;
+    let edited = fs::read_to_string(&path)?;
+    let edited = edited.trim_end_matches('\n');
+    if edited.trim().is_empty() {
+        return Err(anyhow!(
+            "The commit message is empty after the commit-msg hook"
+        ));
     }
+    Ok(edited.to_string())
+}
 
-    // Open head file containing parent commit
-    let mut head_file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(&Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE))?;
-    let mut file_bytes = Vec::new();
-    head_file.read_to_end(&mut file_bytes)?;
+/// Commits the index as the configured user. With `verify` the pre-commit hook runs first,
+/// it may still change the index, then the commit-msg hook may change the message
+pub fn commit(message: String, verify: bool) -> anyhow::Result<String> {
+    let message = if verify {
+        hooks::run_checked(hooks::PRE_COMMIT_HOOK, &[], None)?;
+        run_commit_msg_hook(message)?
+    } else {
+        message
+    };
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))
+        .map_err(|why| anyhow!("Could not parse index file: {}", why))?;
+    let commit_hash = commit_as(&index, message, None)?;
+    hooks::run_reported(hooks::POST_COMMIT_HOOK, &[]);
+    Ok(commit_hash)
+}
 
-    // Safe to use from_utf8_lossy, since no funny stuff should be in 'head'
-    let parent_commit_hash = String::from_utf8_lossy(&file_bytes); // Empty, if first commit
+/// Commits the index with the author (name, email and time) taken from `author_of`,
+/// e.g. when a commit is replayed somewhere else. The configured user is the author otherwise
+pub fn commit_as(
+    index: &Index,
+    message: String,
+    author_of: Option<&Commit>,
+) -> anyhow::Result<String> {
+    let parent_commit_hash = refs::head_commit()?; // None, if first commit
+    let commit_hash = create_commit(index, message, parent_commit_hash, author_of)?;
+
+    // Move HEAD (or the branch it is on) to the new commit
+    refs::update_head(&commit_hash)?;
+
+    Ok(commit_hash)
+}
+
+/// Stores a commit of the index with the given parent, HEAD is left alone
+pub fn create_commit(
+    index: &Index,
+    message: String,
+    parent_commit_hash: Option<String>,
+    author_of: Option<&Commit>,
+) -> anyhow::Result<String> {
+    if index.indices.is_empty() {
+        return Err(anyhow!("Nothing to commit, the index is empty"));
+    }
+    let tree_sha = write_tree::build_tree(index)?;
+    store_commit(&tree_sha, message, parent_commit_hash, author_of)
+}
+
+/// Stores a commit of an already stored tree, HEAD is left alone
+pub fn store_commit(
+    tree_sha: &str,
+    message: String,
+    parent_commit_hash: Option<String>,
+    author_of: Option<&Commit>,
+) -> anyhow::Result<String> {
+    // Create commit object
+    let (username, email) = match author_of {
+        Some(original) => (
+            original
+                .get_username()
+                .ok_or(anyhow!("Original commit has no author"))?,
+            original
+                .get_email()
+                .ok_or(anyhow!("Original commit has no email"))?,
+        ),
+        None => configured_user()?,
+    };
 
     let mut commit = Commit::new();
     commit
-        .set_tree_sha(base_tree_hash)
-        .set_parent_commit_sha(if !parent_commit_hash.is_empty() {
-            Some(parent_commit_hash.to_string())
-        } else {
-            None
-        })
+        .set_tree_sha(tree_sha.to_string())
+        .set_parent_commit_sha(parent_commit_hash)
         .set_message(message)
         .set_username(username)
-        .set_email(email);
-    let commit_hash = commit.dump_to_file()?;
+        .set_email(email)
+        .set_secs_since_epoch(author_of.and_then(|original| original.get_secs_since_epoch()));
+    commit.dump_to_file()
+}
 
-    // Write latest commit hash to the HEAD file
-    head_file.seek(SeekFrom::Start(0))?; // Move cursor to the start of the file
-    head_file.write_all(commit_hash.as_bytes())?;
-    head_file.flush()?;
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{objects::commit::read_commit, test_utils::TempRepository};
 
-    Ok(commit_hash)
+    fn head_message() -> String {
+        let head = refs::head_commit().unwrap().unwrap();
+        read_commit(&head).unwrap().get_message().unwrap()
+    }
+
+    #[test]
+    fn commit_msg_hook_edits_the_message() {
+        let repository = TempRepository::new();
+        repository.hook(
+            hooks::COMMIT_MSG_HOOK,
+            "echo 'Signed-off-by: tester' >> \"$1\"",
+        );
+        repository.add("a.txt", "a\n");
+        commit("first".to_string(), true).unwrap();
+        assert_eq!(head_message(), "first\nSigned-off-by: tester");
+
+        repository.hook(hooks::COMMIT_MSG_HOOK, ": > \"$1\"");
+        repository.add("a.txt", "b\n");
+        assert!(commit("second".to_string(), true).is_err());
+    }
+
+    #[test]
+    fn no_verify_skips_the_hooks() {
+        let repository = TempRepository::new();
+        repository.hook(hooks::PRE_COMMIT_HOOK, "exit 1");
+        repository.hook(hooks::COMMIT_MSG_HOOK, "echo changed > \"$1\"");
+        repository.add("a.txt", "a\n");
+        assert!(commit("first".to_string(), true).is_err());
+        assert_eq!(refs::head_commit().unwrap(), None);
+
+        commit("first".to_string(), false).unwrap();
+        assert_eq!(head_message(), "first");
+    }
 }
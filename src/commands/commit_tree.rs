@@ -0,0 +1,39 @@
+use std::io::Read;
+
+use anyhow::anyhow;
+
+use crate::{
+    commands::commit::store_commit,
+    objects::{commit::read_commit, object_type},
+    refs,
+};
+
+/// Stores a commit of the tree (or of the tree of a commit) and prints its hash.
+/// The message is read from stdin if not given
+pub fn commit_tree(tree: &str, parent: Option<&str>, message: Option<&str>) -> anyhow::Result<()> {
+    let mut tree_sha = refs::peel(&refs::resolve_object(tree)?)?;
+    if object_type(&tree_sha)? == "commit" {
+        tree_sha = read_commit(&tree_sha)?
+            .get_tree_sha()
+            .ok_or(anyhow!("Commit {} has no tree", tree_sha))?;
+    }
+    if object_type(&tree_sha)? != "tree" {
+        return Err(anyhow!("'{}' is not a tree", tree));
+    }
+    let parent = parent.map(refs::resolve_revision).transpose()?;
+
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => {
+            let mut message = String::new();
+            std::io::stdin().read_to_string(&mut message)?;
+            message.trim_end().to_string()
+        }
+    };
+    if message.is_empty() {
+        return Err(anyhow!("The commit message is empty"));
+    }
+
+    println!("{}", store_commit(&tree_sha, message, parent, None)?);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use anyhow::anyhow;
+
+use crate::{
+    config::{self, Config, ConfigScope},
+    objects::ObjectDump,
+};
+
+fn scope_config(scope: ConfigScope) -> anyhow::Result<Config> {
+    let path = scope
+        .path()
+        .ok_or(anyhow!("Could not find a path for {:?} config", scope))?;
+    Config::load(&path)
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Size,
+}
+
+// Prints the value in its canonical form, so scripts don't have to know "on" == "true"
+fn canonical_value(value: &str, value_type: Option<ValueType>) -> anyhow::Result<String> {
+    let Some(value_type) = value_type else {
+        return Ok(value.to_owned());
+    };
+    let canonical = match value_type {
+        ValueType::Bool => config::parse_bool(value).map(|value| value.to_string()),
+        ValueType::Int => config::parse_int(value).map(|value| value.to_string()),
+        ValueType::Size => config::parse_size(value).map(|value| value.to_string()),
+    };
+    canonical.ok_or_else(|| anyhow!("Value '{}' is not a valid {:?}", value, value_type))
+}
+
+/// Reads a value from the given scope, or from all of them if there is none
+pub fn get(
+    key: &str,
+    all: bool,
+    value_type: Option<ValueType>,
+    scope: Option<ConfigScope>,
+) -> anyhow::Result<()> {
+    let (category, name) = config::split_key(key)?;
+    let config = match scope {
+        Some(scope) => scope_config(scope)?,
+        None => config::load_layered()?,
+    };
+
+    let values = if all {
+        config.get_all(category, name)
+    } else {
+        config.get(category, name).into_iter().collect()
+    };
+    if values.is_empty() {
+        return Err(anyhow!("Key '{}' is not set", key));
+    }
+    for value in values {
+        println!("{}", canonical_value(&value, value_type)?);
+    }
+    Ok(())
+}
+
+/// Adds one more value to a multi-valued key
+pub fn add(key: &str, value: &str, scope: Option<ConfigScope>) -> anyhow::Result<()> {
+    let (category, name) = config::split_key(key)?;
+    let mut config = scope_config(scope.unwrap_or(ConfigScope::Local))?;
+    config.add(category, name, value);
+    config.dump_to_file()?;
+    Ok(())
+}
+
+/// Writes to the local config unless told otherwise
+pub fn set(key: &str, value: &str, scope: Option<ConfigScope>) -> anyhow::Result<()> {
+    let (category, name) = config::split_key(key)?;
+    let mut config = scope_config(scope.unwrap_or(ConfigScope::Local))?;
+    config.set(category, name, value);
+    config.dump_to_file()?;
+    Ok(())
+}
+
+pub fn unset(key: &str, scope: Option<ConfigScope>) -> anyhow::Result<()> {
+    let (category, name) = config::split_key(key)?;
+    let mut config = scope_config(scope.unwrap_or(ConfigScope::Local))?;
+    if !config.unset(category, name) {
+        return Err(anyhow!("Key '{}' is not set", key));
+    }
+    config.dump_to_file()?;
+    Ok(())
+}
+
+pub fn list(scope: Option<ConfigScope>) -> anyhow::Result<()> {
+    let config = match scope {
+        Some(scope) => scope_config(scope)?,
+        None => config::load_layered()?,
+    };
+    for (category, name, value) in config.entries() {
+        println!("{}.{}={}", category, name, value);
+    }
+    Ok(())
+}
@@ -0,0 +1,235 @@
+// Output is a fast-import stream, the format `git fast-import` reads too:
+/*
+ * blob
+ * mark :1
+ * data <size>
+ * <contents>
+ *
+ * reset refs/heads/main            before a commit without parent
+ * commit refs/heads/main
+ * mark :2
+ * author <name> <<email>> <secs> +0000
+ * committer <name> <<email>> <secs> +0000
+ * data <size>
+ * <message>
+ * from :<mark of the parent>
+ * M <mode> :1 <path>               files which changed since the parent, mode is
+ *                                  100644, 100755 for executables or 120000 for links
+ * D <path>                         files which are gone
+ */
+// then branches are reset to their commits and tags are created.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Write,
+    path::PathBuf,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    objects::{
+        ObjectPump,
+        blob::Blob,
+        commit::{self, Commit},
+        object_type,
+        tag::Tag,
+        tree::FileType,
+    },
+    refs,
+};
+
+struct Exporter<W: Write> {
+    output: W,
+    marks: HashMap<String, usize>, // hash of a blob or commit -> its mark
+    shallow: BTreeSet<String>,     // commits exported as if they had no parent
+}
+
+impl<W: Write> Exporter<W> {
+    fn new_mark(&mut self, sha: &str) -> usize {
+        let mark = self.marks.len() + 1;
+        self.marks.insert(sha.to_string(), mark);
+        mark
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        writeln!(self.output, "data {}", data.len())?;
+        self.output.write_all(data)?;
+        writeln!(self.output)?;
+        Ok(())
+    }
+
+    fn export_blob(&mut self, sha: &str) -> anyhow::Result<usize> {
+        if let Some(mark) = self.marks.get(sha) {
+            return Ok(*mark);
+        }
+        let data = Blob::from_file(&object_path(sha))?.get_data();
+        let mark = self.new_mark(sha);
+        writeln!(self.output, "blob\nmark :{}", mark)?;
+        self.write_data(&data).map(|_| mark)
+    }
+
+    /// Exports the commit and whatever of its history is not exported yet
+    fn export_commit(&mut self, tip: &str, ref_name: &str) -> anyhow::Result<usize> {
+        // Commits which are not exported yet, newest first
+        let mut chain = Vec::new();
+        let mut next = Some(tip.to_string());
+        while let Some(sha) = next.take() {
+            if self.marks.contains_key(&sha) {
+                break;
+            }
+            let commit = commit::read_commit(&sha)?;
+            next = refs::history_parent(&sha, &commit, &self.shallow);
+            chain.push((sha, commit));
+        }
+
+        for (sha, commit) in chain.into_iter().rev() {
+            self.write_commit(&sha, &commit, ref_name)?;
+        }
+        self.marks
+            .get(tip)
+            .copied()
+            .ok_or(anyhow!("Commit {} was not exported", tip))
+    }
+
+    fn write_commit(&mut self, sha: &str, commit: &Commit, ref_name: &str) -> anyhow::Result<()> {
+        let parent = refs::history_parent(sha, commit, &self.shallow);
+        let parent_files = match &parent {
+            Some(parent) => commit::read_commit_entries(parent)?,
+            None => BTreeMap::new(),
+        };
+        let files = commit::read_commit_entries(sha)?;
+
+        let mut changes: Vec<(PathBuf, Option<(FileType, usize)>)> = Vec::new();
+        for (path, entry) in &files {
+            if parent_files.get(path) != Some(entry) {
+                let (mode, blob_sha) = entry;
+                changes.push((path.clone(), Some((*mode, self.export_blob(blob_sha)?))));
+            }
+        }
+        for path in parent_files.keys() {
+            if !files.contains_key(path) {
+                changes.push((path.clone(), None));
+            }
+        }
+
+        let person = format!(
+            "{} <{}> {} +0000",
+            commit.get_username().unwrap_or_default(),
+            commit.get_email().unwrap_or_default(),
+            commit.get_secs_since_epoch().unwrap_or_default()
+        );
+        if parent.is_none() {
+            writeln!(self.output, "reset {}", ref_name)?;
+        }
+        let mark = self.new_mark(sha);
+        writeln!(self.output, "commit {}\nmark :{}", ref_name, mark)?;
+        writeln!(self.output, "author {}\ncommitter {}", person, person)?;
+        self.write_data(format!("{}\n", commit.get_message().unwrap_or_default()).as_bytes())?;
+        if let Some(parent) = &parent {
+            writeln!(self.output, "from :{}", self.marks[parent])?;
+        }
+        for (path, blob_mark) in changes {
+            match blob_mark {
+                Some((mode, blob_mark)) => writeln!(
+                    self.output,
+                    "M {} :{} {}",
+                    String::from_utf8_lossy(&mode.to_bytes()),
+                    blob_mark,
+                    path.to_string_lossy()
+                )?,
+                None => writeln!(self.output, "D {}", path.to_string_lossy())?,
+            }
+        }
+        writeln!(self.output)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Exporter<W> {
+    fn export_branch(&mut self, branch: &str) -> anyhow::Result<Option<(String, usize)>> {
+        let Some(sha) = refs::branch_commit(branch)? else {
+            return Ok(None);
+        };
+        let ref_name = format!("refs/heads/{}", branch);
+        let mark = self.export_commit(&sha, &ref_name)?;
+        Ok(Some((ref_name, mark)))
+    }
+
+    fn export_tag(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(sha) = refs::tag_object(name)? else {
+            return Ok(());
+        };
+        let target = refs::peel(&sha)?;
+        if object_type(&target)? != "commit" {
+            eprintln!("Skipping tag '{}': it does not point to a commit", name);
+            return Ok(());
+        }
+        let mark = self.export_commit(&target, &format!("refs/tags/{}", name))?;
+        if object_type(&sha)? == "tag" {
+            let tag = Tag::from_file(&object_path(&sha))?;
+            writeln!(self.output, "tag {}\nfrom :{}", name, mark)?;
+            writeln!(
+                self.output,
+                "tagger {} <{}> {} +0000",
+                tag.get_username().unwrap_or_default(),
+                tag.get_email().unwrap_or_default(),
+                tag.get_secs_since_epoch().unwrap_or_default()
+            )?;
+            self.write_data(format!("{}\n", tag.get_message().unwrap_or_default()).as_bytes())?;
+        } else {
+            writeln!(self.output, "reset refs/tags/{}\nfrom :{}\n", name, mark)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the branches and tags with their history as a fast-import stream to stdout.
+/// Every branch and tag if no names are given
+pub fn fast_export(names: &[String]) -> anyhow::Result<()> {
+    let all_branches = refs::branch_names()?;
+    let all_tags = refs::tag_names()?;
+    let (branches, tags) = if names.is_empty() {
+        (all_branches, all_tags)
+    } else {
+        let mut branches = Vec::new();
+        let mut tags = Vec::new();
+        for name in names {
+            if all_branches.contains(name) {
+                branches.push(name.clone());
+            } else if all_tags.contains(name) {
+                tags.push(name.clone());
+            } else {
+                return Err(anyhow!("'{}' is neither a branch nor a tag", name));
+            }
+        }
+        (branches, tags)
+    };
+
+    let mut exporter = Exporter {
+        output: std::io::BufWriter::new(std::io::stdout().lock()),
+        marks: HashMap::new(),
+        shallow: refs::shallow_commits(),
+    };
+    let mut exported = Vec::new();
+    for branch in &branches {
+        exported.extend(exporter.export_branch(branch)?);
+    }
+    // A detached HEAD is kept on a branch of its own, so it is not lost
+    if names.is_empty()
+        && refs::current_branch()?.is_none()
+        && let Some(sha) = refs::head_commit()?
+    {
+        let ref_name = "refs/heads/detached-head".to_string();
+        exported.push((ref_name.clone(), exporter.export_commit(&sha, &ref_name)?));
+    }
+    for (ref_name, mark) in exported {
+        writeln!(exporter.output, "reset {}\nfrom :{}\n", ref_name, mark)?;
+    }
+    for tag in &tags {
+        exporter.export_tag(tag)?;
+    }
+    exporter.output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,633 @@
+// Reads a fast-import stream from stdin, as `gilltter fast-export` or `git fast-export`
+// write it (see fast_export.rs for the format), and writes the blobs, trees and commits.
+// Commands: blob, commit (with M, D, R, C and deleteall), reset, tag and done; feature,
+// option and checkpoint are accepted and do nothing, progress lines are printed.
+// Data is either `data <size>` followed by that many bytes or `data <<END` followed by
+// lines up to END. A dataref is a mark (:1) or the hash of a stored blob.
+// Like import-git: merges keep their first parent, paths with spaces and submodules are
+// skipped, '/' in branch and tag names becomes '-'.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    commands::{
+        import_git::{self, without_spaces},
+        write_tree,
+    },
+    index::index::Index,
+    objects::{
+        ObjectDump,
+        blob::Blob,
+        commit::{self, Commit},
+        object_type,
+        tag::Tag,
+        tree::{self, FileType},
+    },
+    refs,
+};
+
+const BRANCH_REF_PREFIX: &'static str = "refs/heads/";
+const TAG_REF_PREFIX: &'static str = "refs/tags/";
+const SUBMODULE_MODE: &'static str = "160000";
+const DIRECTORY_MODE: &'static str = "040000";
+const INLINE_DATA: &'static str = "inline";
+const DEFAULT_BRANCH: &'static str = "main";
+
+struct Stream<R: BufRead> {
+    input: R,
+    peeked: Option<String>,
+    line_number: usize,
+}
+
+impl<R: BufRead> Stream<R> {
+    fn read_raw_line(&mut self) -> anyhow::Result<Option<String>> {
+        let mut line = Vec::new();
+        if self.input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        self.line_number += 1;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(Some(String::from_utf8_lossy(&line).to_string()))
+    }
+
+    /// Next line which is not a comment, without its '\n'
+    fn next_line(&mut self) -> anyhow::Result<Option<String>> {
+        if let Some(line) = self.peeked.take() {
+            return Ok(Some(line));
+        }
+        loop {
+            match self.read_raw_line()? {
+                Some(line) if line.starts_with('#') => continue,
+                line => return Ok(line),
+            }
+        }
+    }
+
+    /// Takes the next line if it starts with the prefix, returns what follows the prefix
+    fn take(&mut self, prefix: &str) -> anyhow::Result<Option<String>> {
+        let line = match self.peeked.take() {
+            Some(line) => Some(line),
+            None => self.next_line()?,
+        };
+        match line {
+            Some(line) if line.starts_with(prefix) => Ok(Some(line[prefix.len()..].to_string())),
+            line => {
+                self.peeked = line;
+                Ok(None)
+            }
+        }
+    }
+
+    fn error(&self, why: &str) -> anyhow::Error {
+        anyhow!("Line {}: {}", self.line_number, why)
+    }
+
+    fn read_data(&mut self) -> anyhow::Result<Vec<u8>> {
+        let header = self
+            .take("data ")?
+            .ok_or_else(|| self.error("expected data"))?;
+        if let Some(delimiter) = header.strip_prefix("<<") {
+            let mut data = Vec::new();
+            loop {
+                let line = self
+                    .read_raw_line()?
+                    .ok_or_else(|| self.error("data is not terminated"))?;
+                if line == delimiter {
+                    return Ok(data);
+                }
+                data.extend(line.as_bytes());
+                data.push(b'\n');
+            }
+        }
+
+        let size: usize = header
+            .parse()
+            .map_err(|_| self.error(&format!("bad data size '{}'", header)))?;
+        let mut data = vec![0; size];
+        self.input.read_exact(&mut data)?;
+        // The data may be followed by a '\n' which is not part of it
+        if self.input.fill_buf()?.first() == Some(&b'\n') {
+            self.input.consume(1);
+        }
+        Ok(data)
+    }
+}
+
+// Paths with special characters are quoted C-style: "a \"b\"\n". The path is the whole text,
+// or up to the first space if `ends_at_space`, the rest of the text is returned with it
+fn parse_path(text: &str, ends_at_space: bool) -> anyhow::Result<(String, &str)> {
+    let Some(quoted) = text.strip_prefix('"') else {
+        let (path, rest) = match text.split_once(' ') {
+            Some((path, rest)) if ends_at_space => (path, rest),
+            _ => (text, ""),
+        };
+        check_path(path)?;
+        return Ok((path.to_string(), rest));
+    };
+
+    let mut bytes = Vec::new();
+    let mut chars = quoted.char_indices();
+    while let Some((at, ch)) = chars.next() {
+        match ch {
+            '"' => {
+                let path = String::from_utf8_lossy(&bytes).to_string();
+                check_path(&path)?;
+                return Ok((path, quoted[at + 1..].trim_start()));
+            }
+            '\\' => {
+                let (_, escaped) = chars.next().ok_or(anyhow!("Bad quoted path {}", text))?;
+                let byte = match escaped {
+                    'a' => 0x07,
+                    'b' => 0x08,
+                    'f' => 0x0c,
+                    'n' => b'\n',
+                    'r' => b'\r',
+                    't' => b'\t',
+                    'v' => 0x0b,
+                    '0'..='7' => {
+                        let digits: String = [Some((at, escaped)), chars.next(), chars.next()]
+                            .into_iter()
+                            .flatten()
+                            .map(|(_, digit)| digit)
+                            .collect();
+                        u8::from_str_radix(&digits, 8)
+                            .map_err(|_| anyhow!("Bad quoted path {}", text))?
+                    }
+                    other => other as u8,
+                };
+                bytes.push(byte);
+            }
+            _ => bytes.extend(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Err(anyhow!("Bad quoted path {}", text))
+}
+
+// Paths of the stream become paths of the work tree, so they must stay inside it
+fn check_path(path: &str) -> anyhow::Result<()> {
+    for name in path.split('/') {
+        tree::check_entry_name(name).map_err(|_| anyhow!("Bad path '{}'", path))?;
+    }
+    Ok(())
+}
+
+// "refs/heads/a/b" -> (true, "a-b"), None for refs which are neither branches nor tags
+fn local_name(ref_name: &str) -> Option<(bool, String)> {
+    match (
+        ref_name.strip_prefix(BRANCH_REF_PREFIX),
+        ref_name.strip_prefix(TAG_REF_PREFIX),
+    ) {
+        (Some(name), _) => Some((true, name.replace('/', "-"))),
+        (_, Some(name)) => Some((false, name.replace('/', "-"))),
+        _ => None,
+    }
+}
+
+// path -> (mode, blob hash)
+type Files = BTreeMap<PathBuf, (FileType, String)>;
+
+struct Person {
+    name: String,
+    email: String,
+    secs: u64,
+}
+
+// "<name> <<email>> <secs> <timezone>", names and emails lose their spaces
+fn parse_person(text: &str) -> anyhow::Result<Person> {
+    let bad_person = || anyhow!("Bad author '{}'", text);
+    let (name, rest) = text.split_once('<').ok_or_else(bad_person)?;
+    let (email, rest) = rest.split_once('>').ok_or_else(bad_person)?;
+    let secs = rest
+        .split_whitespace()
+        .next()
+        .and_then(|secs| secs.parse().ok())
+        .ok_or_else(bad_person)?;
+    let email = without_spaces(email);
+    let name = match without_spaces(name) {
+        name if name.is_empty() => email.clone(),
+        name => name,
+    };
+    Ok(Person { name, email, secs })
+}
+
+// Puts a file as git does: a file on the way to it and the files under it are removed
+fn put_file(files: &mut Files, path: PathBuf, mode: FileType, sha: String) {
+    files.retain(|file, _| !file.starts_with(&path) && !path.starts_with(file));
+    files.insert(path, (mode, sha));
+}
+
+// Mode of a file in an M line, the short forms are accepted too
+fn file_mode(mode: &str) -> FileType {
+    match mode {
+        "100755" | "755" => FileType::ExecutableFile,
+        "120000" => FileType::SymbolicLink,
+        _ => FileType::RegularFile,
+    }
+}
+
+struct Importer {
+    marks: HashMap<String, Option<String>>, // ":<n>" -> blob or commit, None for a skipped commit
+    refs: BTreeMap<String, Option<String>>, // refs the stream changed -> commit or tag object
+    imported: usize,
+    warned: BTreeSet<String>,
+    merges_flattened: bool,
+}
+
+impl Importer {
+    fn warn(&mut self, subject: &str, why: &str) {
+        if self.warned.insert(subject.to_string()) {
+            eprintln!("Skipping '{}': {}", subject, why);
+        }
+    }
+
+    fn set_mark(&mut self, mark: Option<String>, sha: Option<String>) {
+        if let Some(mark) = mark {
+            self.marks.insert(mark, sha);
+        }
+    }
+
+    /// Commit a mark, a ref or a revision names, None for a mark of a skipped root commit
+    fn resolve_commit(&self, name: &str) -> anyhow::Result<Option<String>> {
+        if name.starts_with(':') {
+            return self
+                .marks
+                .get(name)
+                .cloned()
+                .ok_or(anyhow!("Unknown mark {}", name));
+        }
+        let sha = match self.refs.get(name) {
+            Some(sha) => sha.clone(),
+            None => match local_name(name) {
+                Some((true, branch)) => refs::branch_commit(&branch)?,
+                Some((false, tag)) => refs::tag_object(&tag)?,
+                None => Some(refs::resolve_revision(name)?),
+            },
+        };
+        sha.map(|sha| refs::peel(&sha)).transpose()
+    }
+
+    fn blob_of(&self, data_ref: &str) -> anyhow::Result<String> {
+        if data_ref.starts_with(':') {
+            return self
+                .marks
+                .get(data_ref)
+                .cloned()
+                .flatten()
+                .ok_or(anyhow!("Unknown mark {}", data_ref));
+        }
+        if object_type(data_ref).ok().as_deref() != Some("blob") {
+            return Err(anyhow!("{} is not a stored blob", data_ref));
+        }
+        Ok(data_ref.to_string())
+    }
+
+    fn blob(&mut self, stream: &mut Stream<impl BufRead>) -> anyhow::Result<()> {
+        let mark = stream.take("mark ")?;
+        stream.take("original-oid ")?;
+        let mut blob = Blob::new();
+        blob.set_data(&stream.read_data()?);
+        let sha = blob.dump_to_file()?;
+        self.set_mark(mark, Some(sha));
+        Ok(())
+    }
+
+    // M, D, R, C and deleteall lines of a commit
+    fn change_files(
+        &mut self,
+        stream: &mut Stream<impl BufRead>,
+        files: &mut Files,
+    ) -> anyhow::Result<()> {
+        loop {
+            if stream.take("deleteall")?.is_some() {
+                files.clear();
+            } else if let Some(change) = stream.take("M ")? {
+                let mut parts = change.splitn(3, ' ');
+                let (Some(mode), Some(data_ref), Some(path)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(stream.error(&format!("bad change 'M {}'", change)));
+                };
+                let (path, _) = parse_path(path, false)?;
+                let blob_sha = if data_ref == INLINE_DATA {
+                    let mut blob = Blob::new();
+                    blob.set_data(&stream.read_data()?);
+                    blob.dump_to_file()?
+                } else {
+                    self.blob_of(data_ref)?
+                };
+                match mode {
+                    SUBMODULE_MODE => self.warn(&path, "submodules are not supported"),
+                    DIRECTORY_MODE => return Err(stream.error("trees can't be put by hash")),
+                    _ if path.contains(char::is_whitespace) => {
+                        self.warn(&path, "gilltter paths can't hold spaces")
+                    }
+                    _ => put_file(files, PathBuf::from(path), file_mode(mode), blob_sha),
+                }
+            } else if let Some(path) = stream.take("D ")? {
+                let (path, _) = parse_path(&path, false)?;
+                let path = Path::new(&path);
+                files.retain(|file, _| !file.starts_with(path));
+            } else if let Some((paths, is_rename)) = match stream.take("R ")? {
+                Some(paths) => Some((paths, true)),
+                None => stream.take("C ")?.map(|paths| (paths, false)),
+            } {
+                let (from, rest) = parse_path(&paths, true)?;
+                let (to, _) = parse_path(rest, false)?;
+                if to.contains(char::is_whitespace) {
+                    self.warn(&to, "gilltter paths can't hold spaces");
+                    continue;
+                }
+                // A file or a whole directory
+                let (from, to) = (PathBuf::from(from), PathBuf::from(to));
+                let copies: Vec<(PathBuf, (FileType, String))> = files
+                    .iter()
+                    .filter_map(|(file, entry)| {
+                        let inner = file.strip_prefix(&from).ok()?;
+                        let target = match inner.as_os_str().is_empty() {
+                            true => to.clone(),
+                            false => to.join(inner),
+                        };
+                        Some((target, entry.clone()))
+                    })
+                    .collect();
+                if is_rename {
+                    files.retain(|file, _| !file.starts_with(&from));
+                }
+                files.retain(|file, _| !file.starts_with(&to) && !to.starts_with(file));
+                files.extend(copies);
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn commit(&mut self, stream: &mut Stream<impl BufRead>, ref_name: &str) -> anyhow::Result<()> {
+        let mark = stream.take("mark ")?;
+        stream.take("original-oid ")?;
+        let author = stream.take("author ")?;
+        let committer = stream
+            .take("committer ")?
+            .ok_or_else(|| stream.error("expected committer"))?;
+        stream.take("encoding ")?;
+        let data = stream.read_data()?;
+        let from = stream.take("from ")?;
+        while stream.take("merge ")?.is_some() {
+            if !self.merges_flattened {
+                eprintln!("Merges keep their first parent only");
+                self.merges_flattened = true;
+            }
+        }
+
+        // Without `from` the commit continues its branch
+        let parent = match from {
+            Some(from) => self.resolve_commit(&from)?,
+            None => match self.refs.get(ref_name) {
+                Some(tip) => tip.clone(),
+                None => self.resolve_commit(ref_name).ok().flatten(),
+            },
+        };
+        let mut files = match &parent {
+            Some(parent) => commit::read_commit_entries(parent)?,
+            None => BTreeMap::new(),
+        };
+        self.change_files(stream, &mut files)?;
+
+        // An empty snapshot can't be stored, such a commit becomes its parent
+        let sha = if files.is_empty() {
+            parent
+        } else {
+            let person = parse_person(author.as_deref().unwrap_or(&committer))?;
+            let message = String::from_utf8_lossy(&data);
+            let mut commit = Commit::new();
+            commit
+                .set_tree_sha(write_tree::build_tree(&Index::from_entries(&files))?)
+                .set_parent_commit_sha(parent)
+                .set_username(person.name)
+                .set_email(person.email)
+                .set_message(message.strip_suffix('\n').unwrap_or(&message))
+                .set_secs_since_epoch(Some(person.secs));
+            self.imported += 1;
+            Some(commit.dump_to_file()?)
+        };
+        self.set_mark(mark, sha.clone());
+        self.refs.insert(ref_name.to_string(), sha);
+        Ok(())
+    }
+
+    fn reset(&mut self, stream: &mut Stream<impl BufRead>, ref_name: &str) -> anyhow::Result<()> {
+        let sha = match stream.take("from ")? {
+            Some(from) => self.resolve_commit(&from)?,
+            None => None,
+        };
+        self.refs.insert(ref_name.to_string(), sha);
+        Ok(())
+    }
+
+    fn tag(&mut self, stream: &mut Stream<impl BufRead>, name: &str) -> anyhow::Result<()> {
+        let mark = stream.take("mark ")?;
+        let from = stream
+            .take("from ")?
+            .ok_or_else(|| stream.error("expected from"))?;
+        stream.take("original-oid ")?;
+        let tagger = stream.take("tagger ")?;
+        let data = stream.read_data()?;
+
+        let ref_name = format!("{}{}", TAG_REF_PREFIX, name);
+        let Some(target) = self.resolve_commit(&from)? else {
+            self.warn(&ref_name, "its history has no files");
+            return Ok(());
+        };
+        // A tag without a tagger stays lightweight
+        let sha = match tagger {
+            Some(tagger) => {
+                let person = parse_person(&tagger)?;
+                let message = String::from_utf8_lossy(&data);
+                let mut tag = Tag::new();
+                tag.set_target(&target, object_type(&target)?)
+                    .set_name(name.replace('/', "-"))
+                    .set_username(person.name)
+                    .set_email(person.email)
+                    .set_message(message.strip_suffix('\n').unwrap_or(&message))
+                    .set_secs_since_epoch(Some(person.secs));
+                tag.dump_to_file()?
+            }
+            None => target,
+        };
+        self.set_mark(mark, Some(sha.clone()));
+        self.refs.insert(ref_name, Some(sha));
+        Ok(())
+    }
+}
+
+/// Writes the objects of a fast-import stream read from stdin and points
+/// the branches and tags it names at them
+pub fn fast_import() -> anyhow::Result<()> {
+    let mut stream = Stream {
+        input: std::io::stdin().lock(),
+        peeked: None,
+        line_number: 0,
+    };
+    let mut importer = Importer {
+        marks: HashMap::new(),
+        refs: BTreeMap::new(),
+        imported: 0,
+        warned: BTreeSet::new(),
+        merges_flattened: false,
+    };
+
+    while let Some(line) = stream.next_line()? {
+        let (command, argument) = line.split_once(' ').unwrap_or((&line, ""));
+        match command {
+            "" => {}
+            "blob" => importer.blob(&mut stream)?,
+            "commit" => importer.commit(&mut stream, argument)?,
+            "reset" => importer.reset(&mut stream, argument)?,
+            "tag" => importer.tag(&mut stream, argument)?,
+            "progress" => println!("{}", line),
+            "feature" | "option" | "checkpoint" => {}
+            "done" => break,
+            _ => return Err(stream.error(&format!("unknown command '{}'", line))),
+        }
+    }
+
+    let mut imported = BTreeMap::new();
+    for (ref_name, sha) in importer.refs.clone() {
+        let Some(sha) = sha else {
+            continue;
+        };
+        match local_name(&ref_name) {
+            Some((is_branch, name)) if refs::check_ref_name(&name).is_ok() => {
+                imported.insert((is_branch, name), sha);
+            }
+            _ => importer.warn(&ref_name, "not a gilltter branch or tag"),
+        }
+    }
+    println!("Imported {} commits", importer.imported);
+
+    // A fresh repository takes main, or the first branch there is
+    let fresh_branch = imported
+        .keys()
+        .filter(|(is_branch, _)| *is_branch)
+        .map(|(_, name)| name.clone())
+        .find(|name| name == DEFAULT_BRANCH)
+        .or_else(|| {
+            imported
+                .keys()
+                .find(|(is_branch, _)| *is_branch)
+                .map(|(_, name)| name.clone())
+        });
+    import_git::update_refs(&imported, fresh_branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(text: &str) -> Stream<&[u8]> {
+        Stream {
+            input: text.as_bytes(),
+            peeked: None,
+            line_number: 0,
+        }
+    }
+
+    fn importer() -> Importer {
+        Importer {
+            marks: HashMap::from([
+                (":1".to_string(), Some("1".repeat(40))),
+                (":2".to_string(), Some("2".repeat(40))),
+            ]),
+            refs: BTreeMap::new(),
+            imported: 0,
+            warned: BTreeSet::new(),
+            merges_flattened: false,
+        }
+    }
+
+    fn paths(files: &Files) -> Vec<String> {
+        files
+            .keys()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn quoted_paths_are_unescaped() {
+        assert_eq!(
+            parse_path("a/b c", false).unwrap(),
+            ("a/b c".to_string(), "")
+        );
+        assert_eq!(parse_path("a b", true).unwrap(), ("a".to_string(), "b"));
+        assert_eq!(
+            parse_path(r#""a \"b\"\n" rest"#, true).unwrap(),
+            ("a \"b\"\n".to_string(), "rest")
+        );
+        // Octal escapes are bytes of UTF-8
+        assert_eq!(
+            parse_path(r#""\303\251t\303\251""#, false).unwrap().0,
+            "été"
+        );
+        assert!(parse_path(r#""unterminated"#, false).is_err());
+        for path in [
+            "../escaped",
+            "/abs",
+            "a//b",
+            "a/./b",
+            "a/",
+            "\"a/..\"",
+            ".gilltter/x",
+        ] {
+            assert!(parse_path(path, false).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn people_lose_spaces() {
+        let person = parse_person("Jane Doe <jane@example.com> 1700000000 +0100").unwrap();
+        assert_eq!(person.name, "Jane_Doe");
+        assert_eq!(person.email, "jane@example.com");
+        assert_eq!(person.secs, 1700000000);
+        assert_eq!(parse_person("<a@b> 5 +0000").unwrap().name, "a@b");
+        assert!(parse_person("Jane <jane> never").is_err());
+    }
+
+    #[test]
+    fn file_changes_apply_in_order() {
+        let mut importer = importer();
+        let mut files = BTreeMap::new();
+        let mut changes = stream(
+            "M 100644 :1 a\n\
+             M 100644 :2 a/b/c\n\
+             M 100755 :1 d/e\n\
+             M 100644 :1 d/f\n\
+             R d g\n\
+             C g/e \"h i\"\n\
+             C g/e h\n\
+             M 100644 :2 h\n\
+             D g/f\n\
+             \n",
+        );
+        importer.change_files(&mut changes, &mut files).unwrap();
+        // A file in the way of a path goes, a path with a space is skipped
+        assert_eq!(paths(&files), ["a/b/c", "g/e", "h"]);
+        assert_eq!(files[Path::new("h")].1, "2".repeat(40));
+        assert_eq!(files[Path::new("g/e")].0, FileType::ExecutableFile);
+
+        let mut changes = stream("M 100644 :1 a/b\nM 120000 :1 a\n");
+        importer.change_files(&mut changes, &mut files).unwrap();
+        assert_eq!(paths(&files), ["a", "g/e", "h"]);
+        assert_eq!(files[Path::new("a")].0, FileType::SymbolicLink);
+
+        let mut changes = stream("deleteall\nM 100644 :2 z\n");
+        importer.change_files(&mut changes, &mut files).unwrap();
+        assert_eq!(paths(&files), ["z"]);
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    base::{GILLTER_BRANCHES_DIR, GILLTTER_TAGS_DIR},
+    refs,
+    remote::{self, CONFIG_REMOTE_FIELD, DEFAULT_REMOTE, FetchFilter},
+};
+
+/// Remote given on the command line, the one the current branch is pulled from, or origin
+pub fn default_remote(remote: Option<&str>) -> anyhow::Result<String> {
+    if let Some(remote) = remote {
+        return Ok(remote.to_string());
+    }
+    if let Some(branch) = refs::current_branch()?
+        && let Some(remote) = remote::branch_setting(&branch, CONFIG_REMOTE_FIELD)?
+    {
+        return Ok(remote);
+    }
+    Ok(DEFAULT_REMOTE.to_string())
+}
+
+/// Fetches the branches and tags of the remote: branches go to remotes/<remote>/<branch>,
+/// tags which are not here yet are created. With `depth` only that many commits of every
+/// branch are taken. Returns the refs of the remote
+pub fn fetch_remote(name: &str, depth: Option<usize>) -> anyhow::Result<BTreeMap<String, String>> {
+    let url = remote::remote_url(name)?;
+    let mut transport = remote::open(&url)?;
+    let remote_refs = transport.list_refs()?;
+    // A detached HEAD is wanted too, it may be the only thing the remote has
+    let wants: Vec<String> = remote_refs
+        .values()
+        .filter(|sha| !sha.is_empty() && !sha.starts_with(refs::HEAD_REF_PREFIX))
+        .cloned()
+        .collect();
+    let filter = FetchFilter {
+        depth,
+        ..FetchFilter::of_remote(name)?
+    };
+    let fetched = transport.fetch(&wants, &filter)?;
+    remote::add_shallow_commits(&fetched.shallow)?;
+    if fetched.objects > 0 {
+        println!("Received {} objects from {}", fetched.objects, url);
+    }
+
+    for (ref_name, sha) in &remote_refs {
+        if let Some(branch) = ref_name
+            .strip_prefix(GILLTER_BRANCHES_DIR)
+            .and_then(|name| name.strip_prefix('/'))
+        {
+            let old = refs::remote_branch_commit(name, branch)?;
+            if old.as_ref() == Some(sha) {
+                continue;
+            }
+            refs::update_remote_branch(name, branch, sha)?;
+            match old {
+                Some(old) => println!(
+                    "   {}..{}  {} -> {}/{}",
+                    refs::short_hash(&old),
+                    refs::short_hash(sha),
+                    branch,
+                    name,
+                    branch
+                ),
+                None => println!(" * [new branch]  {} -> {}/{}", branch, name, branch),
+            }
+        } else if let Some(tag) = ref_name
+            .strip_prefix(GILLTTER_TAGS_DIR)
+            .and_then(|name| name.strip_prefix('/'))
+            && refs::tag_object(tag)?.is_none()
+        {
+            refs::update_tag(tag, sha)?;
+            println!(" * [new tag]     {}", tag);
+        }
+    }
+    Ok(remote_refs)
+}
+
+pub fn fetch(remote: Option<&str>) -> anyhow::Result<()> {
+    fetch_remote(&default_remote(remote)?, None)?;
+    Ok(())
+}
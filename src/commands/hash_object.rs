@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+use crate::{base, utils};
+
+/// Prints the blob hash of every file, storing the blobs if `write` is set
+pub fn hash_object(paths: &[PathBuf], write: bool) -> anyhow::Result<()> {
+    for path in paths {
+        let sha = if write {
+            base::gilltter_add(path)
+        } else {
+            utils::get_file_contents_as_blob(path).map(|blob| utils::generate_hash(&blob))
+        }
+        .map_err(|why| anyhow!("Could not hash '{}': {}", path.to_string_lossy(), why))?;
+        println!("{}", sha);
+    }
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use std::{net::TcpListener, path::Path};
+
+use anyhow::anyhow;
+
+use crate::{commands::serve, remote::http};
+
+/// Serves the repository over HTTP until killed, remotes reach it with an http:// url
+pub fn http_serve(directory: Option<&Path>, bind: &str) -> anyhow::Result<()> {
+    serve::enter_repository(directory)?;
+    let listener =
+        TcpListener::bind(bind).map_err(|why| anyhow!("Could not listen on {}: {}", bind, why))?;
+    println!("Serving on http://{}", listener.local_addr()?);
+    http::serve(listener)
+}
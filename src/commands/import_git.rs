@@ -0,0 +1,268 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    base::{GILLTER_BRANCHES_DIR, GILLTTER_GIT_MAP_FILE, GILLTTER_PATH},
+    commands::reset::{self, ResetMode},
+    git::{self, GitRepository, ObjectKind},
+    objects::{
+        ObjectDump,
+        blob::Blob,
+        commit::Commit,
+        tree::{FileType, Tree, TreeObject},
+    },
+    refs,
+};
+
+fn map_path() -> std::path::PathBuf {
+    Path::new(GILLTTER_PATH).join(GILLTTER_GIT_MAP_FILE)
+}
+
+// git commit -> gilltter commit, of the imports before
+fn load_map() -> anyhow::Result<BTreeMap<String, String>> {
+    let Ok(text) = fs::read_to_string(map_path()) else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(git_sha, sha)| (git_sha.to_string(), sha.to_string()))
+        .collect())
+}
+
+fn save_map(map: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    let text: String = map
+        .iter()
+        .map(|(git_sha, sha)| format!("{} {}\n", git_sha, sha))
+        .collect();
+    fs::write(map_path(), text)?;
+    Ok(())
+}
+
+// Names and emails can't hold spaces in gilltter commits
+pub fn without_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+struct Importer {
+    git: GitRepository,
+    commits: BTreeMap<String, String>,
+    trees: HashMap<String, Option<String>>, // None for a tree with nothing gilltter can store
+    converted: usize,
+    warned: BTreeSet<String>,
+}
+
+impl Importer {
+    fn warn(&mut self, path: &str, why: &str) {
+        if self.warned.insert(path.to_string()) {
+            eprintln!("Skipping '{}': {}", path, why);
+        }
+    }
+
+    fn convert_tree(&mut self, sha: &str, dir: &str) -> anyhow::Result<Option<String>> {
+        if let Some(converted) = self.trees.get(sha) {
+            return Ok(converted.clone());
+        }
+        let mut tree = Tree::new();
+        for entry in git::parse_tree(&self.git.read_kind(sha, ObjectKind::Tree)?)? {
+            let path = format!("{}{}", dir, entry.name);
+            if entry.name.contains(char::is_whitespace) {
+                self.warn(&path, "gilltter paths can't hold spaces");
+                continue;
+            }
+            match entry.mode.as_str() {
+                git::DIRECTORY_MODE => {
+                    if let Some(subtree_sha) =
+                        self.convert_tree(&entry.sha, &format!("{}/", path))?
+                    {
+                        let mut subtree = Tree::new();
+                        subtree.set_hash(&subtree_sha)?;
+                        tree.add_object(&entry.name, TreeObject::Tree(subtree));
+                    }
+                }
+                git::SUBMODULE_MODE => self.warn(&path, "submodules are not supported"),
+                _ => {
+                    let mut blob = Blob::new();
+                    blob.set_data(&self.git.read_kind(&entry.sha, ObjectKind::Blob)?);
+                    let mode = FileType::from_bytes(entry.mode.as_bytes())
+                        .unwrap_or(FileType::RegularFile);
+                    tree.add_object(&entry.name, TreeObject::Blob(mode, blob.dump_to_file()?));
+                }
+            }
+        }
+        let converted = if tree.objects.is_empty() {
+            None
+        } else {
+            Some(tree.dump_to_file()?)
+        };
+        self.trees.insert(sha.to_string(), converted.clone());
+        Ok(converted)
+    }
+
+    /// Converts the commit and its first-parent history which is not converted yet.
+    /// None if the history has nothing gilltter can store
+    fn convert_history(&mut self, tip: &str) -> anyhow::Result<Option<String>> {
+        // Unconverted commits, newest first
+        let mut chain = Vec::new();
+        let mut next = Some(tip.to_string());
+        while let Some(sha) = next.take() {
+            if self.commits.contains_key(&sha) {
+                next = Some(sha);
+                break;
+            }
+            let commit = git::parse_commit(&self.git.read_kind(&sha, ObjectKind::Commit)?)?;
+            next = commit.parents.first().cloned();
+            chain.push((sha, commit));
+        }
+
+        let mut parent = next.and_then(|sha| self.commits.get(&sha).cloned());
+        for (git_sha, git_commit) in chain.into_iter().rev() {
+            // Merges keep their first parent only. An empty snapshot can't be stored,
+            // such a commit becomes its parent
+            if let Some(tree_sha) = self.convert_tree(&git_commit.tree, "")? {
+                let message = git_commit
+                    .message
+                    .strip_suffix('\n')
+                    .unwrap_or(&git_commit.message);
+                let mut commit = Commit::new();
+                commit
+                    .set_tree_sha(tree_sha)
+                    .set_parent_commit_sha(parent.clone())
+                    .set_username(without_spaces(&git_commit.author_name))
+                    .set_email(without_spaces(&git_commit.author_email))
+                    .set_message(message)
+                    .set_secs_since_epoch(Some(git_commit.author_time));
+                parent = Some(commit.dump_to_file()?);
+                self.converted += 1;
+            }
+            if let Some(sha) = &parent {
+                self.commits.insert(git_sha, sha.clone());
+            }
+        }
+        Ok(parent)
+    }
+}
+
+/// Imports branches and tags of a git repository with their history. Hashes of converted
+/// commits are remembered, so importing again only converts what is new
+pub fn import_git(path: &Path) -> anyhow::Result<()> {
+    let git = GitRepository::open(path)?;
+    let mut importer = Importer {
+        git,
+        commits: load_map()?,
+        trees: HashMap::new(),
+        converted: 0,
+        warned: BTreeSet::new(),
+    };
+
+    // (branch or tag, name) -> commit
+    let mut imported = BTreeMap::new();
+    // (branch or tag, name) -> git ref it was taken from, as 'a/b' and 'a-b' get one name
+    let mut sources: BTreeMap<(bool, String), String> = BTreeMap::new();
+    for (ref_name, sha) in importer.git.refs()? {
+        let (is_branch, name) = match (
+            ref_name.strip_prefix(git::BRANCH_REF_PREFIX),
+            ref_name.strip_prefix(git::TAG_REF_PREFIX),
+        ) {
+            (Some(name), _) => (true, name.replace('/', "-")),
+            (_, Some(name)) => (false, name.replace('/', "-")),
+            _ => continue,
+        };
+        if refs::check_ref_name(&name).is_err() {
+            importer.warn(&ref_name, "not a valid gilltter name");
+            continue;
+        }
+        if let Some(taken) = sources.get(&(is_branch, name.clone())) {
+            let why = format!("'{}' is imported as '{}' already", taken, name);
+            importer.warn(&ref_name, &why);
+            continue;
+        }
+        sources.insert((is_branch, name.clone()), ref_name.clone());
+        let Some(commit) = importer.git.peel(&sha)? else {
+            importer.warn(&ref_name, "it does not point to a commit");
+            continue;
+        };
+        match importer.convert_history(&commit)? {
+            Some(converted) => {
+                imported.insert((is_branch, name), converted);
+            }
+            None => importer.warn(&ref_name, "its history has no files"),
+        }
+    }
+    save_map(&importer.commits)?;
+    println!("Converted {} commits", importer.converted);
+
+    // A fresh repository takes the branch git's HEAD is on
+    let head_branch = importer
+        .git
+        .head()?
+        .strip_prefix(git::SYMBOLIC_REF_PREFIX)
+        .and_then(|target| target.strip_prefix(git::BRANCH_REF_PREFIX))
+        .map(|branch| branch.replace('/', "-"));
+    update_refs(&imported, head_branch)
+}
+
+/// Points branches and tags, keyed by (is branch, name), at imported objects and prints
+/// what changed. If the current branch moves, the files follow it; a repository without
+/// commits yet is attached to `fresh_branch`
+pub fn update_refs(
+    imported: &BTreeMap<(bool, String), String>,
+    fresh_branch: Option<String>,
+) -> anyhow::Result<()> {
+    let head = refs::read_head()?;
+    let current = refs::current_branch()?;
+    let moves_current = imported.iter().any(|((is_branch, name), sha)| {
+        *is_branch
+            && current.as_ref() == Some(name)
+            && refs::branch_commit(name).ok().flatten().as_ref() != Some(sha)
+    });
+    if moves_current {
+        reset::ensure_clean()?;
+    }
+
+    for ((is_branch, name), sha) in imported {
+        let old = if *is_branch {
+            refs::branch_commit(name)?
+        } else {
+            refs::tag_object(name)?
+        };
+        if old.as_ref() == Some(sha) {
+            continue;
+        }
+        if *is_branch {
+            refs::update_branch(name, sha)?;
+        } else {
+            refs::update_tag(name, sha)?;
+        }
+        match old {
+            Some(old) => println!(
+                "   {}..{}  {}",
+                refs::short_hash(&old),
+                refs::short_hash(sha),
+                name
+            ),
+            None if *is_branch => println!(" * [new branch]  {}", name),
+            None => println!(" * [new tag]     {}", name),
+        }
+    }
+
+    if head.is_empty()
+        && let Some(branch) = fresh_branch
+        && imported.contains_key(&(true, branch.clone()))
+    {
+        refs::write_head(&format!(
+            "{}{}/{}",
+            refs::HEAD_REF_PREFIX,
+            GILLTER_BRANCHES_DIR,
+            branch
+        ))?;
+        return reset::reset(None, ResetMode::Hard, &[]);
+    }
+    if moves_current {
+        reset::reset(None, ResetMode::Hard, &[])?;
+    }
+    Ok(())
+}
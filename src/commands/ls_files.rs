@@ -0,0 +1,168 @@
+use std::path::Path;
+
+use crate::{
+    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    commands::status::traverse_dirs,
+    index::index::{Index, IndexEntry},
+    objects::ObjectPump,
+    worktree,
+};
+
+/// Which files ls-files prints, with no filter set every index entry is printed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LsFilesFilter {
+    pub modified: bool, // -m, content differs from the index or the file is gone
+    pub deleted: bool,  // -d
+    pub others: bool,   // -o, untracked and not ignored
+}
+
+/// Extra columns printed before the path of index entries
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LsFilesColumns {
+    pub stage: bool, // -s, "<mode> <hash>"
+    pub stat: bool,  // --stat, "<ctime> <mtime> <size>"
+}
+
+impl LsFilesFilter {
+    fn any(&self) -> bool {
+        self.modified || self.deleted || self.others
+    }
+
+    fn keeps(&self, entry: &IndexEntry) -> bool {
+        if !self.any() {
+            return true;
+        }
+        match worktree::file_hash(&entry.filename) {
+            None => self.deleted || self.modified,
+            Some(sha) => self.modified && sha != entry.sha1_hash,
+        }
+    }
+}
+
+fn entry_line(entry: &IndexEntry, columns: LsFilesColumns) -> String {
+    let mut line = String::new();
+    if columns.stage {
+        line += &format!(
+            "{} {} ",
+            String::from_utf8_lossy(&entry.index_type.to_bytes()),
+            entry.sha1_hash
+        );
+    }
+    if columns.stat {
+        line += &format!("{} {} {} ", entry.ctime, entry.mtime, entry.file_size);
+    }
+    line + &entry.filename.to_string_lossy()
+}
+
+// What ls-files prints, every line ends with the terminator
+fn output(
+    filter: LsFilesFilter,
+    columns: LsFilesColumns,
+    terminator: char,
+) -> anyhow::Result<String> {
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    let mut out = String::new();
+
+    if !filter.any() || filter.modified || filter.deleted {
+        for entry in index.indices.iter().filter(|entry| filter.keeps(entry)) {
+            out += &format!("{}{}", entry_line(entry, columns), terminator);
+        }
+    }
+
+    if filter.others {
+        let mut others: Vec<IndexEntry> = traverse_dirs(std::env::current_dir()?)?
+            .into_iter()
+            .filter(|file| {
+                !index
+                    .indices
+                    .iter()
+                    .any(|entry| entry.filename == file.filename)
+            })
+            .collect();
+        others.sort_by(|a, b| a.filename.cmp(&b.filename));
+        for file in others {
+            out += &format!("{}{}", file.filename.to_string_lossy(), terminator);
+        }
+    }
+    Ok(out)
+}
+
+/// Prints the index entries, separated by NUL instead of newline if asked
+pub fn ls_files(
+    filter: LsFilesFilter,
+    columns: LsFilesColumns,
+    null_terminated: bool,
+) -> anyhow::Result<()> {
+    let terminator = if null_terminated { '\0' } else { '\n' };
+    print!("{}", output(filter, columns, terminator)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    fn filter(modified: bool, deleted: bool, others: bool) -> LsFilesFilter {
+        LsFilesFilter {
+            modified,
+            deleted,
+            others,
+        }
+    }
+
+    #[test]
+    fn filters_pick_the_files() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        repository.add("b.txt", "b\n");
+        repository.add("dir/c.txt", "c\n");
+        repository.commit("first");
+        fs::write("a.txt", "changed\n").unwrap();
+        fs::remove_file("b.txt").unwrap();
+        fs::write("new.txt", "new\n").unwrap();
+
+        let list = |filter| output(filter, LsFilesColumns::default(), '\n').unwrap();
+        assert_eq!(
+            list(filter(false, false, false)),
+            "a.txt\nb.txt\ndir/c.txt\n"
+        );
+        assert_eq!(list(filter(true, false, false)), "a.txt\nb.txt\n");
+        assert_eq!(list(filter(false, true, false)), "b.txt\n");
+        assert_eq!(list(filter(false, false, true)), "new.txt\n");
+        assert_eq!(list(filter(false, true, true)), "b.txt\nnew.txt\n");
+        assert_eq!(
+            output(filter(false, false, false), LsFilesColumns::default(), '\0').unwrap(),
+            "a.txt\0b.txt\0dir/c.txt\0"
+        );
+    }
+
+    #[test]
+    fn columns_come_before_the_path() {
+        let repository = TempRepository::new();
+        repository.add("dir/c.txt", "c\n");
+        let sha = worktree::file_hash(Path::new("dir/c.txt")).unwrap();
+
+        let stage = LsFilesColumns {
+            stage: true,
+            stat: false,
+        };
+        assert_eq!(
+            output(LsFilesFilter::default(), stage, '\n').unwrap(),
+            format!("100644 {} dir/c.txt\n", sha)
+        );
+
+        let stat = LsFilesColumns {
+            stage: true,
+            stat: true,
+        };
+        let line = output(LsFilesFilter::default(), stat, '\0').unwrap();
+        let fields: Vec<&str> = line.trim_end_matches('\0').split(' ').collect();
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[..2], ["100644", sha.as_str()]);
+        assert!(fields[2].parse::<u64>().is_ok() && fields[3].parse::<u64>().is_ok());
+        assert_eq!(fields[4..], ["2", "dir/c.txt"]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    objects::{
+        ObjectPump,
+        commit::read_commit,
+        object_type,
+        tree::{FileType, Tree, TreeObject},
+    },
+    refs,
+};
+
+struct Listing<'a> {
+    recursive: bool,
+    filter: Option<&'a str>,
+    terminator: char,
+}
+
+impl Listing<'_> {
+    // Whether the entry itself is listed: everything without a path, otherwise the path
+    // itself or, when it ends with '/', whatever is inside it
+    fn matches(&self, full_path: &str) -> bool {
+        match self.filter {
+            None => true,
+            Some(filter) if filter.ends_with('/') => full_path.starts_with(filter),
+            Some(filter) => full_path == filter || full_path.starts_with(&format!("{}/", filter)),
+        }
+    }
+
+    // Whether the tree has to be opened to reach the path
+    fn leads_to_filter(&self, full_path: &str) -> bool {
+        self.filter
+            .is_some_and(|filter| filter.starts_with(&format!("{}/", full_path)))
+    }
+
+    fn print(&self, out: &mut String, mode: FileType, sha: &str, path: &str) {
+        let obj_type = match mode {
+            FileType::Directory => "tree",
+            _ => "blob",
+        };
+        *out += &format!(
+            "{} {} {}\t{}{}",
+            String::from_utf8_lossy(&mode.to_bytes()),
+            obj_type,
+            sha,
+            path,
+            self.terminator
+        );
+    }
+
+    fn walk(&self, out: &mut String, tree_sha: &str, prefix: &str) -> anyhow::Result<()> {
+        let tree = Tree::from_file(&object_path(tree_sha))
+            .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
+        for (name, object) in &tree.objects {
+            let full_path = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            match object {
+                TreeObject::Blob(mode, sha) => {
+                    if self.matches(&full_path) {
+                        self.print(out, *mode, sha, &full_path);
+                    }
+                }
+                TreeObject::Tree(subtree) => {
+                    let sha = subtree.get_hash()?;
+                    if self.leads_to_filter(&full_path) {
+                        self.walk(out, &sha, &full_path)?;
+                    } else if self.matches(&full_path) {
+                        if self.recursive {
+                            self.walk(out, &sha, &full_path)?;
+                        } else {
+                            self.print(out, FileType::Directory, &sha, &full_path);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// What ls-tree prints, every line ends with the terminator
+fn output(
+    revision: &str,
+    path: Option<&Path>,
+    recursive: bool,
+    terminator: char,
+) -> anyhow::Result<String> {
+    let mut sha = refs::peel(&refs::resolve_object(revision)?)?;
+    if object_type(&sha)? == "commit" {
+        sha = read_commit(&sha)?
+            .get_tree_sha()
+            .ok_or(anyhow!("Commit {} has no tree", sha))?;
+    }
+    if object_type(&sha)? != "tree" {
+        return Err(anyhow!("'{}' is not a tree", revision));
+    }
+
+    let filter = path.map(|path| path.to_string_lossy().to_string());
+    let listing = Listing {
+        recursive,
+        filter: filter.as_deref().filter(|filter| !filter.is_empty()),
+        terminator,
+    };
+    let mut out = String::new();
+    listing.walk(&mut out, &sha, "")?;
+    Ok(out)
+}
+
+/// Lists a tree like "<mode> <type> <hash>\t<path>". The revision may name a commit,
+/// a tag or a tree, the path narrows the listing down, "dir/" lists what is inside dir
+pub fn ls_tree(
+    revision: &str,
+    path: Option<&Path>,
+    recursive: bool,
+    null_terminated: bool,
+) -> anyhow::Result<()> {
+    let terminator = if null_terminated { '\0' } else { '\n' };
+    print!("{}", output(revision, path, recursive, terminator)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    // "<type> <path>" of every listed entry
+    fn entries(revision: &str, path: Option<&str>, recursive: bool) -> Vec<String> {
+        output(revision, path.map(Path::new), recursive, '\n')
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let (info, path) = line.split_once('\t').unwrap();
+                format!("{} {}", info.split(' ').nth(1).unwrap(), path)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn listing_follows_the_path_and_recursion() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        repository.add("dir/b.txt", "b\n");
+        repository.add("dir/sub/c.txt", "c\n");
+        repository.commit("first");
+
+        assert_eq!(entries("HEAD", None, false), ["blob a.txt", "tree dir"]);
+        assert_eq!(
+            entries("HEAD", None, true),
+            ["blob a.txt", "blob dir/b.txt", "blob dir/sub/c.txt"]
+        );
+        assert_eq!(entries("HEAD", Some("dir"), false), ["tree dir"]);
+        assert_eq!(
+            entries("HEAD", Some("dir/"), false),
+            ["blob dir/b.txt", "tree dir/sub"]
+        );
+        assert_eq!(
+            entries("HEAD", Some("dir/sub/c.txt"), false),
+            ["blob dir/sub/c.txt"]
+        );
+        assert!(entries("HEAD", Some("missing"), true).is_empty());
+
+        let a = crate::worktree::file_hash(Path::new("a.txt")).unwrap();
+        assert_eq!(
+            output("HEAD", Some(Path::new("a.txt")), false, '\0').unwrap(),
+            format!("100644 blob {}\ta.txt\0", a)
+        );
+    }
+
+    #[test]
+    fn tags_and_paths_name_trees() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        repository.add("dir/b.txt", "b\n");
+        repository.add("dir/sub/c.txt", "c\n");
+        repository.commit("first");
+        crate::commands::tag::create("light", None, None, false, false).unwrap();
+        crate::commands::tag::create("annotated", None, Some("release"), true, false).unwrap();
+
+        assert_eq!(entries("light", None, false), ["blob a.txt", "tree dir"]);
+        assert_eq!(
+            entries("annotated", None, false),
+            ["blob a.txt", "tree dir"]
+        );
+        assert_eq!(entries("HEAD:dir", None, false), ["blob b.txt", "tree sub"]);
+        assert_eq!(entries("light:dir/sub", None, true), ["blob c.txt"]);
+        assert!(output("HEAD:a.txt", None, false, '\n').is_err());
+    }
+}
@@ -1,5 +1,32 @@
 pub mod add;
+pub mod archive;
+pub mod bisect;
+pub mod blame;
+pub mod bundle;
+pub mod cat_file;
+pub mod check_ignore;
+pub mod cherry_pick;
+pub mod clone;
 pub mod commit;
+pub mod commit_tree;
+pub mod config;
+pub mod fast_export;
+pub mod fast_import;
+pub mod fetch;
+pub mod hash_object;
+pub mod http_serve;
+pub mod import_git;
+pub mod ls_files;
+pub mod ls_tree;
+pub mod pull;
+pub mod push;
+pub mod rebase;
 pub mod reset;
 pub mod revert;
+pub mod serve;
+pub mod show;
+pub mod stash;
 pub mod status;
+pub mod tag;
+pub mod update_ref;
+pub mod write_tree;
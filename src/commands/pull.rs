@@ -0,0 +1,104 @@
+use anyhow::anyhow;
+
+use crate::{
+    base::GILLTER_BRANCHES_DIR,
+    commands::{
+        fetch::{default_remote, fetch_remote},
+        rebase,
+        reset::{self, ResetMode},
+    },
+    refs,
+    remote::{self, CONFIG_MERGE_FIELD},
+};
+
+/// Fetches the remote and brings its branch (the one the current branch tracks by default)
+/// into HEAD: fast-forwards, or rebases the local commits onto it if the histories diverged
+pub fn pull(remote: Option<&str>, branch: Option<&str>) -> anyhow::Result<()> {
+    let name = default_remote(remote)?;
+    let current = refs::current_branch()?;
+    let branch = match (branch, &current) {
+        (Some(branch), _) => branch.to_string(),
+        (None, Some(current)) => {
+            remote::branch_setting(current, CONFIG_MERGE_FIELD)?.unwrap_or(current.clone())
+        }
+        (None, None) => return Err(anyhow!("HEAD is detached, name the branch to pull")),
+    };
+
+    let remote_refs = fetch_remote(&name, None)?;
+    let upstream = remote_refs
+        .get(&format!("{}/{}", GILLTER_BRANCHES_DIR, branch))
+        .ok_or(anyhow!("Remote '{}' has no branch '{}'", name, branch))?
+        .clone();
+
+    let Some(head) = refs::head_commit()? else {
+        reset::ensure_clean()?;
+        refs::update_head(&upstream)?;
+        return reset::reset(None, ResetMode::Hard, &[]);
+    };
+    if refs::first_parent_chain(&head)?.contains(&upstream) {
+        println!("Already up to date");
+        return Ok(());
+    }
+    if refs::first_parent_chain(&upstream)?.contains(&head) {
+        reset::ensure_clean()?;
+        println!(
+            "Fast-forward {}..{}",
+            refs::short_hash(&head),
+            refs::short_hash(&upstream)
+        );
+        return reset::reset(Some(&upstream), ResetMode::Hard, &[]);
+    }
+    // Commits have a single parent, so a merge commit could not contain the remote history
+    // and push would keep refusing it. Local commits are replayed on top of it instead
+    rebase::rebase(&upstream, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        commands::push::push,
+        config::{Config, ConfigScope},
+        objects::{ObjectDump, commit::read_commit},
+        remote::{CONFIG_REMOTE_SECTION, CONFIG_URL_FIELD, DEFAULT_REMOTE},
+        test_utils::TempRepository,
+    };
+
+    // The repository is its own remote, branch other is pulled into main and pushed back
+    #[test]
+    fn diverged_histories_can_be_pushed_after_pull() {
+        let repository = TempRepository::new();
+        let mut config = Config::load(&ConfigScope::Local.path().unwrap()).unwrap();
+        config.set(
+            &format!("{}.{}", CONFIG_REMOTE_SECTION, DEFAULT_REMOTE),
+            CONFIG_URL_FIELD,
+            &repository.path.to_string_lossy(),
+        );
+        config.dump_to_file().unwrap();
+
+        repository.add("a.txt", "1\n2\n3\n4\n5\n");
+        let base = repository.commit("base");
+        repository.add("a.txt", "one\n2\n3\n4\n5\n");
+        let theirs = repository.commit("theirs");
+        refs::update_branch("other", &theirs).unwrap();
+        reset::reset(Some(&base), ResetMode::Hard, &[]).unwrap();
+        repository.add("a.txt", "1\n2\n3\n4\nfive\n");
+        repository.commit("ours");
+        let push_back = || push(None, &["main:other".to_string()], false, false, false);
+        assert!(push_back().is_err());
+
+        pull(None, Some("other")).unwrap();
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n2\n3\n4\nfive\n");
+        let head = refs::head_commit().unwrap().unwrap();
+        let replayed = read_commit(&head).unwrap();
+        assert_eq!(replayed.get_message().unwrap(), "ours");
+        assert_eq!(replayed.get_parent_commit_sha(), Some(theirs));
+
+        push_back().unwrap();
+        assert_eq!(refs::branch_commit("other").unwrap(), Some(head.clone()));
+        pull(None, Some("other")).unwrap();
+        assert_eq!(refs::head_commit().unwrap(), Some(head));
+    }
+}
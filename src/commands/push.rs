@@ -0,0 +1,205 @@
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTER_BRANCHES_DIR, GILLTTER_TAGS_DIR},
+    commands::fetch::default_remote,
+    hooks, refs,
+    remote::{self, RefUpdate},
+};
+
+// "<src>[:<dst>]" -> (local revision, ref of the remote). A tag name pushes the tag,
+// anything else goes to a branch, named like the source unless <dst> is given
+fn parse_refspec(refspec: &str) -> anyhow::Result<(String, String)> {
+    let (src, dst) = match refspec.split_once(':') {
+        Some((src, dst)) => (src, Some(dst)),
+        None => (refspec, None),
+    };
+    let is_tag = refs::branch_commit(src)?.is_none() && refs::tag_object(src)?.is_some();
+    let dst = match dst {
+        Some(dst) if dst.starts_with(&format!("{}/", GILLTTER_TAGS_DIR)) => dst.to_string(),
+        Some(dst) => format!("{}/{}", GILLTER_BRANCHES_DIR, dst),
+        None if is_tag => format!("{}/{}", GILLTTER_TAGS_DIR, src),
+        None if src == "HEAD" => {
+            return Err(anyhow!("Name the branch of the remote: HEAD:<branch>"));
+        }
+        None => format!("{}/{}", GILLTER_BRANCHES_DIR, src),
+    };
+    refs::check_ref_name(dst.split_once('/').map_or("", |(_, name)| name))?;
+    let sha = if is_tag {
+        refs::tag_object(src)?.unwrap_or_default()
+    } else {
+        refs::resolve_revision(src)?
+    };
+    Ok((sha, dst))
+}
+
+/// Sends the branches (the current one if none given) and tags to the remote.
+/// Branches are only fast-forwarded and tags are not moved, unless forced.
+/// With `verify` the pre-push hook may stop the push
+pub fn push(
+    remote: Option<&str>,
+    refspecs: &[String],
+    all_tags: bool,
+    force: bool,
+    verify: bool,
+) -> anyhow::Result<()> {
+    let name = default_remote(remote)?;
+    let url = remote::remote_url(&name)?;
+
+    let mut refspecs = refspecs.to_vec();
+    if refspecs.is_empty() && !all_tags {
+        refspecs.push(refs::current_branch()?.ok_or(anyhow!(
+            "HEAD is detached, name what to push: HEAD:<branch>"
+        ))?);
+    }
+    if all_tags {
+        refspecs.extend(refs::tag_names()?);
+    }
+
+    let mut transport = remote::open(&url)?;
+    let remote_refs = transport.list_refs()?;
+    let mut updates = Vec::new();
+    let mut hook_input = String::new(); // "<src> <sha> <dst> <old>" per update for pre-push
+    for refspec in &refspecs {
+        let (sha, dst) = parse_refspec(refspec)?;
+        let old = remote_refs.get(&dst).cloned();
+        if old.as_ref() == Some(&sha) {
+            continue;
+        }
+        if let Some(old) = &old
+            && !force
+        {
+            if dst.starts_with(GILLTTER_TAGS_DIR) {
+                return Err(anyhow!("'{}' already exists on the remote", dst));
+            }
+            let is_fast_forward = refs::first_parent_chain(&sha)?.contains(old);
+            if !is_fast_forward {
+                return Err(anyhow!(
+                    "'{}' of the remote has commits which are not here, fetch and pull first",
+                    dst
+                ));
+            }
+        }
+        let src = refspec
+            .split_once(':')
+            .map_or(refspec.as_str(), |(src, _)| src);
+        hook_input += &format!(
+            "{} {} {} {}\n",
+            src,
+            sha,
+            dst,
+            old.as_deref().unwrap_or(hooks::NULL_HASH)
+        );
+        updates.push(RefUpdate {
+            name: dst,
+            old,
+            new: sha,
+        });
+    }
+    if updates.is_empty() {
+        println!("Everything up to date");
+        return Ok(());
+    }
+
+    if verify {
+        hooks::run_checked(
+            hooks::PRE_PUSH_HOOK,
+            &[&name, &url],
+            Some(hook_input.as_bytes()),
+        )?;
+    }
+    transport.push(&updates)?;
+    println!("To {}", url);
+    for update in &updates {
+        let target = update.name.split_once('/').map_or("", |(_, name)| name);
+        match &update.old {
+            Some(old) => println!(
+                "   {}..{}  {}",
+                refs::short_hash(old),
+                refs::short_hash(&update.new),
+                target
+            ),
+            None => println!(" * [new]  {}", target),
+        }
+        if let Some(branch) = update
+            .name
+            .strip_prefix(GILLTER_BRANCHES_DIR)
+            .and_then(|name| name.strip_prefix('/'))
+        {
+            refs::update_remote_branch(&name, branch, &update.new)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::tag,
+        config::{Config, ConfigScope},
+        objects::ObjectDump,
+        remote::{CONFIG_REMOTE_SECTION, CONFIG_URL_FIELD, DEFAULT_REMOTE},
+        test_utils::TempRepository,
+    };
+
+    #[test]
+    fn refspecs_name_remote_refs() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let commit = repository.commit("first");
+        tag::create("v1", None, None, false, false).unwrap();
+
+        let parsed = |refspec: &str| parse_refspec(refspec).unwrap();
+        assert_eq!(
+            parsed("main"),
+            (commit.clone(), "branches/main".to_string())
+        );
+        assert_eq!(
+            parsed("HEAD:other"),
+            (commit.clone(), "branches/other".to_string())
+        );
+        assert_eq!(parsed("v1"), (commit.clone(), "tags/v1".to_string()));
+        assert_eq!(parsed("main:tags/v2"), (commit, "tags/v2".to_string()));
+        assert!(parse_refspec("HEAD").is_err());
+        assert!(parse_refspec("main:bad..name").is_err());
+        assert!(parse_refspec("missing").is_err());
+    }
+
+    // The repository is its own remote, pushes go to other names
+    #[test]
+    fn only_fast_forwards_and_new_tags_are_pushed() {
+        let repository = TempRepository::new();
+        let config_path = ConfigScope::Local.path().unwrap();
+        let mut config = Config::load(&config_path).unwrap();
+        config.set(
+            &format!("{}.{}", CONFIG_REMOTE_SECTION, DEFAULT_REMOTE),
+            CONFIG_URL_FIELD,
+            &repository.path.to_string_lossy(),
+        );
+        config.dump_to_file().unwrap();
+        let push_to =
+            |refspec: &str, force: bool| push(None, &[refspec.to_string()], false, force, false);
+
+        repository.add("a.txt", "a\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "b\n");
+        let second = repository.commit("second");
+        push_to("main:other", false).unwrap();
+        assert_eq!(refs::branch_commit("other").unwrap(), Some(second.clone()));
+
+        refs::update_head(&first).unwrap();
+        assert!(push_to("main:other", false).is_err());
+        assert_eq!(refs::branch_commit("other").unwrap(), Some(second.clone()));
+        push_to("main:other", true).unwrap();
+        assert_eq!(refs::branch_commit("other").unwrap(), Some(first.clone()));
+
+        tag::create("v1", Some(&first), None, false, false).unwrap();
+        tag::create("v2", Some(&second), None, false, false).unwrap();
+        push_to("v1:tags/copy", false).unwrap();
+        assert!(push_to("v2:tags/copy", false).is_err());
+        assert_eq!(refs::tag_object("copy").unwrap(), Some(first));
+        push_to("v2:tags/copy", true).unwrap();
+        assert_eq!(refs::tag_object("copy").unwrap(), Some(second));
+    }
+}
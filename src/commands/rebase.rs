@@ -0,0 +1,615 @@
+// A rebase in progress keeps its state in .gilltter/rebase/:
+/*
+ * orig-head  commit HEAD pointed to before the rebase, for --abort
+ * onto       commit the branch is replayed onto
+ * current    step being applied, empty between steps
+ * todo       steps left, one per line
+ * conflicts  paths which had conflicts in the current step, one per line
+ * message    commit message being edited
+ */
+// Steps look like the lines of the interactive todo file:
+/*
+ * pick a1b2c3d subject of the commit
+ */
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH, GILLTTER_REBASE_DIR},
+    commands::{
+        cherry_pick::apply_changes,
+        commit::{commit_as, create_commit},
+        reset::{self, ResetMode},
+    },
+    config, hooks,
+    index::index::Index,
+    objects::{
+        ObjectPump,
+        commit::{read_commit, read_commit_entries},
+        tree::FileType,
+    },
+    refs, worktree,
+};
+
+const STATE_ORIG_HEAD_FILE: &'static str = "orig-head";
+const STATE_ONTO_FILE: &'static str = "onto";
+const STATE_CURRENT_FILE: &'static str = "current";
+const STATE_TODO_FILE: &'static str = "todo";
+const STATE_CONFLICTS_FILE: &'static str = "conflicts";
+const STATE_MESSAGE_FILE: &'static str = "message";
+const DEFAULT_EDITOR: &'static str = "vi";
+
+const TODO_HELP: &'static str = "
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's message
+# d, drop <commit> = remove commit
+#
+# Steps are applied from top to bottom. Removing a line drops the commit too,
+# removing every line aborts the rebase.
+";
+
+const MESSAGE_HELP: &'static str = "
+# Enter the commit message. Lines starting with '#' are ignored,
+# an empty message stops the rebase.
+";
+
+fn state_dir() -> PathBuf {
+    Path::new(GILLTTER_PATH).join(GILLTTER_REBASE_DIR)
+}
+
+fn first_line(message: &str) -> &str {
+    message.lines().next().unwrap_or_default()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    Pick,
+    Reword,
+    Squash,
+    Fixup,
+    Drop,
+}
+
+impl Action {
+    fn from_word(word: &str) -> Option<Self> {
+        match word {
+            "p" | "pick" => Some(Self::Pick),
+            "r" | "reword" => Some(Self::Reword),
+            "s" | "squash" => Some(Self::Squash),
+            "f" | "fixup" => Some(Self::Fixup),
+            "d" | "drop" => Some(Self::Drop),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Pick => "pick",
+            Self::Reword => "reword",
+            Self::Squash => "squash",
+            Self::Fixup => "fixup",
+            Self::Drop => "drop",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Step {
+    action: Action,
+    sha: String,
+}
+
+impl Step {
+    /// None for empty and comment lines
+    fn parse(line: &str) -> anyhow::Result<Option<Self>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let mut words = line.split_whitespace();
+        let action = words.next().unwrap_or_default();
+        let action =
+            Action::from_word(action).ok_or(anyhow!("Unknown rebase command '{}'", action))?;
+        let revision = words
+            .next()
+            .ok_or(anyhow!("No commit in rebase step '{}'", line))?;
+        let sha = refs::resolve_revision(revision)?;
+        read_commit(&sha)?; // make sure it is a commit
+        Ok(Some(Self { action, sha }))
+    }
+
+    // Full hash, so the state does not depend on prefixes staying unique
+    fn to_state_line(&self) -> String {
+        format!("{} {}", self.action.name(), self.sha)
+    }
+
+    fn to_line(&self) -> anyhow::Result<String> {
+        let message = read_commit(&self.sha)?.get_message().unwrap_or_default();
+        Ok(format!(
+            "{} {} {}",
+            self.action.name(),
+            refs::short_hash(&self.sha),
+            first_line(&message)
+        ))
+    }
+}
+
+struct State {
+    orig_head: String,
+    onto: String,
+    current: Option<Step>,
+    todo: Vec<Step>,
+    conflicts: Vec<PathBuf>,
+}
+
+impl State {
+    fn load() -> anyhow::Result<Self> {
+        let dir = state_dir();
+        if !dir.is_dir() {
+            return Err(anyhow!("There is no rebase in progress"));
+        }
+        let read = |name: &str| -> anyhow::Result<String> {
+            fs::read_to_string(dir.join(name))
+                .map_err(|why| anyhow!("Broken rebase state, could not read '{}': {}", name, why))
+        };
+        let parse_steps = |text: String| -> anyhow::Result<Vec<Step>> {
+            let mut steps = Vec::new();
+            for line in text.lines() {
+                steps.extend(Step::parse(line)?);
+            }
+            Ok(steps)
+        };
+
+        Ok(Self {
+            orig_head: read(STATE_ORIG_HEAD_FILE)?.trim().to_string(),
+            onto: read(STATE_ONTO_FILE)?.trim().to_string(),
+            current: parse_steps(read(STATE_CURRENT_FILE)?)?.into_iter().next(),
+            todo: parse_steps(read(STATE_TODO_FILE)?)?,
+            conflicts: read(STATE_CONFLICTS_FILE)?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let dir = state_dir();
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(STATE_ORIG_HEAD_FILE), &self.orig_head)?;
+        fs::write(dir.join(STATE_ONTO_FILE), &self.onto)?;
+        let current = match &self.current {
+            Some(step) => step.to_state_line(),
+            None => String::new(),
+        };
+        fs::write(dir.join(STATE_CURRENT_FILE), current)?;
+        let todo: Vec<String> = self.todo.iter().map(|step| step.to_state_line()).collect();
+        fs::write(dir.join(STATE_TODO_FILE), todo.join("\n"))?;
+        let conflicts: Vec<String> = self
+            .conflicts
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        fs::write(dir.join(STATE_CONFLICTS_FILE), conflicts.join("\n"))?;
+        Ok(())
+    }
+}
+
+/// Editor from GILLTTER_EDITOR, Core.Editor, VISUAL or EDITOR, in that order
+fn editor() -> anyhow::Result<String> {
+    if let Ok(editor) = std::env::var("GILLTTER_EDITOR") {
+        return Ok(editor);
+    }
+    if let Some(editor) =
+        config::load_layered()?.get(config::CONFIG_CORE_SECTION, config::CONFIG_EDITOR_FIELD)
+    {
+        return Ok(editor);
+    }
+    Ok(std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or(DEFAULT_EDITOR.to_string()))
+}
+
+// The editor command may have arguments of its own, so it goes through the shell
+fn edit_file(path: &Path) -> anyhow::Result<()> {
+    let editor = editor()?;
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .status()
+        .map_err(|why| anyhow!("Could not run editor '{}': {}", editor, why))?;
+    if !status.success() {
+        return Err(anyhow!("Editor '{}' failed", editor));
+    }
+    Ok(())
+}
+
+// Text without comment lines
+fn strip_comments(text: &str) -> String {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn edit_message(message: &str) -> anyhow::Result<String> {
+    let path = state_dir().join(STATE_MESSAGE_FILE);
+    fs::write(&path, format!("{}\n{}", message, MESSAGE_HELP))?;
+    edit_file(&path)?;
+    let message = strip_comments(&fs::read_to_string(&path)?);
+    if message.is_empty() {
+        return Err(anyhow!("Empty commit message"));
+    }
+    Ok(message)
+}
+
+fn head_entries() -> anyhow::Result<BTreeMap<PathBuf, (FileType, String)>> {
+    match refs::head_commit()? {
+        Some(head) => read_commit_entries(&head),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+fn print_commit(commit_hash: &str, message: &str) {
+    println!(
+        "[{}] {}",
+        refs::short_hash(commit_hash),
+        first_line(message)
+    );
+}
+
+// Records the changes of the step, which are already in the index
+fn finish_step(step: &Step, files: &BTreeMap<PathBuf, (FileType, String)>) -> anyhow::Result<()> {
+    let picked = read_commit(&step.sha)?;
+    let message = picked.get_message().unwrap_or_default();
+    match step.action {
+        Action::Drop => Ok(()),
+        Action::Pick | Action::Reword => {
+            if *files == head_entries()? {
+                println!(
+                    "Skipping {} ({}), it changes nothing",
+                    refs::short_hash(&step.sha),
+                    first_line(&message)
+                );
+                return Ok(());
+            }
+            let message = match step.action {
+                Action::Reword => edit_message(&message)?,
+                _ => message,
+            };
+            if files.is_empty() {
+                return Err(anyhow!("Nothing would be left to commit"));
+            }
+            let commit_hash =
+                commit_as(&Index::from_checkout(files), message.clone(), Some(&picked))?;
+            print_commit(&commit_hash, &message);
+            Ok(())
+        }
+        Action::Squash | Action::Fixup => {
+            // The previous commit is replaced by one with the changes of both
+            let head = refs::head_commit()?.ok_or(anyhow!(
+                "There is no previous commit to {} into",
+                step.action.name()
+            ))?;
+            let previous = read_commit(&head)?;
+            let previous_message = previous.get_message().unwrap_or_default();
+            let message = match step.action {
+                Action::Squash => edit_message(&format!("{}\n\n{}", previous_message, message))?,
+                _ => previous_message,
+            };
+            if files.is_empty() {
+                return Err(anyhow!("Nothing would be left to commit"));
+            }
+            // Stored on the parent of the previous commit, which is None for a root commit
+            let commit_hash = create_commit(
+                &Index::from_checkout(files),
+                message.clone(),
+                previous.get_parent_commit_sha(),
+                Some(&previous),
+            )?;
+            refs::update_head(&commit_hash)?;
+            print_commit(&commit_hash, &message);
+            Ok(())
+        }
+    }
+}
+
+// Applies the steps left one by one. The state is saved before every step,
+// so the rebase can be continued whatever stops it
+fn run(mut state: State) -> anyhow::Result<()> {
+    while !state.todo.is_empty() {
+        let step = state.todo.remove(0);
+        state.current = Some(step.clone());
+        state.conflicts.clear();
+        state.save()?;
+        if step.action == Action::Drop {
+            continue;
+        }
+
+        let merged = apply_changes(&step.sha, &read_commit(&step.sha)?)?;
+        if !merged.conflicts.is_empty() {
+            for (path, kind) in &merged.conflicts {
+                println!("CONFLICT ({}): {}", kind, path.to_string_lossy());
+            }
+            state.conflicts = merged.conflicts.into_iter().map(|(path, _)| path).collect();
+            state.save()?;
+            return Err(anyhow!(
+                "Could not apply {}, fix the conflicts, add the files and run 'gilltter rebase --continue'. \
+                 Use --skip to drop this commit or --abort to stop rebasing",
+                step.to_line()?
+            ));
+        }
+        finish_step(&step, &merged.files)?;
+    }
+
+    fs::remove_dir_all(state_dir())?;
+    match refs::current_branch()? {
+        Some(branch) => println!(
+            "Successfully rebased {} onto {}",
+            branch,
+            refs::short_hash(&state.onto)
+        ),
+        None => println!(
+            "Successfully rebased onto {}",
+            refs::short_hash(&state.onto)
+        ),
+    }
+    Ok(())
+}
+
+// Lets the user edit the todo list, returns the steps left in it
+fn edit_todo(steps: &[Step], onto: &str) -> anyhow::Result<Vec<Step>> {
+    let path = state_dir().join(STATE_TODO_FILE);
+    let mut text = String::new();
+    for step in steps {
+        text.push_str(&step.to_line()?);
+        text.push('\n');
+    }
+    text.push_str(&format!(
+        "\n# Rebase onto {} ({} commands){}",
+        refs::short_hash(onto),
+        steps.len(),
+        TODO_HELP
+    ));
+    fs::write(&path, text)?;
+    edit_file(&path)?;
+
+    let mut edited = Vec::new();
+    for line in fs::read_to_string(&path)?.lines() {
+        edited.extend(Step::parse(line)?);
+    }
+    if let Some(first) = edited.iter().find(|step| step.action != Action::Drop)
+        && matches!(first.action, Action::Squash | Action::Fixup)
+    {
+        return Err(anyhow!(
+            "Can't {} without a previous commit",
+            first.action.name()
+        ));
+    }
+    Ok(edited)
+}
+
+/// Replays the commits of HEAD which are not in `upstream` on top of it
+pub fn rebase(upstream: &str, interactive: bool) -> anyhow::Result<()> {
+    if state_dir().exists() {
+        return Err(anyhow!(
+            "A rebase is in progress, use --continue, --skip or --abort"
+        ));
+    }
+    let orig_head = refs::head_commit()?.ok_or(anyhow!("There are no commits to rebase"))?;
+    let onto = refs::resolve_revision(upstream)?;
+    read_commit(&onto)?; // make sure it is a commit
+    reset::ensure_clean()?;
+
+    let commits = refs::resolve_commits(&[format!("{}..{}", onto, orig_head)])?;
+    if !interactive && refs::first_parent_chain(&orig_head)?.contains(&onto) {
+        println!("Current branch is up to date");
+        return Ok(());
+    }
+    let mut todo: Vec<Step> = commits
+        .into_iter()
+        .map(|sha| Step {
+            action: Action::Pick,
+            sha,
+        })
+        .collect();
+
+    if interactive {
+        fs::create_dir_all(state_dir())?;
+        todo = match edit_todo(&todo, &onto) {
+            Ok(todo) => todo,
+            Err(why) => {
+                fs::remove_dir_all(state_dir())?;
+                return Err(why);
+            }
+        };
+        if todo.is_empty() {
+            fs::remove_dir_all(state_dir())?;
+            println!("Nothing to do");
+            return Ok(());
+        }
+    }
+
+    let state = State {
+        orig_head,
+        onto: onto.clone(),
+        current: None,
+        todo,
+        conflicts: Vec::new(),
+    };
+    state.save()?;
+    reset::reset(Some(&onto), ResetMode::Hard, &[])?;
+    hooks::post_checkout(Some(&state.orig_head), &onto);
+    run(state)
+}
+
+/// Records the resolved step and applies the rest
+pub fn rebase_continue() -> anyhow::Result<()> {
+    let mut state = State::load()?;
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    let files = index.entries();
+    for path in &state.conflicts {
+        if worktree::file_hash(path).as_ref() != files.get(path).map(|(_, sha)| sha) {
+            return Err(anyhow!(
+                "'{}' had conflicts and is not added yet",
+                path.to_string_lossy()
+            ));
+        }
+    }
+
+    if let Some(step) = &state.current {
+        finish_step(step, &files)?;
+    }
+    state.current = None;
+    run(state)
+}
+
+/// Throws the changes of the current step away and applies the rest
+pub fn rebase_skip() -> anyhow::Result<()> {
+    let mut state = State::load()?;
+    reset::reset(None, ResetMode::Hard, &[])?;
+    state.current = None;
+    run(state)
+}
+
+/// Goes back to where HEAD was before the rebase
+pub fn rebase_abort() -> anyhow::Result<()> {
+    let state = State::load()?;
+    let old = refs::head_commit()?;
+    reset::reset(Some(&state.orig_head), ResetMode::Hard, &[])?;
+    fs::remove_dir_all(state_dir())?;
+    hooks::post_checkout(old.as_deref(), &state.orig_head);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::commit::read_commit_files, test_utils::TempRepository};
+
+    #[test]
+    fn todo_lines_are_parsed() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let sha = repository.commit("first");
+
+        for action in [
+            Action::Pick,
+            Action::Reword,
+            Action::Squash,
+            Action::Fixup,
+            Action::Drop,
+        ] {
+            assert_eq!(Action::from_word(action.name()), Some(action));
+            assert_eq!(Action::from_word(&action.name()[..1]), Some(action));
+        }
+        assert_eq!(Action::from_word("edit"), None);
+
+        let step = Step::parse(&format!("  s {} first  ", refs::short_hash(&sha)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(step.action, Action::Squash);
+        assert_eq!(step.sha, sha);
+        assert_eq!(
+            step.to_line().unwrap(),
+            format!("squash {} first", refs::short_hash(&sha))
+        );
+        assert!(Step::parse("").unwrap().is_none());
+        assert!(Step::parse("# pick 1234567").unwrap().is_none());
+        assert!(Step::parse(&format!("edit {}", sha)).is_err());
+        assert!(Step::parse("pick").is_err());
+        assert!(Step::parse("pick 0000000").is_err());
+    }
+
+    #[test]
+    fn state_survives_saving() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "b\n");
+        let second = repository.commit("second");
+
+        State {
+            orig_head: second.clone(),
+            onto: first.clone(),
+            current: Some(Step {
+                action: Action::Reword,
+                sha: second.clone(),
+            }),
+            todo: vec![
+                Step {
+                    action: Action::Fixup,
+                    sha: first.clone(),
+                },
+                Step {
+                    action: Action::Drop,
+                    sha: second.clone(),
+                },
+            ],
+            conflicts: vec![PathBuf::from("a.txt"), PathBuf::from("dir/b.txt")],
+        }
+        .save()
+        .unwrap();
+
+        let state = State::load().unwrap();
+        assert_eq!(
+            (state.orig_head, state.onto),
+            (second.clone(), first.clone())
+        );
+        let current = state.current.unwrap();
+        assert_eq!(
+            (current.action, current.sha),
+            (Action::Reword, second.clone())
+        );
+        let todo: Vec<(Action, String)> = state
+            .todo
+            .into_iter()
+            .map(|step| (step.action, step.sha))
+            .collect();
+        assert_eq!(todo, vec![(Action::Fixup, first), (Action::Drop, second)]);
+        assert_eq!(
+            state.conflicts,
+            vec![PathBuf::from("a.txt"), PathBuf::from("dir/b.txt")]
+        );
+
+        fs::write(state_dir().join(STATE_CURRENT_FILE), "").unwrap();
+        assert!(State::load().unwrap().current.is_none());
+    }
+
+    #[test]
+    fn fixup_into_a_root_commit_stays_a_root() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let root = repository.commit("root");
+        repository.add("b.txt", "b\n");
+        let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE)).unwrap();
+        let fixup = commit_as(&index, "fixup".to_string(), None).unwrap();
+        refs::update_head(&root).unwrap();
+
+        let step = Step {
+            action: Action::Fixup,
+            sha: fixup,
+        };
+        finish_step(&step, &index.entries()).unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        assert_ne!(head, root);
+        let commit = read_commit(&head).unwrap();
+        assert_eq!(commit.get_parent_commit_sha(), None);
+        assert_eq!(commit.get_message().unwrap(), "root");
+        assert_eq!(read_commit_files(&head).unwrap(), index.files());
+    }
+}
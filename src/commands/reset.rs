@@ -1,51 +1,262 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{Read, Write},
-    path::Path,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
 };
 
 use anyhow::anyhow;
 
 use crate::{
-    base::{GILLTER_HEAD_FILE, GILLTER_OBJECTS_DIR, GILLTTER_PATH},
-    objects::{ObjectDump, ObjectPump, commit::Commit},
-    utils,
+    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    index::index::{Index, IndexEntry},
+    objects::{
+        ObjectDump, ObjectPump,
+        commit::{read_commit, read_commit_entries, read_commit_files},
+        tree::FileType,
+    },
+    refs, worktree,
 };
 
-pub fn reset(value: i32) -> anyhow::Result<()> {
-    // Read sha of the current commit
-    let head_file_path = Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE);
-    let mut head_file = File::open(&head_file_path)?;
-    let mut head_bytes = Vec::new();
-    head_file.read_to_end(&mut head_bytes)?;
-
-    // Read current commit
-    let commit_sha = String::from_utf8_lossy(&head_bytes)
-        .to_string()
-        .trim()
-        .to_string();
-    let commit_path = Path::new(GILLTTER_PATH)
-        .join(GILLTER_OBJECTS_DIR)
-        .join(commit_sha);
-    let mut commit_file = Commit::from_file(&commit_path)?;
-    // Iterate commits until we get to the one we need
-    for _ in 0..value {
-        // Iterate until the commit we need
-        if let Some(parent_commit_sha) = commit_file.get_parent_commit_sha() {
-            let parent_commit_path = Path::new(GILLTTER_PATH)
-                .join(GILLTER_OBJECTS_DIR)
-                .join(parent_commit_sha);
-            commit_file = Commit::from_file(&parent_commit_path)?;
-        } else {
-            return Err(anyhow!("Value is too big, commit history is not that long"));
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetMode {
+    Soft,  // move HEAD only
+    Mixed, // also make the index match the commit
+    Hard,  // also make the work tree match the commit
+}
+
+/// Fails if the index or tracked files differ from HEAD, a hard reset would throw those changes away
+pub fn ensure_clean() -> anyhow::Result<()> {
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    let files = index.files();
+    let head_files = match refs::head_commit()? {
+        Some(head) => read_commit_files(&head)?,
+        None => BTreeMap::new(),
+    };
+    if files != head_files {
+        return Err(anyhow!("The index has staged changes, commit them first"));
+    }
+    for (path, sha) in &files {
+        if worktree::file_hash(path).as_ref() != Some(sha) {
+            return Err(anyhow!(
+                "'{}' has local changes, commit or reset them first",
+                path.to_string_lossy()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Plain small numbers mean "this many commits back", the rest is a revision
+fn resolve_target(revision: &str) -> anyhow::Result<String> {
+    let sha = match revision.parse::<usize>() {
+        Ok(steps) if revision.len() < 4 => refs::resolve_revision(&format!("HEAD~{}", steps))?,
+        _ => refs::resolve_revision(revision)?,
+    };
+    read_commit(&sha)?; // make sure it is a commit
+    Ok(sha)
+}
+
+fn is_under(path: &Path, prefix: &Path) -> bool {
+    prefix.as_os_str().is_empty() || path.starts_with(prefix)
+}
+
+/// reset <rev> -- <paths>: index entries of the paths are taken from the commit, HEAD does not move
+fn reset_paths(
+    index: &mut Index,
+    target_entries: &BTreeMap<PathBuf, (FileType, String)>,
+    paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    let paths: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| crate::ignore::normalize_path(path))
+        .collect();
+    let mut matched = vec![false; paths.len()];
+
+    let mut kept = Vec::new();
+    for entry in index.indices.drain(..) {
+        match paths
+            .iter()
+            .position(|path| is_under(&entry.filename, path))
+        {
+            Some(pos) => matched[pos] = true,
+            None => kept.push(entry),
         }
     }
+    index.indices = kept;
+
+    for (filepath, (mode, sha)) in target_entries {
+        if let Some(pos) = paths.iter().position(|path| is_under(filepath, path)) {
+            matched[pos] = true;
+            index.add(IndexEntry::for_entry(filepath, *mode, sha));
+        }
+    }
+
+    if let Some(pos) = matched.iter().position(|matched| !matched) {
+        return Err(anyhow!(
+            "Path '{}' is neither in the index nor in the commit",
+            paths[pos].to_string_lossy()
+        ));
+    }
+    Ok(())
+}
+
+pub fn reset(revision: Option<&str>, mode: ResetMode, paths: &[PathBuf]) -> anyhow::Result<()> {
+    // No revision means HEAD, which may not exist yet
+    let target = match revision {
+        Some(revision) => Some(resolve_target(revision)?),
+        None => refs::head_commit()?,
+    };
+    let target_entries = match &target {
+        Some(sha) => read_commit_entries(sha)?,
+        None => BTreeMap::new(),
+    };
+
+    let mut index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+
+    if !paths.is_empty() {
+        if mode != ResetMode::Mixed {
+            return Err(anyhow!("Can't do a {:?} reset with paths", mode));
+        }
+        reset_paths(&mut index, &target_entries, paths)?;
+        index.dump_to_file()?;
+        return Ok(());
+    }
+
+    let head_files = match refs::head_commit()? {
+        Some(sha) => read_commit_files(&sha)?,
+        None => BTreeMap::new(),
+    };
+    if let Some(sha) = &target {
+        refs::update_head(sha)?;
+    }
+    if mode == ResetMode::Soft {
+        return Ok(());
+    }
+
+    if mode == ResetMode::Hard {
+        // Everything tracked either by the index or by the old HEAD goes away if the target does not have it
+        let mut tracked = head_files;
+        tracked.extend(index.files());
+        let target_files = target_entries
+            .iter()
+            .map(|(path, (_, sha))| (path.clone(), sha.clone()))
+            .collect();
+        worktree::checkout_files(&tracked, &target_files)?;
+        worktree::apply_modes(&target_entries)?;
+    }
 
-    let commit_bytes = commit_file.convert_to_bytes()?;
-    let commit_sha = utils::generate_hash(&commit_bytes);
-    // Set current commit in HEAD to value, this way it is a soft reset
-    let path = Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE);
-    let mut head_file = OpenOptions::new().truncate(true).write(true).open(&path)?;
-    head_file.write_all(commit_sha.as_bytes())?;
+    // Built after the work tree is written, so unchanged files get their real metadata
+    Index::from_checkout(&target_entries).dump_to_file()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    fn index_files() -> BTreeMap<PathBuf, String> {
+        Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))
+            .unwrap()
+            .files()
+    }
+
+    #[test]
+    fn small_numbers_are_steps_back() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "1\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "2\n");
+        let second = repository.commit("second");
+        repository.add("a.txt", "3\n");
+        let third = repository.commit("third");
+
+        assert_eq!(resolve_target("0").unwrap(), third);
+        assert_eq!(resolve_target("1").unwrap(), second);
+        assert_eq!(resolve_target("2").unwrap(), first);
+        assert!(resolve_target("3").is_err());
+        assert_eq!(resolve_target(refs::short_hash(&first)).unwrap(), first);
+        assert_eq!(resolve_target("HEAD~1").unwrap(), second);
+    }
+
+    #[test]
+    fn modes_reach_further_and_further() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "1\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "2\n");
+        repository.add("b.txt", "b\n");
+        let second = repository.commit("second");
+        let second_files = read_commit_files(&second).unwrap();
+
+        reset(Some(&first), ResetMode::Soft, &[]).unwrap();
+        assert_eq!(refs::head_commit().unwrap(), Some(first.clone()));
+        assert_eq!(index_files(), second_files);
+
+        refs::update_head(&second).unwrap();
+        reset(Some(&first), ResetMode::Mixed, &[]).unwrap();
+        assert_eq!(index_files(), read_commit_files(&first).unwrap());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "2\n");
+        assert!(Path::new("b.txt").exists());
+
+        refs::update_head(&second).unwrap();
+        reset(None, ResetMode::Mixed, &[]).unwrap();
+        reset(Some(&first), ResetMode::Hard, &[]).unwrap();
+        assert_eq!(index_files(), read_commit_files(&first).unwrap());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "1\n");
+        assert!(!Path::new("b.txt").exists());
+    }
+
+    #[test]
+    fn paths_are_unstaged() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        repository.add("dir/b.txt", "b\n");
+        let commit = repository.commit("first");
+        let committed = read_commit_files(&commit).unwrap();
+        repository.add("a.txt", "changed a\n");
+        repository.add("dir/b.txt", "changed b\n");
+        repository.add("dir/c.txt", "c\n");
+        let staged = index_files();
+
+        reset(None, ResetMode::Mixed, &[PathBuf::from("dir")]).unwrap();
+        let files = index_files();
+        assert_eq!(files[Path::new("a.txt")], staged[Path::new("a.txt")]);
+        assert_eq!(
+            files[Path::new("dir/b.txt")],
+            committed[Path::new("dir/b.txt")]
+        );
+        assert!(!files.contains_key(Path::new("dir/c.txt")));
+        assert_eq!(fs::read_to_string("dir/b.txt").unwrap(), "changed b\n");
+        assert_eq!(refs::head_commit().unwrap(), Some(commit));
+
+        assert!(reset(None, ResetMode::Mixed, &[PathBuf::from("missing.txt")]).is_err());
+        assert!(reset(None, ResetMode::Hard, &[PathBuf::from("a.txt")]).is_err());
+        assert_eq!(index_files(), files);
+    }
+
+    #[test]
+    fn mixed_reset_keeps_modes_of_changed_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repository = TempRepository::new();
+        fs::write("run.sh", "#!/bin/sh\n").unwrap();
+        fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+        repository.add("run.sh", "#!/bin/sh\n");
+        repository.commit("first");
+        fs::write("run.sh", "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions("run.sh", fs::Permissions::from_mode(0o644)).unwrap();
+
+        reset(None, ResetMode::Mixed, &[]).unwrap();
+        let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE)).unwrap();
+        assert_eq!(
+            index.entries()[Path::new("run.sh")].0,
+            FileType::ExecutableFile
+        );
+
+        reset(None, ResetMode::Hard, &[]).unwrap();
+        let mode = fs::metadata("run.sh").unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+    }
+}
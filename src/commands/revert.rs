@@ -1,141 +1,215 @@
-use std::{
-    fs::OpenOptions,
-    io::Write,
-    os::unix::fs::MetadataExt,
-    path::{Path, PathBuf},
-};
+use std::{collections::BTreeMap, path::Path};
 
 use anyhow::anyhow;
 
 use crate::{
-    base::{GILLTER_HEAD_FILE, GILLTER_OBJECTS_DIR, GILLTTER_INDEX_FILE, GILLTTER_PATH},
-    index::index::{Index, IndexEntry, IndexType},
+    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    commands::commit::commit_as,
+    index::index::Index,
+    merge,
     objects::{
-        ObjectDump, ObjectPump,
-        blob::Blob,
-        commit::Commit,
-        tree::{Tree, TreeObject},
+        ObjectPump,
+        commit::{read_commit, read_commit_entries},
     },
-    utils,
+    refs,
 };
 
-pub fn revert(commit_hash: &Path) -> anyhow::Result<()> {
-    let path = Path::new(GILLTTER_PATH)
-        .join(GILLTER_OBJECTS_DIR)
-        .join(commit_hash);
-    if !path.exists() {
-        return Err(anyhow!("Such commit object does not exist"));
-    }
+/// Undoes the changes of the commits on top of HEAD: the commit is merged
+/// into HEAD as if its parent was the change, so later edits survive.
+/// Without `no_commit` every reverted commit gets a commit of its own,
+/// with it the result is only left in the index and the work tree
+pub fn revert(revisions: &[String], no_commit: bool) -> anyhow::Result<()> {
+    let mut commits = refs::resolve_commits(revisions)?;
+    commits.reverse(); // newest first, so every revert applies on top of the later changes
 
-    // Get a commit
-    let commit = Commit::from_file(&path)?;
-
-    // Parse index and remove all files (and dirs) that are there (beware of unstaged deleted?)
-    let mut index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
-    for i_entry in &index.indices {
-        println!("File: {:?}", i_entry.filename);
-        let filepath_as_str = i_entry.filename.to_string_lossy();
-        let dirs: Vec<&str> = filepath_as_str.split('/').collect();
-
-        let root_path = Path::new(dirs[0]);
-        if root_path.is_file() || root_path.is_symlink() {
-            if let Err(why) = std::fs::remove_file(dirs[0]) {
-                eprintln!("Could not delete file {}, because: {}", dirs[0], why);
-                return Err(anyhow!(why));
-            }
-        } else {
-            // if let Err(why) = std::fs::remove_dir_all(root_path) { // FIXME: what if there is an untracked file?
-            //     eprintln!("Could not delete dir {}, because: {}", dirs[0], why);
-            //     return Err(anyhow!(why));
-            // }
-        }
-    }
-    index.remove_all();
-
-    // Create new files from that commit
-    let root_tree_hash = commit.get_tree_sha().ok_or(anyhow!(
-        "How there is no tree in {} commit",
-        commit_hash.to_str().unwrap()
-    ))?;
-    let root_tree_path = Path::new(GILLTTER_PATH)
-        .join(GILLTER_OBJECTS_DIR)
-        .join(root_tree_hash);
-    let root_tree = Tree::from_file(&root_tree_path)?;
-
-    let mut root_tree_files = Vec::new();
-    let mut current_path = PathBuf::new();
-    // load files from this root tree into vector
-    traverse_head_tree(&mut root_tree_files, &mut current_path, &root_tree)?;
-
-    // itrate files and create dirs and files
-    for object_file in root_tree_files.iter() {
-        let prefix = object_file.filename.parent();
-        if prefix.is_some() {
-            std::fs::create_dir_all(prefix.unwrap())?;
+    for (done, sha) in commits.iter().enumerate() {
+        let short = refs::short_hash(sha);
+        let reverted = read_commit(sha)?;
+        let parent_entries = match refs::history_parent(sha, &reverted, &refs::shallow_commits()) {
+            Some(parent) => read_commit_entries(&parent)?,
+            None => BTreeMap::new(),
+        };
+
+        let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+        let head_entries = match refs::head_commit()? {
+            Some(head) => read_commit_entries(&head)?,
+            None => return Err(anyhow!("There are no commits to revert on top of")),
+        };
+        if !no_commit && index.entries() != head_entries {
+            return Err(anyhow!(
+                "The index has staged changes, commit them first or use --no-commit"
+            ));
         }
 
-        let blob = Blob::from_file(
-            &Path::new(GILLTTER_PATH)
-                .join(GILLTER_OBJECTS_DIR)
-                .join(&object_file.sha1_hash),
+        let merged = merge::merge_trees(
+            &read_commit_entries(sha)?,
+            &index.entries(),
+            &parent_entries,
+            "HEAD",
+            &format!("parent of {}", short),
         )?;
-        let mut file = std::fs::File::create(&object_file.filename)?;
-        file.write_all(&blob.get_data())?;
-
-        // Add file to index
-        let file_metadata = file.metadata()?;
-        index.add(IndexEntry::new(
-            file_metadata.ctime(),
-            file_metadata.mtime(),
-            file_metadata.size(),
-            IndexType::RegularFile,
-            object_file.filename.clone(),
-            // object_file.sha1_hash.clone(),
-            utils::generate_hash(&blob.convert_to_bytes().unwrap()), // blob convert to bytes cant fail
-        ));
-
-        file.flush()?;
-    }
+        merge::apply(&merged, &index)?;
 
-    // Save index file
-    index.dump_to_file()?;
+        let message = format!(
+            "Revert \"{}\"\n\nThis reverts commit {}.",
+            reverted.get_message().unwrap_or_default().trim(),
+            sha
+        );
+        if !merged.conflicts.is_empty() {
+            for (path, kind) in &merged.conflicts {
+                println!("CONFLICT ({}): {}", kind, path.to_string_lossy());
+            }
+            let left: Vec<&str> = commits[done + 1..]
+                .iter()
+                .map(|sha| refs::short_hash(sha))
+                .collect();
+            if !left.is_empty() {
+                println!("Not reverted yet: {}", left.join(" "));
+            }
+            return Err(anyhow!(
+                "Could not revert {}, fix the conflicts, add the files and commit with message:\n{}",
+                short,
+                message
+            ));
+        }
 
-    // Update head TODO
-    let mut head_file = OpenOptions::new()
-        .truncate(true)
-        .write(true)
-        .open(&Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE))?;
-    head_file.write_all(commit_hash.to_string_lossy().as_bytes())?;
-    head_file.flush()?;
+        if no_commit {
+            println!("Reverted {} in the work tree", short);
+            continue;
+        }
+        if merged.files == head_entries {
+            println!("Reverting {} changes nothing, skipping", short);
+            continue;
+        }
+        if merged.files.is_empty() {
+            return Err(anyhow!("Reverting {} would leave nothing to commit", short));
+        }
+        let commit_hash = commit_as(&Index::from_checkout(&merged.files), message, None)?;
+        println!(
+            "[{}] Revert \"{}\"",
+            refs::short_hash(&commit_hash),
+            reverted.get_message().unwrap_or_default().trim()
+        );
+    }
     Ok(())
 }
 
-// TODO: Maybe factor this out in util
-fn traverse_head_tree(
-    head_files: &mut Vec<IndexEntry>,
-    current_path: &mut PathBuf,
-    tree: &Tree,
-) -> anyhow::Result<()> {
-    let tree_objects = tree.get_objects();
-    for (path, object) in &tree_objects {
-        if let TreeObject::Blob(blob_hash) = object {
-            head_files.push(IndexEntry::new(
-                0,
-                0,
-                0,
-                IndexType::RegularFile,
-                current_path.join(path),
-                blob_hash.to_string(),
-            ));
-        } else if let TreeObject::Tree(tree) = object {
-            let tree = Tree::from_file(
-                &Path::new(GILLTTER_PATH)
-                    .join(GILLTER_OBJECTS_DIR)
-                    .join(tree.get_hash().unwrap()),
-            )
-            .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
-            traverse_head_tree(head_files, &mut current_path.join(path), &tree)?;
-        }
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{objects::commit::read_commit_files, test_utils::TempRepository};
+
+    fn head_message() -> String {
+        read_commit(&refs::head_commit().unwrap().unwrap())
+            .unwrap()
+            .get_message()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn revert_commits_the_inverse() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        repository.add("b.txt", "new\n");
+        let second = repository.commit("second");
+
+        revert(std::slice::from_ref(&second), false).unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        assert_eq!(
+            read_commit(&head).unwrap().get_parent_commit_sha(),
+            Some(second.clone())
+        );
+        assert_eq!(
+            read_commit_files(&head).unwrap(),
+            read_commit_files(&first).unwrap()
+        );
+        assert_eq!(
+            head_message().trim(),
+            format!("Revert \"second\"\n\nThis reverts commit {}.", second)
+        );
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n");
+        assert!(!Path::new("b.txt").exists());
+    }
+
+    #[test]
+    fn ranges_are_reverted_newest_first() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        repository.commit("second");
+        repository.add("a.txt", "three\n");
+        let third = repository.commit("third");
+
+        revert(&[format!("{}..{}", first, third)], false).unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        assert_eq!(
+            read_commit_files(&head).unwrap(),
+            read_commit_files(&first).unwrap()
+        );
+        assert!(head_message().starts_with("Revert \"second\""));
+        assert!(refs::first_parent_chain(&head).unwrap().contains(&third));
+        assert_eq!(refs::first_parent_chain(&head).unwrap().len(), 5);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n");
+    }
+
+    #[test]
+    fn no_commit_leaves_the_changes_staged() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+
+        revert(std::slice::from_ref(&second), true).unwrap();
+        assert_eq!(refs::head_commit().unwrap(), Some(second));
+        let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE)).unwrap();
+        assert_eq!(index.files(), read_commit_files(&first).unwrap());
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n");
+    }
+
+    #[test]
+    fn conflicts_leave_markers_and_no_commit() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+        repository.add("a.txt", "three\n");
+        let third = repository.commit("third");
+
+        assert!(revert(&[second], false).is_err());
+        assert_eq!(refs::head_commit().unwrap(), Some(third));
+        let contents = fs::read_to_string("a.txt").unwrap();
+        assert!(contents.contains("<<<<<<< HEAD\nthree\n"));
+        assert!(contents.contains("=======\none\n>>>>>>> parent of"));
+    }
+
+    #[test]
+    fn reverts_restore_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::objects::tree::FileType;
+
+        let repository = TempRepository::new();
+        repository.add("run.sh", "#!/bin/sh\n");
+        repository.commit("first");
+        fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+        repository.add("run.sh", "#!/bin/sh\n");
+        let chmod = repository.commit("chmod");
+        repository.add("run.sh", "#!/bin/sh\nexit 0\n");
+        repository.commit("third");
+
+        revert(std::slice::from_ref(&chmod), false).unwrap();
+        let head = refs::head_commit().unwrap().unwrap();
+        let entries = read_commit_entries(&head).unwrap();
+        assert_eq!(entries[Path::new("run.sh")].0, FileType::RegularFile);
+        assert_eq!(fs::read_to_string("run.sh").unwrap(), "#!/bin/sh\nexit 0\n");
+        let mode = fs::metadata("run.sh").unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0);
     }
-    Ok(())
 }
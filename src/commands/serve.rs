@@ -0,0 +1,31 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::{
+    base::GILLTTER_PATH,
+    remote::{self, protocol},
+};
+
+/// Makes the root of the repository at `directory` (the current one if None) the current directory
+pub fn enter_repository(directory: Option<&Path>) -> anyhow::Result<()> {
+    let dir = remote::repository_dir(directory.unwrap_or(Path::new(".")))?;
+    if dir.file_name() != Some(GILLTTER_PATH.as_ref()) {
+        return Err(anyhow!(
+            "'{}' is not a gilltter repository",
+            dir.to_string_lossy()
+        ));
+    }
+    if let Some(root) = dir.parent()
+        && !root.as_os_str().is_empty()
+    {
+        std::env::set_current_dir(root)?;
+    }
+    Ok(())
+}
+
+/// Serves the repository over stdin/stdout, the other end is a remote with an ext:: or ssh:// url
+pub fn serve(directory: Option<&Path>) -> anyhow::Result<()> {
+    enter_repository(directory)?;
+    protocol::serve(std::io::stdin().lock(), std::io::stdout().lock())
+}
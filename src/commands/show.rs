@@ -0,0 +1,79 @@
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    commands::cat_file::pretty_print,
+    diff,
+    objects::{
+        ObjectPump,
+        commit::{read_commit, read_commit_files},
+        object_type,
+        tag::Tag,
+    },
+    refs, utils,
+};
+
+fn show_commit(sha: &str) -> anyhow::Result<()> {
+    let commit = read_commit(sha)?;
+    println!("commit {}", sha);
+    println!(
+        "Author: {} <{}>",
+        commit.get_username().unwrap_or_default(),
+        commit.get_email().unwrap_or_default()
+    );
+    println!(
+        "Date:   {}",
+        utils::format_timestamp(commit.get_secs_since_epoch().unwrap_or_default())
+    );
+    println!();
+    for line in commit.get_message().unwrap_or_default().lines() {
+        println!("    {}", line);
+    }
+    println!();
+
+    // The first commit is diffed against nothing
+    let parent_files = match refs::history_parent(sha, &commit, &refs::shallow_commits()) {
+        Some(parent) => read_commit_files(&parent)?,
+        None => Default::default(),
+    };
+    print!(
+        "{}",
+        diff::tree_diff(&parent_files, &read_commit_files(sha)?)?
+    );
+    Ok(())
+}
+
+fn show_tag(sha: &str) -> anyhow::Result<()> {
+    let tag = Tag::from_file(&object_path(sha))?;
+    println!("tag {}", tag.get_name().unwrap_or_default());
+    println!(
+        "Tagger: {} <{}>",
+        tag.get_username().unwrap_or_default(),
+        tag.get_email().unwrap_or_default()
+    );
+    println!(
+        "Date:   {}",
+        utils::format_timestamp(tag.get_secs_since_epoch().unwrap_or_default())
+    );
+    println!();
+    println!("{}", tag.get_message().unwrap_or_default());
+    println!();
+
+    let target = tag
+        .get_target_sha()
+        .ok_or(anyhow!("Tag {} has no target", sha))?;
+    show_object(&target)
+}
+
+fn show_object(sha: &str) -> anyhow::Result<()> {
+    match object_type(sha)?.as_str() {
+        "commit" => show_commit(sha),
+        "tag" => show_tag(sha),
+        _ => pretty_print(sha),
+    }
+}
+
+/// Commits with their diff, tags with what they tag, files of <revision>:<path> as they are
+pub fn show(revision: Option<&str>) -> anyhow::Result<()> {
+    show_object(&refs::resolve_object(revision.unwrap_or("HEAD"))?)
+}
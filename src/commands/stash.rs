@@ -0,0 +1,260 @@
+// Every stash is a pair of commits:
+/*
+ * I  "index on <branch>: <hash> <subject>"  the index, its parent is HEAD at the time of the stash
+ * W  "WIP on <branch>: <hash> <subject>"    tracked files of the work tree, its parent is I
+ */
+// and .gilltter/stash lists the W commits, see refs.rs. Untracked files are not stashed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{self, GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    commands::{
+        commit::create_commit,
+        reset::{self, ResetMode},
+    },
+    index::index::Index,
+    merge::{self, TreeMerge},
+    objects::{
+        ObjectPump,
+        commit::{read_commit, read_commit_entries, read_commit_files},
+    },
+    refs, worktree,
+};
+
+fn read_index() -> anyhow::Result<Index> {
+    Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))
+}
+
+// Tracked files as they are in the work tree, changed ones are stored as blobs
+fn worktree_files(
+    index_files: &BTreeMap<PathBuf, String>,
+    head_files: &BTreeMap<PathBuf, String>,
+) -> anyhow::Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    for path in index_files.keys().chain(head_files.keys()) {
+        let Some(sha) = worktree::file_hash(path) else {
+            continue; // deleted
+        };
+        if index_files.get(path) != Some(&sha) && head_files.get(path) != Some(&sha) {
+            base::gilltter_add(path)?;
+        }
+        files.insert(path.clone(), sha);
+    }
+    Ok(files)
+}
+
+fn stash_sha(stash: Option<&str>) -> anyhow::Result<(usize, String)> {
+    let stash = stash.unwrap_or("stash@{0}");
+    let pos = refs::parse_stash_ref(stash).ok_or(anyhow!("'{}' is not a stash", stash))?;
+    let sha = refs::stash_list()?
+        .get(pos)
+        .cloned()
+        .ok_or(anyhow!("There is no stash@{{{}}}", pos))?;
+    Ok((pos, sha))
+}
+
+/// Saves the index and the changes of tracked files, then resets to HEAD
+pub fn push(message: Option<&str>) -> anyhow::Result<()> {
+    let head = refs::head_commit()?.ok_or(anyhow!("There are no commits to stash on top of"))?;
+    let head_files = read_commit_files(&head)?;
+    let index = read_index()?;
+    let index_files = index.files();
+    let work_files = worktree_files(&index_files, &head_files)?;
+    if index_files == head_files && work_files == index_files {
+        println!("No local changes to save");
+        return Ok(());
+    }
+
+    let subject = read_commit(&head)?.get_message().unwrap_or_default();
+    let branch = refs::current_branch()?.unwrap_or("(no branch)".to_string());
+    let position = format!(
+        "{}: {} {}",
+        branch,
+        refs::short_hash(&head),
+        subject.lines().next().unwrap_or_default()
+    );
+    let index_commit = create_commit(&index, format!("index on {}", position), Some(head), None)?;
+    let message = match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}", position),
+    };
+    let work_commit = create_commit(
+        &Index::from_files(&work_files),
+        message.clone(),
+        Some(index_commit),
+        None,
+    )?;
+
+    let mut stashes = refs::stash_list()?;
+    stashes.insert(0, work_commit);
+    refs::write_stash_list(&stashes)?;
+
+    reset::reset(None, ResetMode::Hard, &[])?;
+    println!("Saved working directory and index state {}", message);
+    Ok(())
+}
+
+/// Brings the stashed index and work tree back, merging them with the current ones
+/// if HEAD has moved since. Pop drops the stash unless there were conflicts
+pub fn apply(stash: Option<&str>, pop: bool) -> anyhow::Result<()> {
+    let (pos, work_commit) = stash_sha(stash)?;
+    let index_commit = read_commit(&work_commit)?
+        .get_parent_commit_sha()
+        .ok_or(anyhow!("Broken stash {}", work_commit))?;
+    let base = read_commit(&index_commit)?
+        .get_parent_commit_sha()
+        .ok_or(anyhow!("Broken stash {}", work_commit))?;
+    let base_entries = read_commit_entries(&base)?;
+
+    let index = read_index()?;
+    let ours = index.entries();
+    let index_merge = merge::merge_trees(
+        &base_entries,
+        &ours,
+        &read_commit_entries(&index_commit)?,
+        "Updated upstream",
+        "Stashed changes",
+    )?;
+    let work_merge = merge::merge_trees(
+        &base_entries,
+        &ours,
+        &read_commit_entries(&work_commit)?,
+        "Updated upstream",
+        "Stashed changes",
+    )?;
+
+    let mut conflicts = work_merge.conflicts;
+    for conflict in index_merge.conflicts {
+        if !conflicts.iter().any(|(path, _)| *path == conflict.0) {
+            conflicts.push(conflict);
+        }
+    }
+    let merged = TreeMerge {
+        files: index_merge.files,
+        worktree: work_merge.worktree,
+        conflicts,
+    };
+    merge::apply(&merged, &index)?;
+
+    if !merged.conflicts.is_empty() {
+        for (path, kind) in &merged.conflicts {
+            println!("CONFLICT ({}): {}", kind, path.to_string_lossy());
+        }
+        return Err(anyhow!(
+            "Could not apply stash@{{{}}} cleanly, the stash is kept",
+            pos
+        ));
+    }
+    if pop {
+        drop_entry(Some(&pos.to_string()))?;
+    }
+    Ok(())
+}
+
+pub fn list() -> anyhow::Result<()> {
+    for (pos, sha) in refs::stash_list()?.iter().enumerate() {
+        let message = read_commit(sha)?.get_message().unwrap_or_default();
+        println!(
+            "stash@{{{}}}: {}",
+            pos,
+            message.lines().next().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+pub fn drop_entry(stash: Option<&str>) -> anyhow::Result<()> {
+    let (pos, sha) = stash_sha(stash)?;
+    let mut stashes = refs::stash_list()?;
+    stashes.remove(pos);
+    refs::write_stash_list(&stashes)?;
+    println!("Dropped stash@{{{}}} ({})", pos, refs::short_hash(&sha));
+    Ok(())
+}
+
+/// Files the stash changes compared to the commit it was made on
+pub fn show(stash: Option<&str>) -> anyhow::Result<()> {
+    let (_, work_commit) = stash_sha(stash)?;
+    let base = refs::resolve_revision(&format!("{}~2", work_commit))?;
+    let base_files = read_commit_files(&base)?;
+    let work_files = read_commit_files(&work_commit)?;
+
+    let paths: BTreeSet<&PathBuf> = base_files.keys().chain(work_files.keys()).collect();
+    for path in paths {
+        let status = match (base_files.get(path), work_files.get(path)) {
+            (None, Some(_)) => "A",
+            (Some(_), None) => "D",
+            (Some(before), Some(after)) if before != after => "M",
+            _ => continue,
+        };
+        println!("{}\t{}", status, path.to_string_lossy());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{base::object_path, objects::blob::Blob, test_utils::TempRepository};
+
+    // Contents of the file in the index
+    fn staged(path: &str) -> String {
+        let sha = read_index().unwrap().files()[Path::new(path)].clone();
+        String::from_utf8(Blob::from_file(&object_path(&sha)).unwrap().get_data()).unwrap()
+    }
+
+    #[test]
+    fn stashes_round_trip() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        repository.commit("first");
+        repository.add("a.txt", "staged\n");
+        fs::write("a.txt", "worktree\n").unwrap();
+
+        push(Some("changes")).unwrap();
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "a\n");
+        assert_eq!(staged("a.txt"), "a\n");
+        assert_eq!(refs::stash_list().unwrap().len(), 1);
+
+        apply(None, false).unwrap();
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "worktree\n");
+        assert_eq!(staged("a.txt"), "staged\n");
+        assert_eq!(refs::stash_list().unwrap().len(), 1);
+
+        reset::reset(None, ResetMode::Hard, &[]).unwrap();
+        apply(Some("stash@{0}"), true).unwrap();
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "worktree\n");
+        assert!(refs::stash_list().unwrap().is_empty());
+        assert!(apply(None, true).is_err());
+    }
+
+    #[test]
+    fn stashes_merge_with_later_commits() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "1\n2\n3\n4\n5\n");
+        repository.commit("first");
+        fs::write("a.txt", "1\n2\n3\n4\nfive\n").unwrap();
+        push(None).unwrap();
+
+        repository.add("a.txt", "one\n2\n3\n4\n5\n");
+        repository.commit("second");
+        apply(None, true).unwrap();
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n2\n3\n4\nfive\n");
+
+        // Both sides change the same line
+        push(None).unwrap();
+        repository.add("a.txt", "one\n2\n3\n4\nFIVE\n");
+        repository.commit("third");
+        assert!(apply(None, true).is_err());
+        assert_eq!(refs::stash_list().unwrap().len(), 1);
+        assert!(fs::read_to_string("a.txt").unwrap().contains("<<<<<<<"));
+    }
+}
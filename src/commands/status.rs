@@ -1,21 +1,13 @@
 use anyhow::anyhow;
 use colored::Colorize;
-use std::{
-    io::Read,
-    os::unix::fs::MetadataExt,
-    path::{Path, PathBuf},
-};
+use std::{os::unix::fs::MetadataExt, path::Path};
 
 use crate::{
-    base::{GILLTER_HEAD_FILE, GILLTER_OBJECTS_DIR, GILLTTER_INDEX_FILE, GILLTTER_PATH},
-    ignore,
+    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    ignore::IgnoreMatcher,
     index::index::{Index, IndexEntry, IndexType},
-    objects::{
-        ObjectPump,
-        commit::Commit,
-        tree::{Tree, TreeObject},
-    },
-    utils,
+    objects::{ObjectPump, commit::read_commit_files},
+    refs, utils,
 };
 
 static DONT_TRACK_DIRS: &[&str] = &[".gilltter"];
@@ -23,19 +15,22 @@ static DONT_TRACK_DIRS: &[&str] = &[".gilltter"];
 fn traverse_dirs_impl(
     entries: &mut Vec<IndexEntry>,
     path: std::path::PathBuf,
-    ignore_files: &Vec<String>,
+    ignore_matcher: &mut IgnoreMatcher,
 ) -> anyhow::Result<()> {
     let dir = path;
     let root_path = std::env::current_dir()?;
-    for entry in std::fs::read_dir(dir)? {
+    let relative_dir = dir.strip_prefix(&root_path)?.to_path_buf();
+
+    for entry in std::fs::read_dir(&dir)? {
         let entry = entry?;
-        if ignore::should_ignore(&entry.file_name(), &ignore_files)? {
+        let filetype = entry.file_type()?;
+        let relative_path = relative_dir.join(entry.file_name());
+        if ignore_matcher.is_ignored(&relative_path, filetype.is_dir())? {
             continue;
         }
 
-        let filetype = entry.file_type()?;
-        if filetype.is_file() {
-            let meta = std::fs::metadata(entry.path())?;
+        if filetype.is_file() || filetype.is_symlink() {
+            let meta = std::fs::symlink_metadata(entry.path())?;
             let content = utils::get_file_contents_as_blob(&entry.path())?;
             let sha1 = utils::generate_hash(&content);
 
@@ -44,8 +39,8 @@ fn traverse_dirs_impl(
                 meta.ctime(),
                 meta.mtime(),
                 meta.size(),
-                crate::index::index::IndexType::RegularFile,
-                entry.path().strip_prefix(&root_path)?.to_path_buf(),
+                crate::index::index::IndexType::of_metadata(&meta),
+                relative_path,
                 sha1,
             ));
         } else if filetype.is_dir() {
@@ -57,7 +52,7 @@ fn traverse_dirs_impl(
             ) {
                 continue;
             }
-            traverse_dirs_impl(entries, entry.path(), ignore_files)?;
+            traverse_dirs_impl(entries, entry.path(), ignore_matcher)?;
         }
     }
     Ok(())
@@ -65,8 +60,8 @@ fn traverse_dirs_impl(
 
 pub fn traverse_dirs(path: std::path::PathBuf) -> anyhow::Result<Vec<IndexEntry>> {
     let mut entries: Vec<IndexEntry> = Vec::new();
-    let ignore_files = ignore::gilltter_get_ignorefile()?;
-    if let Err(why) = traverse_dirs_impl(&mut entries, path, &ignore_files) {
+    let mut ignore_matcher = IgnoreMatcher::new()?;
+    if let Err(why) = traverse_dirs_impl(&mut entries, path, &mut ignore_matcher) {
         return Err(why);
     }
     Ok(entries)
@@ -136,48 +131,19 @@ fn get_deleted_files(
 }
 
 fn traverse_head_get_files() -> anyhow::Result<Vec<IndexEntry>> {
-    let mut head_file = match std::fs::File::open(Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE))
-    {
-        Ok(file) => file,
-        Err(why) => {
-            // fatal error, head file should always be there
-            eprintln!("Could not open the head file, because: {}", why);
-            return Err(anyhow!(why));
-        }
-    };
-
-    let mut head_commit_bytes = Vec::new();
-    head_file.read_to_end(&mut head_commit_bytes)?;
-
     let mut head_files: Vec<IndexEntry> = Vec::new();
-    if !head_commit_bytes.is_empty() {
+    if let Some(commit_sha) = refs::head_commit()? {
         // Нужно пройтись по хеду и добавить файлы в head_file, путь ставить относительно root_path
-        let commit_sha = String::from_utf8_lossy(&head_commit_bytes)
-            .trim()
-            .to_string();
-
-        let commit = Commit::from_file(
-            &Path::new(GILLTTER_PATH)
-                .join(GILLTER_OBJECTS_DIR)
-                .join(commit_sha.to_string()),
-        )
-        .map_err(|why| anyhow!("Commit file does not exist: {}", why))?;
-
-        // Get a tree
-        // println!("commit: {}", String::from_utf8_lossy(&commit.convert_to_bytes()));
-        let tree_hash = commit
-            .get_tree_sha()
-            .expect("Should've had a tree hash inside");
-
-        // Then traverse trees and add them to trees
-        let mut current_path = PathBuf::new(); // Track current path, empty = base tree, commit always points to a base tree
-        let base_tree = Tree::from_file(
-            &Path::new(GILLTTER_PATH)
-                .join(GILLTER_OBJECTS_DIR)
-                .join(&tree_hash),
-        )
-        .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
-        traverse_head_tree(&mut head_files, &mut current_path, &base_tree)?; // traverse from head untiil the end
+        for (filename, blob_hash) in read_commit_files(&commit_sha)? {
+            head_files.push(IndexEntry::new(
+                0,
+                0,
+                0,
+                IndexType::RegularFile,
+                filename,
+                blob_hash,
+            ));
+        }
     }
     Ok(head_files)
 }
@@ -233,35 +199,6 @@ fn get_staged_and_commited(
     Ok((staged_files, commited_files))
 }
 
-fn traverse_head_tree(
-    head_files: &mut Vec<IndexEntry>,
-    current_path: &mut PathBuf,
-    tree: &Tree,
-) -> anyhow::Result<()> {
-    let tree_objects = tree.get_objects();
-    for (path, object) in &tree_objects {
-        if let TreeObject::Blob(blob_hash) = object {
-            head_files.push(IndexEntry::new(
-                0,
-                0,
-                0,
-                IndexType::RegularFile,
-                current_path.join(path),
-                blob_hash.to_string(),
-            ));
-        } else if let TreeObject::Tree(tree) = object {
-            let tree = Tree::from_file(
-                &Path::new(GILLTTER_PATH)
-                    .join(GILLTER_OBJECTS_DIR)
-                    .join(tree.get_hash().unwrap()),
-            )
-            .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
-            traverse_head_tree(head_files, &mut current_path.join(path), &tree)?;
-        }
-    }
-    Ok(())
-}
-
 // TODO: Better error handling, more transparent errors, check edge cases like when one of the tree doesn't exist
 // idea n1: use HashMaps instead of Vec to optimize lookup
 // idead n2: Store references instead of clones()
@@ -0,0 +1,131 @@
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    commands::commit::configured_user,
+    objects::{ObjectDump, ObjectPump, object_type, tag::Tag},
+    refs,
+};
+
+/// Tags the revision (HEAD if omitted). With a message the tag is annotated,
+/// i.e. a tag object is stored, otherwise the tag just holds the commit hash
+pub fn create(
+    name: &str,
+    revision: Option<&str>,
+    message: Option<&str>,
+    annotate: bool,
+    force: bool,
+) -> anyhow::Result<()> {
+    refs::check_ref_name(name)?;
+    if !force && refs::tag_object(name)?.is_some() {
+        return Err(anyhow!("Tag '{}' already exists, use -f to move it", name));
+    }
+    let target = refs::resolve_revision(revision.unwrap_or("HEAD"))?;
+
+    let sha = match (message, annotate) {
+        (None, true) => return Err(anyhow!("Annotated tags need a message, use -m")),
+        (None, false) => target,
+        (Some(message), _) => {
+            let (username, email) = configured_user()?;
+            let mut tag = Tag::new();
+            tag.set_target(&target, object_type(&target)?)
+                .set_name(name)
+                .set_username(username)
+                .set_email(email)
+                .set_message(message);
+            tag.dump_to_file()?
+        }
+    };
+    refs::update_tag(name, &sha)
+}
+
+/// Tag names, only those matching the glob pattern if there is one
+pub fn list(pattern: Option<&str>) -> anyhow::Result<()> {
+    let pattern = pattern.map(glob::Pattern::new).transpose()?;
+    for name in refs::tag_names()? {
+        if pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.matches(&name))
+        {
+            println!("{}", name);
+        }
+    }
+    Ok(())
+}
+
+pub fn delete(names: &[String]) -> anyhow::Result<()> {
+    for name in names {
+        let sha = refs::tag_object(name)?.ok_or(anyhow!("Tag '{}' not found", name))?;
+        refs::delete_tag(name)?;
+        println!("Deleted tag '{}' (was {})", name, refs::short_hash(&sha));
+    }
+    Ok(())
+}
+
+/// Checks that the tags point to existing objects and that tag objects are intact
+pub fn verify(names: &[String]) -> anyhow::Result<()> {
+    for name in names {
+        let sha = refs::tag_object(name)?.ok_or(anyhow!("Tag '{}' not found", name))?;
+        let obj_type = object_type(&sha)?;
+        if obj_type != "tag" {
+            println!("{}: lightweight tag of {} {}", name, obj_type, sha);
+            continue;
+        }
+
+        let tag = Tag::from_file(&object_path(&sha))?;
+        let target = tag.get_target_sha().unwrap_or_default();
+        let target_type = tag.get_target_type().unwrap_or_default();
+        if tag.get_name().as_deref() != Some(name.as_str()) {
+            return Err(anyhow!(
+                "Tag '{}' holds a tag object named '{}'",
+                name,
+                tag.get_name().unwrap_or_default()
+            ));
+        }
+        let actual_type = object_type(&target)
+            .map_err(|why| anyhow!("Tag '{}' points to a missing object: {}", name, why))?;
+        if actual_type != target_type {
+            return Err(anyhow!(
+                "Tag '{}' says {} is a {}, but it is a {}",
+                name,
+                target,
+                target_type,
+                actual_type
+            ));
+        }
+        println!(
+            "{}: tag object {} of {} {}, tagged by {} <{}>",
+            name,
+            refs::short_hash(&sha),
+            target_type,
+            target,
+            tag.get_username().unwrap_or_default(),
+            tag.get_email().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    #[test]
+    fn tag_names_cannot_reach_other_refs() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let sha = repository.commit("first");
+
+        assert!(create("../branches/main", None, None, false, true).is_err());
+        assert!(delete(&["../branches/main".to_string()]).is_err());
+        assert!(verify(&["../branches/main".to_string()]).is_err());
+        assert!(refs::delete_tag("../branches/main").is_err());
+        assert_eq!(refs::branch_commit("main").unwrap(), Some(sha.clone()));
+
+        create("v1", None, None, false, false).unwrap();
+        assert_eq!(refs::tag_object("v1").unwrap(), Some(sha));
+        delete(&["v1".to_string()]).unwrap();
+        assert_eq!(refs::tag_object("v1").unwrap(), None);
+    }
+}
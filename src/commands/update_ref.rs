@@ -0,0 +1,84 @@
+use anyhow::anyhow;
+
+use crate::{base::GILLTTER_TAGS_DIR, objects::object_type, refs};
+
+// The old value update-ref gets when the ref must not exist yet
+const NO_REF_HASH: &'static str = "0000000000000000000000000000000000000000";
+
+fn expected_value(old: Option<&str>) -> anyhow::Result<Option<String>> {
+    match old {
+        None => Ok(None),
+        Some(old) if old.is_empty() || old == NO_REF_HASH => Ok(Some(String::new())),
+        Some(old) => refs::resolve_object(old).map(Some),
+    }
+}
+
+/// Points the ref (HEAD, branches/<name>, tags/<name> or a branch name) to the object,
+/// only if it is at `old` now when `old` is given
+pub fn update_ref(name: &str, new: &str, old: Option<&str>) -> anyhow::Result<()> {
+    let path = refs::ref_file(name)?;
+    let sha = refs::resolve_object(new)?;
+    let is_tag = name.starts_with(&format!("{}/", GILLTTER_TAGS_DIR));
+    if !is_tag && object_type(&sha)? != "commit" {
+        return Err(anyhow!("'{}' can only point to a commit", name));
+    }
+    refs::compare_and_swap(&path, expected_value(old)?.as_deref(), Some(&sha))
+}
+
+/// Deletes the ref, only if it is at `old` now when `old` is given
+pub fn delete_ref(name: &str, old: Option<&str>) -> anyhow::Result<()> {
+    if name == "HEAD" {
+        return Err(anyhow!("HEAD can't be deleted"));
+    }
+    let path = refs::ref_file(name)?;
+    if !path.exists() {
+        return Err(anyhow!("There is no ref '{}'", name));
+    }
+    refs::compare_and_swap(&path, expected_value(old)?.as_deref(), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    #[test]
+    fn old_values_are_checked() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+
+        assert!(update_ref("main", &first, Some(&first)).is_err());
+        assert_eq!(refs::branch_commit("main").unwrap(), Some(second.clone()));
+        update_ref("branches/main", &first, Some(&second)).unwrap();
+        assert_eq!(refs::branch_commit("main").unwrap(), Some(first.clone()));
+
+        // All zeros: the ref must not exist yet
+        assert!(update_ref("main", &second, Some(NO_REF_HASH)).is_err());
+        update_ref("other", &second, Some(NO_REF_HASH)).unwrap();
+        assert_eq!(refs::branch_commit("other").unwrap(), Some(second.clone()));
+
+        assert!(delete_ref("other", Some(&first)).is_err());
+        assert_eq!(refs::branch_commit("other").unwrap(), Some(second.clone()));
+        delete_ref("other", Some(&second)).unwrap();
+        assert_eq!(refs::branch_commit("other").unwrap(), None);
+        assert!(delete_ref("other", None).is_err());
+        assert!(delete_ref("HEAD", None).is_err());
+    }
+
+    #[test]
+    fn attached_head_moves_its_branch() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        repository.commit("second");
+
+        update_ref("HEAD", &first, None).unwrap();
+        assert_eq!(refs::current_branch().unwrap().as_deref(), Some("main"));
+        assert_eq!(refs::branch_commit("main").unwrap(), Some(first.clone()));
+        assert_eq!(refs::head_commit().unwrap(), Some(first));
+    }
+}
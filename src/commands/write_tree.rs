@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
+    index::index::Index,
+    objects::{
+        ObjectDump, ObjectPump,
+        tree::{self, Tree, TreeObject},
+    },
+};
+
+/// Stores the trees of the index, returns the hash of the root one
+pub fn build_tree(index: &Index) -> anyhow::Result<String> {
+    if index.indices.is_empty() {
+        return Err(anyhow!("Nothing to write, the index is empty"));
+    }
+    // Create a base tree, which all other objects are added to
+    let mut base_tree = Tree::new();
+
+    for entry in index.indices.iter() {
+        let name = entry.filename.to_string_lossy().to_string();
+        let paths: Vec<&str> = name.split('/').collect();
+        let (file, dirs) = paths.split_last().unwrap();
+
+        // Walk down to the tree holding the file, creating missing ones
+        let mut this_tree = &mut base_tree;
+        for (depth, dir) in dirs.iter().enumerate() {
+            this_tree.add_object_if_not_exists(dir, || TreeObject::Tree(Tree::new()));
+            this_tree = match this_tree.get_object_mut(dir).unwrap() {
+                TreeObject::Tree(tree) => tree,
+                _ => {
+                    return Err(anyhow!(
+                        "'{}' is a file, so '{}' can't be under it",
+                        dirs[..=depth].join("/"),
+                        name
+                    ));
+                }
+            };
+        }
+        if let Some(TreeObject::Tree(_)) = this_tree.get_object(file) {
+            return Err(anyhow!(
+                "'{}' is a directory, so it can't be a file too",
+                name
+            ));
+        }
+        this_tree.add_object(
+            file,
+            TreeObject::Blob(entry.index_type.file_type(), entry.sha1_hash.clone()),
+        );
+    }
+
+    let base_tree_hash = base_tree.dump_to_file()?;
+
+    let base_tree_objects = base_tree.get_objects();
+    for object in base_tree_objects.values() {
+        if let TreeObject::Tree(tree) = object {
+            // Dump all subtrees
+            tree::dump_tree_recursive(tree)?;
+        }
+    }
+    Ok(base_tree_hash)
+}
+
+/// Writes the trees of the current index and prints the root one
+pub fn write_tree() -> anyhow::Result<()> {
+    let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))?;
+    println!("{}", build_tree(&index)?);
+    Ok(())
+}
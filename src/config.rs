@@ -1,84 +1,650 @@
 // we need to parse config and make functions to set these settings
 // config format will be like:
 /*
+ * # comment, ';' works too
  * [<Category-name>]
- * <key>=<value> (no space)
+ * <key> = <value>          ; inline comment
+ * <key> = "quoted # value with \"escapes\"\n"
+ * <flag>                   (no value means true)
+ * [<Category-name> "<subsection>"]
+ */
+// Config is layered, later layers override earlier ones:
+/*
+ * system:  /etc/gilltterconfig
+ * global:  ~/.gilltterconfig or $XDG_CONFIG_HOME/gilltter/config
+ * local:   .gilltter/config
+ * env:     GILLTTER_CONFIG_COUNT, GILLTTER_CONFIG_KEY_<n>, GILLTTER_CONFIG_VALUE_<n>
  */
 
 use std::{
-    collections::HashMap,
-    fs::File,
-    io::{BufRead, BufReader, Cursor, Read},
-    path::Path,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Cursor, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
 };
 
 use anyhow::anyhow;
 
-use crate::objects::ObjectPump;
+use crate::{
+    base::{GILLTER_CONFIG_FILE, GILLTTER_PATH},
+    objects::{ObjectDump, ObjectPump},
+};
 
 pub const CONFIG_GENERAL_SECTION: &'static str = "General";
 pub const CONFIG_USERNAME_FIELD: &'static str = "Username";
 pub const CONFIG_EMAIL_FIELD: &'static str = "Email";
+pub const CONFIG_CORE_SECTION: &'static str = "Core";
+pub const CONFIG_EXCLUDES_FILE_FIELD: &'static str = "ExcludesFile";
+pub const CONFIG_EDITOR_FIELD: &'static str = "Editor";
+pub const CONFIG_ALIAS_SECTION: &'static str = "Alias";
+
+pub const CONFIG_SYSTEM_PATH: &'static str = "/etc/gilltterconfig";
+pub const CONFIG_GLOBAL_FILE: &'static str = ".gilltterconfig";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigScope {
+    System,
+    Global,
+    Local,
+}
+
+impl ConfigScope {
+    /// File the scope is read from and written to. GILLTTER_CONFIG_SYSTEM and GILLTTER_CONFIG_GLOBAL override it
+    pub fn path(&self) -> Option<PathBuf> {
+        match self {
+            Self::System => Some(
+                std::env::var_os("GILLTTER_CONFIG_SYSTEM")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(CONFIG_SYSTEM_PATH)),
+            ),
+            Self::Global => {
+                if let Some(path) = std::env::var_os("GILLTTER_CONFIG_GLOBAL") {
+                    return Some(PathBuf::from(path));
+                }
+                let home = std::env::var_os("HOME").map(PathBuf::from);
+                let xdg = std::env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home.as_ref().map(|home| home.join(".config")))
+                    .map(|dir| dir.join("gilltter").join("config"));
+
+                // ~/.gilltterconfig wins if it is there, XDG file is used only if it already exists
+                let home_file = home.map(|home| home.join(CONFIG_GLOBAL_FILE));
+                match (home_file, xdg) {
+                    (Some(home_file), _) if home_file.exists() => Some(home_file),
+                    (_, Some(xdg)) if xdg.exists() => Some(xdg),
+                    (home_file, xdg) => home_file.or(xdg),
+                }
+            }
+            Self::Local => Some(Path::new(GILLTTER_PATH).join(GILLTER_CONFIG_FILE)),
+        }
+    }
+}
+
+/// "Remote.origin" is a [Remote "origin"] category: the name is case insensitive, the subsection is not
+#[derive(Clone, Debug)]
+struct Category {
+    name: String,
+    subsection: Option<String>,
+}
+
+impl Category {
+    fn from_key(category: &str) -> Self {
+        match category.split_once('.') {
+            Some((name, subsection)) => Self {
+                name: name.to_owned(),
+                subsection: Some(subsection.to_owned()),
+            },
+            None => Self {
+                name: category.to_owned(),
+                subsection: None,
+            },
+        }
+    }
+
+    fn matches(&self, category: &str) -> bool {
+        let other = Category::from_key(category);
+        self.name.eq_ignore_ascii_case(&other.name) && self.subsection == other.subsection
+    }
+
+    fn to_key(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!("{}.{}", self.name, subsection),
+            None => self.name.clone(),
+        }
+    }
+
+    fn to_header(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!(
+                "[{} \"{}\"]",
+                self.name,
+                subsection.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+            None => format!("[{}]", self.name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ConfigEntry {
+    category: Category,
+    key: String,
+    value: String,       // unquoted and unescaped
+    raw: Option<String>, // original text, None once the value is changed
+    line_number: usize,
+    source: Option<PathBuf>,
+}
+
+impl ConfigEntry {
+    fn matches(&self, category_name: &str, name: &str) -> bool {
+        self.category.matches(category_name) && self.key.eq_ignore_ascii_case(name)
+    }
+
+    /// Where the value came from, for error messages
+    fn location(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{}:{}", source.to_string_lossy(), self.line_number),
+            None if self.line_number > 0 => format!("line {}", self.line_number),
+            None => String::from("environment"),
+        }
+    }
+
+    fn parse_typed<T>(&self, kind: &str, parser: fn(&str) -> Option<T>) -> anyhow::Result<T> {
+        parser(&self.value).ok_or(anyhow!(
+            "Bad {} value '{}' for {}.{} ({})",
+            kind,
+            self.value,
+            self.category.to_key(),
+            self.key,
+            self.location()
+        ))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum ConfigLine {
+    Section { category: Category, raw: String },
+    Entry(ConfigEntry),
+    Other(String), // comments and blank lines are kept as is
+}
+
+impl ConfigLine {
+    fn to_text(&self) -> String {
+        match self {
+            Self::Section { raw, .. } => raw.clone(),
+            Self::Entry(entry) => entry
+                .raw
+                .clone()
+                .unwrap_or_else(|| format!("{}={}", entry.key, format_value(&entry.value))),
+            Self::Other(raw) => raw.clone(),
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|ch| ch.is_ascii_alphabetic())
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+}
+
+// Quotes the value if it would not survive parsing as is
+fn format_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';', '"', '\\', '\n', '\t']);
+    if !needs_quotes {
+        return value.to_owned();
+    }
+
+    let mut quoted = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Everything after '=': strips comments and surrounding spaces, handles quotes and escapes
+fn parse_value(text: &str) -> anyhow::Result<String> {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut trailing_spaces = 0; // unquoted spaces at the end are not a part of the value
+    let mut chars = text.trim_start().chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                trailing_spaces = 0;
+                continue;
+            }
+            '\\' => {
+                let escaped = match chars.next() {
+                    Some('\\') => '\\',
+                    Some('"') => '"',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some(other) => return Err(anyhow!("unknown escape sequence '\\{}'", other)),
+                    None => return Err(anyhow!("backslash at the end of the line")),
+                };
+                value.push(escaped);
+                trailing_spaces = 0;
+                continue;
+            }
+            '#' | ';' if !in_quotes => break,
+            _ => {}
+        }
+        value.push(ch);
+        if ch.is_whitespace() && !in_quotes {
+            trailing_spaces += ch.len_utf8();
+        } else {
+            trailing_spaces = 0;
+        }
+    }
+    if in_quotes {
+        return Err(anyhow!("unterminated quoted string"));
+    }
+    value.truncate(value.len() - trailing_spaces);
+    Ok(value)
+}
+
+// "[name]" or "[name "subsection"]", maybe followed by a comment
+fn parse_header(line: &str) -> anyhow::Result<Category> {
+    let inner = &line[1..];
+    let mut name = String::new();
+    let mut subsection: Option<String> = None;
+    let mut chars = inner.chars();
+    let mut closed = false;
+    let mut spaced = false; // the name is over, only a subsection may follow
+    while let Some(ch) = chars.next() {
+        match ch {
+            ']' => {
+                closed = true;
+                break;
+            }
+            '"' if subsection.is_some() => {
+                return Err(anyhow!("more than one subsection name"));
+            }
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => quoted.push(escaped),
+                            None => return Err(anyhow!("unterminated subsection name")),
+                        },
+                        Some(other) => quoted.push(other),
+                        None => return Err(anyhow!("unterminated subsection name")),
+                    }
+                }
+                subsection = Some(quoted);
+            }
+            ch if ch.is_whitespace() => spaced = !name.is_empty(),
+            ch if subsection.is_some() => {
+                return Err(anyhow!("unexpected '{}' after subsection name", ch));
+            }
+            _ if spaced && !name.is_empty() => {
+                return Err(anyhow!("space in category name '{}'", name));
+            }
+            _ => name.push(ch),
+        }
+    }
+    if !closed {
+        return Err(anyhow!("missing ']'"));
+    }
+    let rest = chars.as_str().trim();
+    if !rest.is_empty() && !rest.starts_with('#') && !rest.starts_with(';') {
+        return Err(anyhow!("unexpected text after ']': {}", rest));
+    }
+
+    // Old style [name.subsection] is the same as [name "subsection"]
+    let mut category = Category::from_key(&name);
+    if let Some(subsection) = subsection {
+        if category.subsection.is_some() {
+            return Err(anyhow!("both dotted and quoted subsection in '{}'", name));
+        }
+        category.subsection = Some(subsection);
+    }
+    if !category
+        .name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+        || category.name.is_empty()
+    {
+        return Err(anyhow!("invalid category name '{}'", category.name));
+    }
+    Ok(category)
+}
+
+/// true/yes/on/1 and false/no/off/0, case insensitive
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Integer with an optional k, m or g suffix (powers of 1024)
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, multiplier) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.parse::<i64>().ok()?.checked_mul(multiplier)
+}
+
+/// Same as parse_int, but it can't be negative, e.g. "10m"
+pub fn parse_size(value: &str) -> Option<u64> {
+    parse_int(value).and_then(|size| u64::try_from(size).ok())
+}
 
 pub struct Config {
-    variables: HashMap<String, HashMap<String, String>>, // Category -> [<var-name> <var-value, ...]
+    lines: Vec<ConfigLine>, // in file order, so the file can be written back as it was
+    filepath: Option<PathBuf>,
 }
 
 impl Config {
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
+            lines: Vec::new(),
+            filepath: None,
         }
     }
 
-    // TODO: Handle all Config::parse() cases!!, then base.rs
     pub fn parse(data: String) -> anyhow::Result<Config> {
         let mut config = Config::new();
-        let mut current_category = String::from("unnamed");
+        let mut current_category: Option<Category> = None;
         let reader = BufReader::new(Cursor::new(data));
 
-        for line in reader.lines() {
-            let line = line?;
-            let line = line.trim();
+        for (line_idx, line) in reader.lines().enumerate() {
+            let line_number = line_idx + 1;
+            let raw = line?;
+            let line = raw.trim();
 
-            if line.starts_with('[') && line.ends_with(']') {
-                let category_name = &line[1..line.len() - 1];
-                current_category = category_name.to_owned().to_lowercase();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                config.lines.push(ConfigLine::Other(raw));
+            } else if line.starts_with('[') {
+                let category =
+                    parse_header(line).map_err(|why| anyhow!("line {}: {}", line_number, why))?;
+                current_category = Some(category.clone());
+                config.lines.push(ConfigLine::Section { category, raw });
             } else {
-                if let Some(equal_pos) = line.chars().position(|ch| ch == '=') {
-                    let name = &line[0..equal_pos];
-                    let value = &line[equal_pos + 1..];
-                    config.add(&current_category, &name.to_lowercase(), value);
-                } else {
-                    return Err(anyhow!("Malformed line: {}", line));
+                let category = current_category.clone().ok_or(anyhow!(
+                    "line {}: variable outside of a [Category]",
+                    line_number
+                ))?;
+
+                let (name, value) = match line.split_once('=') {
+                    Some((name, value)) => (
+                        name.trim(),
+                        parse_value(value)
+                            .map_err(|why| anyhow!("line {}: {}", line_number, why))?,
+                    ),
+                    // A key without a value is a boolean flag
+                    None => (line, String::from("true")),
+                };
+                if !is_valid_name(name) {
+                    return Err(anyhow!("line {}: invalid key name '{}'", line_number, name));
                 }
+
+                config.lines.push(ConfigLine::Entry(ConfigEntry {
+                    category,
+                    key: name.to_owned(),
+                    value,
+                    raw: Some(raw.clone()),
+                    line_number,
+                    source: None,
+                }));
             }
         }
         Ok(config)
     }
 
-    /// category_name must be CamelCase, name is also CamelCase
+    fn set_source(&mut self, filepath: &Path) {
+        self.filepath = Some(filepath.to_path_buf());
+        for line in self.lines.iter_mut() {
+            if let ConfigLine::Entry(entry) = line {
+                entry.source = Some(filepath.to_path_buf());
+            }
+        }
+    }
+
+    /// Config read from filepath, or an empty one if there is no such file yet
+    pub fn load(filepath: &Path) -> anyhow::Result<Config> {
+        match File::open(filepath) {
+            Ok(mut file) => {
+                let mut contents = Vec::new();
+                file.read_to_end(&mut contents)?;
+
+                let mut config = Config::from_raw_data(&contents)
+                    .map_err(|why| anyhow!("{}: {}", filepath.to_string_lossy(), why))?;
+                config.set_source(filepath);
+                Ok(config)
+            }
+            Err(why) if why.kind() == ErrorKind::NotFound => {
+                let mut config = Config::new();
+                config.set_source(filepath);
+                Ok(config)
+            }
+            Err(why) => Err(anyhow!(
+                "Could not open config '{}': {}",
+                filepath.to_string_lossy(),
+                why
+            )),
+        }
+    }
+
+    /// Adds one more value of the variable (keys can have several).
+    /// category_name is case insensitive, but the "<Category>.<subsection>" part is not
     pub fn add(&mut self, category_name: &str, name: &str, value: &str) {
-        self.variables
-            .entry(category_name.to_lowercase())
-            .or_insert(HashMap::new())
-            .insert(name.to_lowercase(), value.to_owned());
+        let entry = ConfigLine::Entry(ConfigEntry {
+            category: Category::from_key(category_name),
+            key: name.to_owned(),
+            value: value.to_owned(),
+            raw: None,
+            line_number: 0,
+            source: self.filepath.clone(),
+        });
+
+        // Put it right after the last line of the category, if there is one
+        let mut insert_at = None;
+        for (idx, line) in self.lines.iter().enumerate() {
+            let category = match line {
+                ConfigLine::Section { category, .. } => category,
+                ConfigLine::Entry(entry) => &entry.category,
+                ConfigLine::Other(_) => continue,
+            };
+            if category.matches(category_name) {
+                insert_at = Some(idx + 1);
+            }
+        }
+
+        match insert_at {
+            Some(idx) => self.lines.insert(idx, entry),
+            None => {
+                let category = Category::from_key(category_name);
+                self.lines.push(ConfigLine::Section {
+                    raw: category.to_header(),
+                    category,
+                });
+                self.lines.push(entry);
+            }
+        }
     }
 
-    /// This is case sensitive: category_name must be CamelCase, name is also CamelCase
+    /// Changes the last value of the variable or adds a new one
+    pub fn set(&mut self, category_name: &str, name: &str, new_value: &str) {
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            ConfigLine::Entry(entry) if entry.matches(category_name, name) => Some(entry),
+            _ => None,
+        });
+
+        match existing {
+            Some(entry) => {
+                entry.value = new_value.to_owned();
+                entry.raw = None;
+            }
+            None => self.add(category_name, name, new_value),
+        }
+    }
+
+    /// Removes every value of the variable, returns false if there were none
+    pub fn unset(&mut self, category_name: &str, name: &str) -> bool {
+        let len = self.lines.len();
+        self.lines.retain(
+            |line| !matches!(line, ConfigLine::Entry(entry) if entry.matches(category_name, name)),
+        );
+        len != self.lines.len()
+    }
+
+    fn last_entry(&self, category_name: &str, name: &str) -> Option<&ConfigEntry> {
+        self.lines.iter().rev().find_map(|line| match line {
+            ConfigLine::Entry(entry) if entry.matches(category_name, name) => Some(entry),
+            _ => None,
+        })
+    }
+
+    /// The last value wins if there are several
     pub fn get(&self, category_name: &str, name: &str) -> Option<String> {
-        if let Some(vars) = self.variables.get(&category_name.to_lowercase()) {
-            return vars.get(&name.to_lowercase()).map(|s| s.to_string());
+        self.last_entry(category_name, name)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Every value of a multi-valued variable, in file order
+    pub fn get_all(&self, category_name: &str, name: &str) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                ConfigLine::Entry(entry) if entry.matches(category_name, name) => {
+                    Some(entry.value.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bool(&self, category_name: &str, name: &str) -> anyhow::Result<Option<bool>> {
+        self.last_entry(category_name, name)
+            .map(|entry| entry.parse_typed("boolean", parse_bool))
+            .transpose()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_int(&self, category_name: &str, name: &str) -> anyhow::Result<Option<i64>> {
+        self.last_entry(category_name, name)
+            .map(|entry| entry.parse_typed("integer", parse_int))
+            .transpose()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self, category_name: &str, name: &str) -> anyhow::Result<Option<u64>> {
+        self.last_entry(category_name, name)
+            .map(|entry| entry.parse_typed("size", parse_size))
+            .transpose()
+    }
+
+    /// Names of all subsections of a category, e.g. "origin" for [Remote "origin"]
+    #[allow(dead_code)]
+    pub fn subsections(&self, category_name: &str) -> Vec<String> {
+        let mut result: Vec<String> = Vec::new();
+        for line in &self.lines {
+            let category = match line {
+                ConfigLine::Section { category, .. } => category,
+                ConfigLine::Entry(entry) => &entry.category,
+                ConfigLine::Other(_) => continue,
+            };
+            if let Some(subsection) = &category.subsection
+                && category.name.eq_ignore_ascii_case(category_name)
+                && !result.contains(subsection)
+            {
+                result.push(subsection.clone());
+            }
         }
-        None
+        result
     }
-    pub fn get_int(&self, category_name: &str, name: &str) -> Option<i32> {
-        self.variables
-            .get(&category_name.to_lowercase())
-            .and_then(|vars| vars.get(&name.to_lowercase()).and_then(|s| s.parse::<i32>().ok()))
+
+    /// (category, name, value) of every variable, in file order
+    pub fn entries(&self) -> Vec<(String, String, String)> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                ConfigLine::Entry(entry) => Some((
+                    entry.category.to_key(),
+                    entry.key.clone(),
+                    entry.value.clone(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Appends all lines of other, so its values take precedence
+    pub fn merge(&mut self, other: Config) {
+        self.lines.extend(other.lines);
     }
 }
 
+/// Splits "Category.Name" or "Category.subsection.Name" into category and name
+pub fn split_key(key: &str) -> anyhow::Result<(&str, &str)> {
+    match key.rsplit_once('.') {
+        Some((category, name)) if !category.is_empty() && is_valid_name(name) => {
+            Ok((category, name))
+        }
+        _ => Err(anyhow!(
+            "Key '{}' should look like <Category>.<Name> or <Category>.<subsection>.<Name>",
+            key
+        )),
+    }
+}
+
+fn env_overrides() -> anyhow::Result<Config> {
+    let mut config = Config::new();
+    let count = match std::env::var("GILLTTER_CONFIG_COUNT") {
+        Ok(count) => count
+            .parse::<usize>()
+            .map_err(|why| anyhow!("Bad GILLTTER_CONFIG_COUNT: {}", why))?,
+        Err(_) => 0,
+    };
+    for idx in 0..count {
+        let key = std::env::var(format!("GILLTTER_CONFIG_KEY_{}", idx))
+            .map_err(|_| anyhow!("GILLTTER_CONFIG_KEY_{} is not set", idx))?;
+        let value = std::env::var(format!("GILLTTER_CONFIG_VALUE_{}", idx)).unwrap_or_default();
+        let (category, name) = split_key(&key)?;
+        config.lines.push(ConfigLine::Entry(ConfigEntry {
+            category: Category::from_key(category),
+            key: name.to_owned(),
+            value,
+            raw: None,
+            line_number: 0,
+            source: None,
+        }));
+    }
+    Ok(config)
+}
+
+/// System, global and local configs plus environment overrides, the last one wins
+pub fn load_layered() -> anyhow::Result<Config> {
+    let mut config = Config::new();
+    for scope in [ConfigScope::System, ConfigScope::Global, ConfigScope::Local] {
+        if let Some(path) = scope.path() {
+            config.merge(Config::load(&path)?);
+        }
+    }
+    config.merge(env_overrides()?);
+    Ok(config)
+}
+
 impl ObjectPump for Config {
     fn from_raw_data(data: &[u8]) -> anyhow::Result<Self> {
         let data = String::from_utf8_lossy(data).to_string();
@@ -90,9 +656,159 @@ impl ObjectPump for Config {
                 let mut contents = Vec::new();
                 file.read_to_end(&mut contents)?;
 
-                Config::from_raw_data(&contents)
+                let mut config = Config::from_raw_data(&contents)
+                    .map_err(|why| anyhow!("{}: {}", filepath.to_string_lossy(), why))?;
+                config.set_source(filepath);
+                Ok(config)
             }
             Err(why) => Err(anyhow!("Could not open file: {}", why)),
         }
     }
 }
+
+impl ObjectDump for Config {
+    fn convert_to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for line in &self.lines {
+            writeln!(&mut bytes, "{}", line.to_text())?;
+        }
+        Ok(bytes)
+    }
+    /// Writes the config back to the file it was loaded from
+    fn dump_to_file(&self) -> anyhow::Result<String> {
+        let path = self
+            .filepath
+            .as_ref()
+            .ok_or(anyhow!("Config was not loaded from a file"))?;
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(&self.convert_to_bytes()?)?;
+        file.flush()?;
+        Ok(path.to_string_lossy().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_keeps_comments_and_order() {
+        let text = "# who am i\n[General]\nUsername=pencil\n\n; mail\nEmail=a@b.c\n[Core]\nExcludesFile=~/ignore\n";
+        let config = Config::parse(text.to_string()).unwrap();
+        let bytes = config.convert_to_bytes().unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), text);
+    }
+
+    #[test]
+    fn set_and_unset() {
+        let text = "[General]\n# name\nUsername=pencil\n[Core]\nExcludesFile=x\n";
+        let mut config = Config::parse(text.to_string()).unwrap();
+        config.set("general", "username", "legend");
+        config.set("General", "Email", "a@b.c");
+        config.set("Alias", "st", "status");
+        assert!(config.unset("Core", "ExcludesFile"));
+        assert!(!config.unset("Core", "ExcludesFile"));
+
+        let bytes = config.convert_to_bytes().unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "[General]\n# name\nUsername=legend\nEmail=a@b.c\n[Core]\n[Alias]\nst=status\n"
+        );
+    }
+
+    #[test]
+    fn later_layer_wins() {
+        let mut config =
+            Config::parse("[General]\nUsername=global\nEmail=g@g".to_string()).unwrap();
+        config.merge(Config::parse("[General]\nUsername=local".to_string()).unwrap());
+        assert_eq!(config.get("General", "Username").unwrap(), "local");
+        assert_eq!(config.get("General", "Email").unwrap(), "g@g");
+    }
+
+    #[test]
+    fn quoted_values_and_inline_comments() {
+        let text = "[Core]\n  Pager = \"less -R\"  # inline\nMsg=\"a \\\"quoted\\\" # not comment\\n\"\nTrailing = spaced out   ; comment\nFlag\n";
+        let config = Config::parse(text.to_string()).unwrap();
+        assert_eq!(config.get("core", "pager").unwrap(), "less -R");
+        assert_eq!(
+            config.get("Core", "Msg").unwrap(),
+            "a \"quoted\" # not comment\n"
+        );
+        assert_eq!(config.get("Core", "Trailing").unwrap(), "spaced out");
+        assert_eq!(config.get_bool("Core", "Flag").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn typed_values() {
+        let text = "[Core]\nBig=10m\nSmall=1k\nNum=-3\nYes=on\nNo=False\nBad=maybe\n";
+        let config = Config::parse(text.to_string()).unwrap();
+        assert_eq!(
+            config.get_size("Core", "Big").unwrap(),
+            Some(10 * 1024 * 1024)
+        );
+        assert_eq!(config.get_int("Core", "Small").unwrap(), Some(1024));
+        assert_eq!(config.get_int("Core", "Num").unwrap(), Some(-3));
+        assert!(config.get_size("Core", "Num").is_err());
+        assert_eq!(config.get_bool("Core", "Yes").unwrap(), Some(true));
+        assert_eq!(config.get_bool("Core", "No").unwrap(), Some(false));
+        assert_eq!(config.get_bool("Core", "Missing").unwrap(), None);
+
+        let err = config.get_bool("Core", "Bad").unwrap_err().to_string();
+        assert!(err.contains("line 7"), "{}", err);
+    }
+
+    #[test]
+    fn subsections_and_multiple_values() {
+        let text = "[Remote \"origin\"]\nUrl=/srv/a\nFetch=main\nFetch=dev\n[Remote \"Backup\"]\nUrl=/srv/b\n";
+        let mut config = Config::parse(text.to_string()).unwrap();
+        assert_eq!(config.get("remote.origin", "url").unwrap(), "/srv/a");
+        assert_eq!(config.get("Remote.backup", "Url"), None);
+        assert_eq!(
+            config.get_all("Remote.origin", "Fetch"),
+            vec!["main", "dev"]
+        );
+        assert_eq!(config.subsections("remote"), vec!["origin", "Backup"]);
+
+        config.add("Remote.origin", "Fetch", "stable");
+        config.set("Remote.new one", "Url", "has # hash");
+        let bytes = config.convert_to_bytes().unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains("Fetch=dev\nFetch=stable\n[Remote \"Backup\"]"));
+        assert!(text.ends_with("[Remote \"new one\"]\nUrl=\"has # hash\"\n"));
+
+        let reparsed = Config::parse(text).unwrap();
+        assert_eq!(reparsed.get("Remote.new one", "Url").unwrap(), "has # hash");
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        for (text, line) in [
+            ("[General]\nUsername=\"open\n", "line 2"),
+            ("# c\n\n[Bad name\n", "line 3"),
+            ("key=value\n", "line 1"),
+            ("[General]\nbad key=1\n", "line 2"),
+        ] {
+            let err = Config::parse(text.to_string()).err().unwrap().to_string();
+            assert!(err.starts_with(line), "{}: {}", text, err);
+        }
+    }
+
+    #[test]
+    fn headers_are_strict() {
+        let config = Config::parse("[ Remote  \"a b\" ]\nUrl=x\n".to_string()).unwrap();
+        assert_eq!(config.get("Remote.a b", "Url").unwrap(), "x");
+        for header in ["[Gen eral]", "[Remote \"a\" \"b\"]", "[Remote \"a\"\"b\"]"] {
+            assert!(parse_header(header).is_err(), "{}", header);
+        }
+    }
+}
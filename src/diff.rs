@@ -0,0 +1,414 @@
+// Line based diffs. Lines are matched by their longest common subsequence (Myers' algorithm),
+// unmatched lines of the old text are removed, unmatched lines of the new one are added.
+// Unified diffs of files look like:
+/*
+ * diff --gilltter a/<path> b/<path>
+ * --- a/<path>                  (/dev/null for added files)
+ * +++ b/<path>                  (/dev/null for deleted files)
+ * @@ -<old start>,<old count> +<new start>,<new count> @@
+ *  context line
+ * -removed line
+ * +added line
+ */
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use crate::{
+    base::object_path,
+    objects::{ObjectPump, blob::Blob},
+};
+
+const CONTEXT_LINES: usize = 3;
+// Above this many edits in one stretch lines are matched only roughly
+const MAX_EDIT_COST: usize = 1024;
+const NO_NEWLINE_MARKER: &'static str = "\\ No newline at end of file";
+const NULL_PATH: &'static str = "/dev/null";
+
+/// Splits data into lines, every line keeps its '\n'
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|byte| *byte == b'\n').collect()
+}
+
+/// For every line of `a` the index of the line of `b` it is matched with, if any.
+/// Matches are increasing, so they describe a common subsequence, the longest one
+/// unless the texts differ in more than about `MAX_EDIT_COST` lines
+pub fn match_lines(a: &[&[u8]], b: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; a.len()];
+    match_range(a, b, 0, 0, &mut matches);
+    matches
+}
+
+// Matches a[..] against b[..], which start at a_start and b_start of the whole texts.
+// Myers' algorithm in linear space: common prefix and suffix are matched right away,
+// the middle is split around a snake of the shortest edit script and both sides recurse
+fn match_range(
+    a: &[&[u8]],
+    b: &[&[u8]],
+    a_start: usize,
+    b_start: usize,
+    matches: &mut [Option<usize>],
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    for i in 0..prefix {
+        matches[a_start + i] = Some(b_start + i);
+    }
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    for i in 1..=suffix {
+        matches[a_start + a.len() - i] = Some(b_start + b.len() - i);
+    }
+
+    let a = &a[prefix..a.len() - suffix];
+    let b = &b[prefix..b.len() - suffix];
+    if a.is_empty() || b.is_empty() {
+        return;
+    }
+    let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+
+    let (x0, y0, x1, y1) = middle_snake(a, b);
+    match_range(&a[..x0], &b[..y0], a_start, b_start, matches);
+    for i in 0..x1 - x0 {
+        matches[a_start + x0 + i] = Some(b_start + y0 + i);
+    }
+    match_range(&a[x1..], &b[y1..], a_start + x1, b_start + y1, matches);
+}
+
+// Snake (x0, y0)..(x1, y1) in the middle of a shortest edit script of a and b, which
+// are non empty and differ in the first and in the last line. When the script is longer
+// than twice MAX_EDIT_COST the texts are split in their middles instead,
+// which may be off the shortest script but keeps the work bounded
+fn middle_snake(a: &[&[u8]], b: &[&[u8]]) -> (usize, usize, usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let max = ((n + m + 1) / 2).min(MAX_EDIT_COST as isize);
+    let offset = max + 1;
+    // forward[k] is the furthest x on diagonal k = x - y from the start,
+    // backward[k] the furthest x on diagonal k going back from the end, counted from the end
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+    let at = |k: isize| (k + offset) as usize;
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && a[x as usize] == b[(x - k) as usize] {
+                x += 1;
+            }
+            forward[at(k)] = x;
+            let back_k = delta - k;
+            if delta % 2 != 0
+                && (-(d - 1)..=d - 1).contains(&back_k)
+                && x + backward[at(back_k)] >= n
+            {
+                return (x0 as usize, y0 as usize, x as usize, (x - k) as usize);
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)]
+            } else {
+                backward[at(k - 1)] + 1
+            };
+            let (x0, y0) = (x, x - k);
+            while x < n && x - k < m && a[(n - 1 - x) as usize] == b[(m - 1 - (x - k)) as usize] {
+                x += 1;
+            }
+            backward[at(k)] = x;
+            let forward_k = delta - k;
+            if delta % 2 == 0 && (-d..=d).contains(&forward_k) && x + forward[at(forward_k)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - (x - k)) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+    }
+
+    // Too expensive, split both texts in the middle. Only reached for more than
+    // 2 * MAX_EDIT_COST lines, so both halves are smaller
+    let (x, y) = (a.len() / 2, b.len() / 2);
+    (x, y, x, y)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Equal(usize, usize), // old line, new line
+    Delete(usize),       // old line
+    Insert(usize),       // new line
+}
+
+impl Edit {
+    fn is_change(&self) -> bool {
+        !matches!(self, Self::Equal(..))
+    }
+}
+
+/// Edit script turning `old` into `new`, removals of a region go before its additions
+pub fn edits(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut next_new = 0;
+    for (i, matched) in match_lines(old, new).into_iter().enumerate() {
+        match matched {
+            Some(j) => {
+                edits.extend((next_new..j).map(Edit::Insert));
+                edits.push(Edit::Equal(i, j));
+                next_new = j + 1;
+            }
+            None => edits.push(Edit::Delete(i)),
+        }
+    }
+    edits.extend((next_new..new.len()).map(Edit::Insert));
+    edits
+}
+
+// Hunk header range: 1-based start, the count is omitted when it is 1
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+fn push_line(diff: &mut String, prefix: char, line: &[u8]) {
+    diff.push(prefix);
+    diff.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        diff.push('\n');
+        diff.push_str(NO_NEWLINE_MARKER);
+        diff.push('\n');
+    }
+}
+
+/// Hunks of the unified diff between two texts, empty if they are the same
+pub fn unified_diff(old: &[u8], new: &[u8]) -> String {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = edits(&old_lines, &new_lines);
+
+    // Changes closer than two contexts share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (pos, _) in edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| edit.is_change())
+    {
+        let start = pos.saturating_sub(CONTEXT_LINES);
+        match hunks.last_mut() {
+            Some((_, end)) if start <= *end + CONTEXT_LINES => *end = pos + 1,
+            _ => hunks.push((start, pos + 1)),
+        }
+    }
+
+    let mut diff = String::new();
+    for (start, end) in hunks {
+        let end = (end + CONTEXT_LINES).min(edits.len());
+        let hunk = &edits[start..end];
+        // Lines of each side before the hunk
+        let old_start = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_start = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+        let old_count = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete(_)))
+            .count();
+
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        for edit in hunk {
+            match *edit {
+                Edit::Equal(i, _) => push_line(&mut diff, ' ', old_lines[i]),
+                Edit::Delete(i) => push_line(&mut diff, '-', old_lines[i]),
+                Edit::Insert(j) => push_line(&mut diff, '+', new_lines[j]),
+            }
+        }
+    }
+    diff
+}
+
+fn read_blob(sha: Option<&String>) -> anyhow::Result<Vec<u8>> {
+    match sha {
+        Some(sha) => Ok(Blob::from_file(&object_path(sha))?.get_data()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Unified diff of one file between two blobs, None meaning the file does not exist
+pub fn file_diff(path: &str, old: Option<&String>, new: Option<&String>) -> anyhow::Result<String> {
+    let mut diff = format!("diff --gilltter a/{} b/{}\n", path, path);
+    match (old, new) {
+        (None, Some(_)) => diff.push_str("new file\n"),
+        (Some(_), None) => diff.push_str("deleted file\n"),
+        _ => {}
+    }
+
+    let (old_data, new_data) = (read_blob(old)?, read_blob(new)?);
+    if old_data.contains(&0) || new_data.contains(&0) {
+        diff.push_str(&format!("Binary files a/{} and b/{} differ\n", path, path));
+        return Ok(diff);
+    }
+    let old_name = match old {
+        Some(_) => format!("a/{}", path),
+        None => NULL_PATH.to_string(),
+    };
+    let new_name = match new {
+        Some(_) => format!("b/{}", path),
+        None => NULL_PATH.to_string(),
+    };
+    diff.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+    diff.push_str(&unified_diff(&old_data, &new_data));
+    Ok(diff)
+}
+
+/// Diffs of every file which differs between two file lists (path -> blob hash)
+pub fn tree_diff(
+    old: &BTreeMap<PathBuf, String>,
+    new: &BTreeMap<PathBuf, String>,
+) -> anyhow::Result<String> {
+    let paths: BTreeSet<&PathBuf> = old.keys().chain(new.keys()).collect();
+    let mut diff = String::new();
+    for path in paths {
+        let (old_sha, new_sha) = (old.get(path), new.get(path));
+        if old_sha != new_sha {
+            diff.push_str(&file_diff(&path.to_string_lossy(), old_sha, new_sha)?);
+        }
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_match_in_order() {
+        let a = split_lines(b"a\nb\nc\nd\n");
+        let b = split_lines(b"b\nx\nd\n");
+        assert_eq!(match_lines(&a, &b), vec![None, Some(0), None, Some(2)]);
+    }
+
+    // Length of the longest common subsequence, from the full table
+    fn lcs_length(a: &[&[u8]], b: &[&[u8]]) -> usize {
+        let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i][j] = if a[i] == b[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+        lengths[0][0]
+    }
+
+    fn assert_valid(a: &[&[u8]], b: &[&[u8]], matches: &[Option<usize>]) {
+        let matched: Vec<(usize, usize)> = matches
+            .iter()
+            .enumerate()
+            .filter_map(|(i, j)| j.map(|j| (i, j)))
+            .collect();
+        assert!(matched.windows(2).all(|w| w[0].1 < w[1].1));
+        assert!(matched.iter().all(|&(i, j)| a[i] == b[j]));
+    }
+
+    #[test]
+    fn matches_are_longest() {
+        // Small alphabets give many equally long candidates
+        let mut seed = 7u32;
+        let mut text = |len: usize| -> String {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    format!("{}\n", (seed >> 16) % 4)
+                })
+                .collect()
+        };
+        for len in 0..40 {
+            let (old, new) = (text(len), text(40 - len));
+            let (a, b) = (split_lines(old.as_bytes()), split_lines(new.as_bytes()));
+            let matches = match_lines(&a, &b);
+            assert_valid(&a, &b, &matches);
+            assert_eq!(matches.iter().flatten().count(), lcs_length(&a, &b));
+        }
+    }
+
+    #[test]
+    fn rewritten_big_texts_are_matched() {
+        let old: String = (0..10_000).map(|n| format!("old {}\n", n)).collect();
+        let new: String = (0..10_000)
+            .map(|n| match n % 200 {
+                0 => format!("old {}\n", n),
+                _ => format!("new {}\n", n),
+            })
+            .collect();
+        let (a, b) = (split_lines(old.as_bytes()), split_lines(new.as_bytes()));
+        let matches = match_lines(&a, &b);
+        assert_valid(&a, &b, &matches);
+        assert_eq!(matches.iter().flatten().count(), 50);
+    }
+
+    #[test]
+    fn same_texts_have_no_diff() {
+        assert_eq!(unified_diff(b"a\nb\n", b"a\nb\n"), "");
+    }
+
+    #[test]
+    fn hunk_has_context_around_changes() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n";
+        assert_eq!(
+            unified_diff(old.as_bytes(), new.as_bytes()),
+            "@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+        );
+    }
+
+    #[test]
+    fn far_changes_get_separate_hunks() {
+        let old: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let new: String = (1..=20)
+            .map(|n| match n {
+                2 => "two\n".to_string(),
+                19 => "nineteen\n".to_string(),
+                n => format!("{}\n", n),
+            })
+            .collect();
+        let diff = unified_diff(old.as_bytes(), new.as_bytes());
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.starts_with("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n"));
+    }
+
+    #[test]
+    fn added_file_and_missing_newline() {
+        assert_eq!(
+            unified_diff(b"", b"a\nb"),
+            "@@ -0,0 +1,2 @@\n+a\n+b\n\\ No newline at end of file\n"
+        );
+    }
+}
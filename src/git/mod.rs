@@ -0,0 +1,325 @@
+// Reading of Git repositories, for import-git. Objects are loose (objects/ab/cdef...,
+// zlib of "<type> <size>\0<data>") or in packs, see pack.rs. Refs are loose files under
+// refs/ or lines of packed-refs.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{git::pack::Pack, utils};
+
+pub mod pack;
+
+const GIT_DIR: &'static str = ".git";
+const HEAD_FILE: &'static str = "HEAD";
+const PACKED_REFS_FILE: &'static str = "packed-refs";
+const REFS_DIR: &'static str = "refs";
+pub const BRANCH_REF_PREFIX: &'static str = "refs/heads/";
+pub const TAG_REF_PREFIX: &'static str = "refs/tags/";
+pub const SYMBOLIC_REF_PREFIX: &'static str = "ref: ";
+pub const DIRECTORY_MODE: &'static str = "40000";
+pub const SUBMODULE_MODE: &'static str = "160000";
+
+// Kind of an object with its data, without the header
+pub type GitObject = (ObjectKind, Vec<u8>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectKind {
+    pub fn from_pack_type(kind: u8) -> anyhow::Result<Self> {
+        match kind {
+            1 => Ok(Self::Commit),
+            2 => Ok(Self::Tree),
+            3 => Ok(Self::Blob),
+            4 => Ok(Self::Tag),
+            _ => Err(anyhow!("Unknown object type {} in a pack", kind)),
+        }
+    }
+
+    fn from_name(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "commit" => Ok(Self::Commit),
+            "tree" => Ok(Self::Tree),
+            "blob" => Ok(Self::Blob),
+            "tag" => Ok(Self::Tag),
+            _ => Err(anyhow!("Unknown object type '{}'", name)),
+        }
+    }
+}
+
+pub struct GitCommit {
+    pub tree: String,
+    pub parents: Vec<String>,
+    pub author_name: String,
+    pub author_email: String,
+    pub author_time: u64,
+    pub message: String,
+}
+
+pub struct GitTreeEntry {
+    pub mode: String,
+    pub name: String,
+    pub sha: String,
+}
+
+pub struct GitRepository {
+    dir: PathBuf,
+    packs: Vec<Pack>,
+}
+
+impl GitRepository {
+    /// `path` is a work tree with .git inside or a bare repository
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let dir = if path.join(GIT_DIR).is_dir() {
+            path.join(GIT_DIR)
+        } else {
+            path.to_path_buf()
+        };
+        if !dir.join(HEAD_FILE).is_file() || !dir.join("objects").is_dir() {
+            return Err(anyhow!(
+                "'{}' is not a git repository",
+                path.to_string_lossy()
+            ));
+        }
+
+        let mut packs = Vec::new();
+        let pack_dir = dir.join("objects").join("pack");
+        if pack_dir.is_dir() {
+            for entry in fs::read_dir(pack_dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|extension| extension == "idx") {
+                    packs.push(Pack::open(&path)?);
+                }
+            }
+        }
+        Ok(Self { dir, packs })
+    }
+
+    pub fn read_object(&self, sha: &str) -> anyhow::Result<GitObject> {
+        let loose = self
+            .dir
+            .join("objects")
+            .join(&sha[..2.min(sha.len())])
+            .join(sha.get(2..).unwrap_or_default());
+        if loose.is_file() {
+            let mut data = utils::decompress(&fs::read(loose)?)?;
+            let header_end = data
+                .iter()
+                .position(|byte| *byte == 0)
+                .ok_or(anyhow!("Git object {} has no header", sha))?;
+            let header = String::from_utf8_lossy(&data[..header_end]).to_string();
+            let kind = header.split(' ').next().unwrap_or_default();
+            return Ok((ObjectKind::from_name(kind)?, data.split_off(header_end + 1)));
+        }
+        let read_any = |sha: &str| self.read_object(sha);
+        for pack in &self.packs {
+            if let Some(object) = pack.read(sha, &read_any)? {
+                return Ok(object);
+            }
+        }
+        Err(anyhow!("Git object {} is missing", sha))
+    }
+
+    /// Object of the expected kind
+    pub fn read_kind(&self, sha: &str, kind: ObjectKind) -> anyhow::Result<Vec<u8>> {
+        let (actual, data) = self.read_object(sha)?;
+        if actual != kind {
+            return Err(anyhow!(
+                "Git object {} is a {:?}, not a {:?}",
+                sha,
+                actual,
+                kind
+            ));
+        }
+        Ok(data)
+    }
+
+    fn read_loose_refs(
+        &self,
+        dir: &Path,
+        refs: &mut BTreeMap<String, String>,
+    ) -> anyhow::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.read_loose_refs(&path, refs)?;
+            } else {
+                let name = path
+                    .strip_prefix(&self.dir)?
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                let value = fs::read_to_string(&path)?.trim().to_string();
+                if !value.starts_with(SYMBOLIC_REF_PREFIX) {
+                    refs.insert(name, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Refs by full name (refs/heads/main) -> hash, loose ones win over packed ones
+    pub fn refs(&self) -> anyhow::Result<BTreeMap<String, String>> {
+        let mut refs = BTreeMap::new();
+        if let Ok(packed) = fs::read_to_string(self.dir.join(PACKED_REFS_FILE)) {
+            for line in packed.lines() {
+                // "# pack-refs with: ..." and "^<peeled hash>" lines are skipped
+                if let Some((sha, name)) = line.split_once(' ')
+                    && !line.starts_with('#')
+                    && !line.starts_with('^')
+                {
+                    refs.insert(name.to_string(), sha.to_string());
+                }
+            }
+        }
+        if self.dir.join(REFS_DIR).is_dir() {
+            self.read_loose_refs(&self.dir.join(REFS_DIR), &mut refs)?;
+        }
+        Ok(refs)
+    }
+
+    /// Contents of HEAD: "ref: refs/heads/<branch>" or a hash
+    pub fn head(&self) -> anyhow::Result<String> {
+        Ok(fs::read_to_string(self.dir.join(HEAD_FILE))?
+            .trim()
+            .to_string())
+    }
+
+    /// Commit a ref points to, through annotated tags. None for tags of other objects
+    pub fn peel(&self, sha: &str) -> anyhow::Result<Option<String>> {
+        let mut sha = sha.to_string();
+        loop {
+            match self.read_object(&sha)? {
+                (ObjectKind::Commit, _) => return Ok(Some(sha)),
+                (ObjectKind::Tag, data) => sha = tag_target(&data)?,
+                _ => return Ok(None),
+            }
+        }
+    }
+}
+
+// "Name <email> 1700000000 +0100"
+fn parse_person(value: &str) -> anyhow::Result<(String, String, u64)> {
+    let (name, rest) = value
+        .split_once('<')
+        .ok_or(anyhow!("Broken person '{}'", value))?;
+    let (email, rest) = rest
+        .split_once('>')
+        .ok_or(anyhow!("Broken person '{}'", value))?;
+    let time = rest
+        .split_whitespace()
+        .next()
+        .and_then(|time| time.parse::<i64>().ok())
+        .ok_or(anyhow!("Broken time of '{}'", value))?;
+    Ok((
+        name.trim().to_string(),
+        email.trim().to_string(),
+        time.max(0) as u64,
+    ))
+}
+
+pub fn parse_commit(data: &[u8]) -> anyhow::Result<GitCommit> {
+    let text = String::from_utf8_lossy(data);
+    let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+    let mut tree = None;
+    let mut parents = Vec::new();
+    let mut author = None;
+    for line in headers.lines() {
+        match line.split_once(' ') {
+            Some(("tree", sha)) => tree = Some(sha.to_string()),
+            Some(("parent", sha)) => parents.push(sha.to_string()),
+            Some(("author", person)) => author = Some(parse_person(person)?),
+            _ => {}
+        }
+    }
+    let (author_name, author_email, author_time) = author.ok_or(anyhow!("Commit has no author"))?;
+    Ok(GitCommit {
+        tree: tree.ok_or(anyhow!("Commit has no tree"))?,
+        parents,
+        author_name,
+        author_email,
+        author_time,
+        message: message.to_string(),
+    })
+}
+
+/// Entries of a tree: "<mode> <name>\0<20 byte hash>"...
+pub fn parse_tree(data: &[u8]) -> anyhow::Result<Vec<GitTreeEntry>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let nul = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(anyhow!("Broken tree entry"))?;
+        // The mode ends before the name, so the space must come before the NUL
+        let space = rest[..nul]
+            .iter()
+            .position(|byte| *byte == b' ')
+            .ok_or(anyhow!("Broken tree entry"))?;
+        let hash = rest
+            .get(nul + 1..nul + 21)
+            .ok_or(anyhow!("Tree ends too early"))?;
+        entries.push(GitTreeEntry {
+            mode: String::from_utf8_lossy(&rest[..space]).to_string(),
+            name: String::from_utf8_lossy(&rest[space + 1..nul]).to_string(),
+            sha: pack::hex(hash),
+        });
+        rest = &rest[nul + 21..];
+    }
+    Ok(entries)
+}
+
+/// Object an annotated tag points to
+pub fn tag_target(data: &[u8]) -> anyhow::Result<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .find_map(|line| line.strip_prefix("object "))
+        .map(|sha| sha.to_string())
+        .ok_or(anyhow!("Tag has no object"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_fields_are_parsed() {
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+parent 1111111111111111111111111111111111111111\n\
+parent 2222222222222222222222222222222222222222\n\
+author Pencil Case <pedosia@gmail.com> 1700000000 +0300\n\
+committer Someone <x@y> 1700000001 +0000\n\
+\n\
+Merge things\n\nbody\n";
+        let commit = parse_commit(data).unwrap();
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.author_name, "Pencil Case");
+        assert_eq!(commit.author_email, "pedosia@gmail.com");
+        assert_eq!(commit.author_time, 1700000000);
+        assert_eq!(commit.message, "Merge things\n\nbody\n");
+    }
+
+    #[test]
+    fn tree_entries_are_parsed() {
+        let mut data = b"100644 a file\0".to_vec();
+        data.extend([0xab; 20]);
+        let entries = parse_tree(&data).unwrap();
+        assert_eq!(entries[0].mode, "100644");
+        assert_eq!(entries[0].name, "a file");
+        assert_eq!(entries[0].sha, "ab".repeat(20));
+
+        let mut broken = b"100644\0a b".to_vec();
+        broken.extend([0; 20]);
+        assert!(parse_tree(&broken).is_err());
+    }
+}
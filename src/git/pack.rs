@@ -0,0 +1,292 @@
+// Git packs: objects/pack/pack-<hash>.idx (version 2) maps hashes to offsets in the .pack.
+// Every object in the .pack starts with its type and size, then comes the zlib data.
+// Deltas hold instructions building the object from a base: an earlier object of the same
+// pack (ofs-delta) or any object named by hash (ref-delta).
+
+use std::{cell::RefCell, collections::HashMap, fs, io::Read, path::Path};
+
+use anyhow::anyhow;
+use flate2::read::ZlibDecoder;
+
+use crate::git::{GitObject, ObjectKind};
+
+const INDEX_MAGIC: &'static [u8] = b"\xfftOc";
+const INDEX_VERSION: u32 = 2;
+const FANOUT_LEN: usize = 256 * 4;
+const HASH_BYTES: usize = 20;
+const PACK_HEADER_LEN: usize = 12;
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+// Bases of deltas are kept while there are not too many
+const BASE_CACHE_LIMIT: usize = 1024;
+
+pub struct Pack {
+    offsets: HashMap<String, usize>,
+    data: Vec<u8>,
+    bases: RefCell<HashMap<usize, GitObject>>,
+}
+
+fn read_u32(data: &[u8], at: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(at..at + 4)
+        .ok_or(anyhow!("Pack index ends too early"))?;
+    Ok(u32::from_be_bytes(bytes.try_into()?))
+}
+
+fn read_offsets(index: &[u8]) -> anyhow::Result<HashMap<String, usize>> {
+    if !index.starts_with(INDEX_MAGIC) || read_u32(index, 4)? != INDEX_VERSION {
+        return Err(anyhow!("Only version 2 pack indexes are supported"));
+    }
+    let fanout_start = 8;
+    let count = read_u32(index, fanout_start + FANOUT_LEN - 4)? as usize;
+    let hashes_start = fanout_start + FANOUT_LEN;
+    let offsets_start = hashes_start + count * HASH_BYTES + count * 4; // after the crc32 table
+    let large_offsets_start = offsets_start + count * 4;
+
+    let mut offsets = HashMap::with_capacity(count);
+    for i in 0..count {
+        let hash = index
+            .get(hashes_start + i * HASH_BYTES..hashes_start + (i + 1) * HASH_BYTES)
+            .ok_or(anyhow!("Pack index ends too early"))?;
+        let offset = read_u32(index, offsets_start + i * 4)?;
+        let offset = if offset & LARGE_OFFSET_FLAG == 0 {
+            offset as usize
+        } else {
+            let at = large_offsets_start + (offset & !LARGE_OFFSET_FLAG) as usize * 8;
+            let high = read_u32(index, at)? as usize;
+            (high << 32) | read_u32(index, at + 4)? as usize
+        };
+        offsets.insert(hex(hash), offset);
+    }
+    Ok(offsets)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn byte_at(data: &[u8], at: usize) -> anyhow::Result<u8> {
+    data.get(at).copied().ok_or(anyhow!("Pack ends too early"))
+}
+
+// Adds the low 7 bits of a byte at `shift` to a variable length number
+fn add_size_bits(size: usize, byte: u8, shift: u32) -> anyhow::Result<usize> {
+    let bits = (byte & 0x7f) as usize;
+    match bits.checked_shl(shift) {
+        Some(shifted) if shifted >> shift == bits => Ok(size | shifted),
+        _ => Err(anyhow!("Size in the pack is too big")),
+    }
+}
+
+// Variable length size of a delta header
+fn read_delta_size(delta: &[u8], pos: &mut usize) -> anyhow::Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = byte_at(delta, *pos)?;
+        *pos += 1;
+        size = add_size_bits(size, byte, shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Builds an object from its base and a delta
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = read_delta_size(delta, &mut pos)?;
+    let result_size = read_delta_size(delta, &mut pos)?;
+    if base_size != base.len() {
+        return Err(anyhow!("Delta does not fit its base"));
+    }
+    // The claimed size is checked at the end, not trusted for an allocation
+    let mut result = Vec::new();
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // Copy from the base: which offset and size bytes follow is in the low bits
+            let (mut offset, mut size) = (0usize, 0usize);
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (byte_at(delta, pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (byte_at(delta, pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            result.extend(
+                base.get(offset..offset + size)
+                    .ok_or(anyhow!("Delta copies past its base"))?,
+            );
+        } else if op != 0 {
+            // Insert the next op bytes
+            let size = op as usize;
+            result.extend(
+                delta
+                    .get(pos..pos + size)
+                    .ok_or(anyhow!("Delta ends too early"))?,
+            );
+            pos += size;
+        } else {
+            return Err(anyhow!("Broken delta instruction"));
+        }
+    }
+    if result.len() != result_size {
+        return Err(anyhow!("Delta built an object of a wrong size"));
+    }
+    Ok(result)
+}
+
+impl Pack {
+    /// Loads the pack of the .idx file
+    pub fn open(index_path: &Path) -> anyhow::Result<Self> {
+        let offsets = read_offsets(&fs::read(index_path)?)
+            .map_err(|why| anyhow!("'{}': {}", index_path.to_string_lossy(), why))?;
+        let data = fs::read(index_path.with_extension("pack"))?;
+        if !data.starts_with(b"PACK") || data.len() < PACK_HEADER_LEN {
+            return Err(anyhow!(
+                "'{}' is not a pack",
+                index_path.with_extension("pack").to_string_lossy()
+            ));
+        }
+        Ok(Self {
+            offsets,
+            data,
+            bases: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Object with the hash, `read_any` reads objects from anywhere for ref-deltas
+    pub fn read(
+        &self,
+        sha: &str,
+        read_any: &dyn Fn(&str) -> anyhow::Result<GitObject>,
+    ) -> anyhow::Result<Option<GitObject>> {
+        match self.offsets.get(sha) {
+            Some(offset) => Ok(Some(self.read_at(*offset, read_any)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        read_any: &dyn Fn(&str) -> anyhow::Result<GitObject>,
+    ) -> anyhow::Result<GitObject> {
+        if let Some(object) = self.bases.borrow().get(&offset) {
+            return Ok(object.clone());
+        }
+
+        let mut pos = offset;
+        let mut byte = byte_at(&self.data, pos)?;
+        pos += 1;
+        let kind = (byte >> 4) & 0x7;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = byte_at(&self.data, pos)?;
+            pos += 1;
+            size = add_size_bits(size, byte, shift)?;
+            shift += 7;
+        }
+
+        let base = match kind {
+            OFS_DELTA => {
+                byte = byte_at(&self.data, pos)?;
+                pos += 1;
+                let mut distance = (byte & 0x7f) as usize;
+                while byte & 0x80 != 0 {
+                    byte = byte_at(&self.data, pos)?;
+                    pos += 1;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|distance| distance.checked_mul(1 << 7))
+                        .ok_or(anyhow!("Delta base is outside of the pack"))?
+                        | (byte & 0x7f) as usize;
+                }
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance != 0)
+                    .ok_or(anyhow!("Delta base is outside of the pack"))?;
+                let base = self.read_at(base_offset, read_any)?;
+                let mut bases = self.bases.borrow_mut();
+                if bases.len() >= BASE_CACHE_LIMIT {
+                    bases.clear();
+                }
+                bases.insert(base_offset, base.clone());
+                Some(base)
+            }
+            REF_DELTA => {
+                let hash = self
+                    .data
+                    .get(pos..pos + HASH_BYTES)
+                    .ok_or(anyhow!("Pack ends too early"))?;
+                pos += HASH_BYTES;
+                Some(read_any(&hex(hash))?)
+            }
+            _ => None,
+        };
+
+        let mut contents = Vec::new();
+        ZlibDecoder::new(&self.data[pos..])
+            .take((size as u64).saturating_add(1))
+            .read_to_end(&mut contents)?;
+        if contents.len() != size {
+            return Err(anyhow!("Object at {} in the pack is broken", offset));
+        }
+
+        match base {
+            Some((base_kind, base_data)) => Ok((base_kind, apply_delta(&base_data, &contents)?)),
+            None => Ok((ObjectKind::from_pack_type(kind)?, contents)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_copies_and_inserts() {
+        let base = b"hello, world";
+        // sizes 12 -> 13, copy 5 bytes from 0, insert "!!", copy 6 bytes from 6
+        let delta = [12, 13, 0x90, 5, 2, b'!', b'!', 0x91, 6, 6];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello!! world");
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+
+    #[test]
+    fn broken_sizes_are_errors() {
+        // A base size which never ends, then one longer than a usize
+        assert!(apply_delta(b"", &[0x80; 16]).is_err());
+        let mut delta = vec![0xff; 10];
+        delta.extend([0x7f, 0]);
+        assert!(apply_delta(b"", &delta).is_err());
+
+        let mut data = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+        data.extend([0xf0; 12]);
+        data.push(0x7f);
+        let mut pack = Pack {
+            offsets: HashMap::new(),
+            data,
+            bases: RefCell::new(HashMap::new()),
+        };
+        let no_objects = |sha: &str| -> anyhow::Result<GitObject> { Err(anyhow!("No {}", sha)) };
+        assert!(pack.read_at(PACK_HEADER_LEN, &no_objects).is_err());
+        // An ofs-delta with its base at its own offset
+        pack.data.truncate(PACK_HEADER_LEN);
+        pack.data.extend([OFS_DELTA << 4 | 1, 0]);
+        assert!(pack.read_at(PACK_HEADER_LEN, &no_objects).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+// Hooks are executables in .gilltter/hooks/ named after the point they run at.
+// They run in the top directory of the work tree:
+/*
+ * pre-commit                       before commit writes objects, fails the commit if it fails
+ * commit-msg <file>                the file holds the message, the hook may change it,
+ *                                  fails the commit if it fails
+ * post-commit                      after the commit, can't change its outcome
+ * post-checkout <old> <new> 1      after clone, bisect or rebase checked a commit out,
+ *                                  <old> is all zeros if there was no commit before
+ * pre-push <remote> <url>          before the objects are sent, fails the push if it fails;
+ *                                  stdin gets "<local> <sha> <remote ref> <remote sha>" lines
+ */
+// `commit --no-verify` and `push --no-verify` skip the hooks which can fail them.
+// A hook file which is not executable is ignored with a warning.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::anyhow;
+
+use crate::base::{GILLTTER_HOOKS_DIR, GILLTTER_PATH};
+
+pub const PRE_COMMIT_HOOK: &'static str = "pre-commit";
+pub const COMMIT_MSG_HOOK: &'static str = "commit-msg";
+pub const POST_COMMIT_HOOK: &'static str = "post-commit";
+pub const POST_CHECKOUT_HOOK: &'static str = "post-checkout";
+pub const PRE_PUSH_HOOK: &'static str = "pre-push";
+// Stands for a missing commit in hook arguments
+pub const NULL_HASH: &'static str = "0000000000000000000000000000000000000000";
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// Path of the hook if there is one to run
+fn hook_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(GILLTTER_PATH).join(GILLTTER_HOOKS_DIR).join(name);
+    if !path.is_file() {
+        return None;
+    }
+    if !is_executable(&path) {
+        eprintln!(
+            "The '{}' hook is ignored, it is not executable: chmod +x {}",
+            name,
+            path.to_string_lossy()
+        );
+        return None;
+    }
+    Some(path)
+}
+
+/// Runs the hook if there is one, with `input` on its stdin. Returns its exit code,
+/// None if there is no hook
+pub fn run_hook(name: &str, args: &[&str], input: Option<&[u8]>) -> anyhow::Result<Option<i32>> {
+    let Some(path) = hook_path(name) else {
+        return Ok(None);
+    };
+    let mut child = Command::new(&path)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
+        .spawn()
+        .map_err(|why| anyhow!("Could not run the '{}' hook: {}", name, why))?;
+    if let Some(input) = input
+        && let Some(mut stdin) = child.stdin.take()
+    {
+        // A hook which does not read its input may exit before taking all of it
+        let _ = stdin.write_all(input);
+    }
+    let status = child.wait()?;
+    Ok(Some(status.code().unwrap_or(1)))
+}
+
+/// Runs the hook if there is one, fails if the hook fails
+pub fn run_checked(name: &str, args: &[&str], input: Option<&[u8]>) -> anyhow::Result<()> {
+    match run_hook(name, args, input)? {
+        Some(code) if code != 0 => Err(anyhow!(
+            "The '{}' hook failed with exit code {}",
+            name,
+            code
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Runs a hook whose outcome changes nothing, a failure is only reported
+pub fn run_reported(name: &str, args: &[&str]) {
+    if let Err(why) = run_checked(name, args, None) {
+        eprintln!("{}", why);
+    }
+}
+
+/// Runs post-checkout after HEAD moved from `old` to `new`
+pub fn post_checkout(old: Option<&str>, new: &str) {
+    run_reported(POST_CHECKOUT_HOOK, &[old.unwrap_or(NULL_HASH), new, "1"]);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    #[test]
+    fn failing_hooks_fail() {
+        let repository = TempRepository::new();
+        assert_eq!(run_hook(PRE_COMMIT_HOOK, &[], None).unwrap(), None);
+
+        repository.hook(PRE_COMMIT_HOOK, "exit 3");
+        assert_eq!(run_hook(PRE_COMMIT_HOOK, &[], None).unwrap(), Some(3));
+        assert!(run_checked(PRE_COMMIT_HOOK, &[], None).is_err());
+
+        // Arguments and input reach the hook
+        repository.hook(
+            PRE_PUSH_HOOK,
+            "[ \"$1\" = origin ] && read line && [ \"$line\" = x ]",
+        );
+        run_checked(PRE_PUSH_HOOK, &["origin"], Some(b"x\n")).unwrap();
+        assert!(run_checked(PRE_PUSH_HOOK, &["other"], Some(b"x\n")).is_err());
+    }
+
+    #[test]
+    fn hooks_which_are_not_executable_are_ignored() {
+        let repository = TempRepository::new();
+        let path = repository.hook(PRE_COMMIT_HOOK, "exit 1");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(run_hook(PRE_COMMIT_HOOK, &[], None).unwrap(), None);
+        run_checked(PRE_COMMIT_HOOK, &[], None).unwrap();
+    }
+}
@@ -1,37 +1,398 @@
-use crate::base::GILLTTER_IGNORE_FILE;
+// Ignore rules follow gitignore semantics:
+/*
+ * - blank lines and lines starting with '#' are skipped, '\#' and '\!' escape them
+ * - '!' negates a pattern, a file inside an ignored directory can't be re-included
+ * - trailing '/' matches only directories
+ * - a '/' at the start or in the middle anchors a pattern to the .gignore dir,
+ *   otherwise the pattern matches a name at any level below it
+ * - '**' matches any number of directories, the last matching pattern wins
+ */
+
+use crate::{
+    base::{GILLTTER_EXCLUDE_FILE, GILLTTER_IGNORE_FILE, GILLTTER_INFO_DIR, GILLTTER_PATH},
+    config,
+};
 use anyhow::anyhow;
 use std::{
-    ffi::OsStr,
+    collections::HashMap,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, ErrorKind},
+    path::{Component, Path, PathBuf},
 };
 
-pub(crate) fn gilltter_get_ignorefile() -> anyhow::Result<Vec<String>> {
-    let mut result = Vec::new();
-    let file = File::open(GILLTTER_IGNORE_FILE)?;
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
 
-    let reader = BufReader::new(file);
-    for line in reader.lines().into_iter() {
-        let line = line?;
-        if line.starts_with('#') {
-            continue;
+#[derive(Clone, Debug)]
+pub(crate) struct IgnorePattern {
+    pattern: glob::Pattern,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+    base: PathBuf, // directory (relative to the project root) the pattern was read from
+    text: String,
+    source: PathBuf, // ignore file and line the pattern came from, for 'check-ignore -v'
+    line_number: usize,
+}
+
+impl IgnorePattern {
+    /// Returns None for lines which do not contain a pattern (blank lines and comments)
+    pub(crate) fn parse(line: &str, base: &Path) -> anyhow::Result<Option<Self>> {
+        let line = trim_trailing_spaces(line.trim_end_matches(['\r', '\n']));
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
         }
 
-        result.push(line);
+        let (negated, body) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let dir_only = body.ends_with('/');
+        let body = body.trim_end_matches('/');
+        let anchored = body.contains('/');
+        let body = body.strip_prefix('/').unwrap_or(body);
+        if body.is_empty() {
+            return Ok(None);
+        }
+
+        let pattern = glob::Pattern::new(&to_glob(body))
+            .map_err(|why| anyhow!("Invalid ignore pattern '{}': {}", line, why))?;
+        Ok(Some(Self {
+            pattern,
+            negated,
+            dir_only,
+            anchored,
+            base: base.to_path_buf(),
+            text: line.to_string(),
+            source: PathBuf::new(),
+            line_number: 0,
+        }))
+    }
+
+    /// path is relative to the project root
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            self.pattern
+                .matches_with(&relative.to_string_lossy(), MATCH_OPTIONS)
+        } else {
+            relative.file_name().is_some_and(|name| {
+                self.pattern
+                    .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+            })
+        }
+    }
+}
+
+// Trailing spaces are ignored unless they are escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+// Converts gitignore escapes to glob ones; '**' which is not a whole path component is just a '*'
+fn to_glob(pattern: &str) -> String {
+    pattern
+        .split('/')
+        .map(|component| {
+            if component == "**" {
+                return component.to_string();
+            }
+            let mut glob = String::new();
+            let mut chars = component.chars().peekable();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '\\' => match chars.next() {
+                        Some(escaped @ ('*' | '?' | '[' | ']')) => {
+                            glob.push('[');
+                            glob.push(escaped);
+                            glob.push(']');
+                        }
+                        Some(escaped) => glob.push(escaped),
+                        None => {}
+                    },
+                    '*' => {
+                        while chars.peek() == Some(&'*') {
+                            chars.next();
+                        }
+                        glob.push('*');
+                    }
+                    _ => glob.push(ch),
+                }
+            }
+            glob
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Drops "./" so user-supplied paths look like the ones we get from traversing the project
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+/// A missing ignore file is the same as an empty one
+pub(crate) fn read_ignorefile(filepath: &Path, base: &Path) -> anyhow::Result<Vec<IgnorePattern>> {
+    let file = match File::open(filepath) {
+        Ok(file) => file,
+        Err(why) if why.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(why) => {
+            return Err(anyhow!(
+                "Could not open ignore file '{}': {}",
+                filepath.to_string_lossy(),
+                why
+            ));
+        }
+    };
+
+    let mut result = Vec::new();
+    let reader = BufReader::new(file);
+    for (line_idx, line) in reader.lines().enumerate() {
+        if let Some(mut pattern) = IgnorePattern::parse(&line?, base)? {
+            pattern.source = filepath.to_path_buf();
+            pattern.line_number = line_idx + 1;
+            result.push(pattern);
+        }
     }
     Ok(result)
 }
 
-pub(crate) fn should_ignore(entry: &OsStr, ignore_files: &[String]) -> anyhow::Result<bool> {
-    let name = entry
-        .to_str()
-        .ok_or_else(|| anyhow!("Could not convert OsStr to &str"))?;
-    for el in ignore_files {
-        let pat = glob::Pattern::new(el)?; // может вернуть ошибку
-        if pat.matches(name) {
-            return Ok(true);
+fn global_excludes_file() -> Option<PathBuf> {
+    let configured = config::load_layered().ok().and_then(|config| {
+        config.get(
+            config::CONFIG_CORE_SECTION,
+            config::CONFIG_EXCLUDES_FILE_FIELD,
+        )
+    });
+    if let Some(path) = configured {
+        return match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+            (Some(rest), Some(home)) => Some(PathBuf::from(home).join(rest)),
+            _ => Some(PathBuf::from(path)),
+        };
+    }
+
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(xdg).join("gilltter").join("ignore"));
+    }
+    std::env::var_os("HOME").map(|home| {
+        PathBuf::from(home)
+            .join(".config")
+            .join("gilltter")
+            .join("ignore")
+    })
+}
+
+/// Build it once per command: ignore files are read and compiled once,
+/// nested .gignore files are loaded the first time a path below them is checked
+pub(crate) struct IgnoreMatcher {
+    patterns: Vec<IgnorePattern>, // global excludes file, .gilltter/info/exclude and the root .gignore
+    nested: HashMap<PathBuf, Vec<IgnorePattern>>, // dir -> patterns from its own .gignore
+}
+
+impl IgnoreMatcher {
+    pub(crate) fn new() -> anyhow::Result<Self> {
+        let mut patterns = Vec::new();
+        if let Some(global) = global_excludes_file() {
+            patterns.extend(read_ignorefile(&global, Path::new(""))?);
         }
+
+        let exclude = Path::new(GILLTTER_PATH)
+            .join(GILLTTER_INFO_DIR)
+            .join(GILLTTER_EXCLUDE_FILE);
+        patterns.extend(read_ignorefile(&exclude, Path::new(""))?);
+        patterns.extend(read_ignorefile(
+            Path::new(GILLTTER_IGNORE_FILE),
+            Path::new(""),
+        )?);
+
+        Ok(Self {
+            patterns,
+            nested: HashMap::new(),
+        })
     }
 
-    Ok(false)
+    fn load_nested(&mut self, path: &Path) -> anyhow::Result<()> {
+        let mut dir = PathBuf::new();
+        if let Some(parent) = path.parent() {
+            for component in parent.components() {
+                dir.push(component);
+                if !self.nested.contains_key(&dir) {
+                    let patterns = read_ignorefile(&dir.join(GILLTTER_IGNORE_FILE), &dir)?;
+                    self.nested.insert(dir.clone(), patterns);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Last matching pattern wins, deeper .gignore files take precedence over upper ones
+    fn last_match(&self, path: &Path, is_dir: bool) -> Option<&IgnorePattern> {
+        let mut applicable: Vec<&IgnorePattern> = self.patterns.iter().collect();
+        let mut dir = PathBuf::new();
+        if let Some(parent) = path.parent() {
+            for component in parent.components() {
+                dir.push(component);
+                if let Some(patterns) = self.nested.get(&dir) {
+                    applicable.extend(patterns.iter());
+                }
+            }
+        }
+        applicable
+            .into_iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+    }
+
+    /// Pattern that decides whether path is ignored, it is a negated one if the path is re-included.
+    /// path is relative to the project root
+    pub(crate) fn matching_pattern(
+        &mut self,
+        path: &Path,
+        is_dir: bool,
+    ) -> anyhow::Result<Option<&IgnorePattern>> {
+        let path = normalize_path(path);
+        self.load_nested(&path)?;
+
+        // A file inside an excluded directory can't be re-included
+        let mut ancestor = PathBuf::new();
+        if let Some(parent) = path.parent() {
+            for component in parent.components() {
+                ancestor.push(component);
+                if self
+                    .last_match(&ancestor, true)
+                    .is_some_and(|pattern| !pattern.negated)
+                {
+                    return Ok(self.last_match(&ancestor, true));
+                }
+            }
+        }
+        Ok(self.last_match(&path, is_dir))
+    }
+
+    pub(crate) fn is_ignored(&mut self, path: &Path, is_dir: bool) -> anyhow::Result<bool> {
+        Ok(self
+            .matching_pattern(path, is_dir)?
+            .is_some_and(|pattern| !pattern.negated))
+    }
+}
+
+impl IgnorePattern {
+    pub(crate) fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// "<source>:<line>:<pattern>" as printed by 'check-ignore -v'
+    pub(crate) fn describe(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.source.to_string_lossy(),
+            self.line_number,
+            self.text
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(lines: &[&str]) -> IgnoreMatcher {
+        let patterns = lines
+            .iter()
+            .filter_map(|line| IgnorePattern::parse(line, Path::new("")).unwrap())
+            .collect();
+        IgnoreMatcher {
+            patterns,
+            nested: HashMap::new(),
+        }
+    }
+
+    fn should_ignore(path: &str, is_dir: bool, matcher: &mut IgnoreMatcher) -> bool {
+        matcher.is_ignored(Path::new(path), is_dir).unwrap()
+    }
+
+    #[test]
+    fn unanchored_matches_any_level() {
+        let mut matcher = matcher(&["*.log"]);
+        assert!(should_ignore("a.log", false, &mut matcher));
+        assert!(should_ignore("src/deep/a.log", false, &mut matcher));
+        assert!(!should_ignore("a.txt", false, &mut matcher));
+    }
+
+    #[test]
+    fn anchored_and_dir_only() {
+        let mut matcher = matcher(&["/build", "target/"]);
+        assert!(should_ignore("build", false, &mut matcher));
+        assert!(!should_ignore("src/build", false, &mut matcher));
+        assert!(should_ignore("target/debug/app", false, &mut matcher));
+        assert!(should_ignore("src/target", true, &mut matcher));
+        assert!(!should_ignore("target", false, &mut matcher));
+    }
+
+    #[test]
+    fn double_star_and_negation() {
+        let mut matcher = matcher(&[
+            "docs/**/*.tmp",
+            "*.log",
+            "!keep.log",
+            "logs/",
+            "!logs/keep.log",
+        ]);
+        assert!(should_ignore("docs/a.tmp", false, &mut matcher));
+        assert!(should_ignore("docs/x/y/a.tmp", false, &mut matcher));
+        assert!(should_ignore("other.log", false, &mut matcher));
+        assert!(!should_ignore("keep.log", false, &mut matcher));
+        // Can't re-include a file if its parent directory is excluded
+        assert!(should_ignore("logs/keep.log", false, &mut matcher));
+    }
+
+    #[test]
+    fn comments_and_escapes() {
+        let mut matcher = matcher(&["# comment", "", "\\#hash", "\\!bang", "star\\*", "space\\ "]);
+        assert_eq!(matcher.patterns.len(), 4);
+        assert!(should_ignore("#hash", false, &mut matcher));
+        assert!(should_ignore("!bang", false, &mut matcher));
+        assert!(should_ignore("star*", false, &mut matcher));
+        assert!(!should_ignore("starry", false, &mut matcher));
+        assert!(should_ignore("space ", false, &mut matcher));
+    }
+
+    #[test]
+    fn nested_patterns_are_relative() {
+        let patterns: Vec<IgnorePattern> = ["/only-here", "*.o"]
+            .iter()
+            .filter_map(|line| IgnorePattern::parse(line, Path::new("sub")).unwrap())
+            .collect();
+        // Root has a pattern which the nested .gignore overrides
+        let mut matcher = matcher(&["*.tmp"]);
+        let mut nested = patterns;
+        nested.extend(IgnorePattern::parse("!keep.tmp", Path::new("sub")).unwrap());
+        matcher.nested.insert(PathBuf::from("sub"), nested);
+
+        assert!(!should_ignore("sub/keep.tmp", false, &mut matcher));
+        assert!(should_ignore("sub/other.tmp", false, &mut matcher));
+        assert!(should_ignore("sub/only-here", false, &mut matcher));
+        assert!(!should_ignore("only-here", false, &mut matcher));
+        assert!(should_ignore("sub/x/a.o", false, &mut matcher));
+        assert!(!should_ignore("a.o", false, &mut matcher));
+    }
+
+    #[test]
+    fn missing_ignorefile_is_empty() {
+        let patterns = read_ignorefile(Path::new("no/such/.gignore"), Path::new("")).unwrap();
+        assert!(patterns.is_empty());
+    }
 }
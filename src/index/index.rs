@@ -1,19 +1,22 @@
 use crate::{
     base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
-    objects::{ObjectDump, ObjectPump},
+    objects::{ObjectDump, ObjectPump, tree::FileType},
     utils,
 };
 use anyhow::anyhow;
 use std::{
-    fs::{File, OpenOptions},
+    collections::BTreeMap,
+    fs::{File, Metadata, OpenOptions},
     io::{BufRead, BufReader, Cursor, Read, Write},
+    os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum IndexType {
     RegularFile,
+    ExecutableFile,
     SymbolicLink,
     // Directory, // for traversing
 }
@@ -22,6 +25,7 @@ impl IndexType {
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             Self::RegularFile => b"100644".to_vec(),
+            Self::ExecutableFile => b"100755".to_vec(),
             Self::SymbolicLink => b"120000".to_vec(),
             // Self::Directory => b"040000".to_vec(),
         }
@@ -30,11 +34,41 @@ impl IndexType {
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         match bytes {
             b"100644" => Some(Self::RegularFile),
+            b"100755" => Some(Self::ExecutableFile),
             b"120000" => Some(Self::SymbolicLink),
             // b"040000" => Some(Self::Directory),
             _ => None,
         }
     }
+
+    /// Type of a file by its metadata, taken without following symbolic links
+    pub fn of_metadata(metadata: &Metadata) -> Self {
+        if metadata.is_symlink() {
+            Self::SymbolicLink
+        } else if metadata.mode() & 0o111 != 0 {
+            Self::ExecutableFile
+        } else {
+            Self::RegularFile
+        }
+    }
+
+    /// Mode the file gets in a tree
+    pub fn file_type(&self) -> FileType {
+        match self {
+            Self::RegularFile => FileType::RegularFile,
+            Self::ExecutableFile => FileType::ExecutableFile,
+            Self::SymbolicLink => FileType::SymbolicLink,
+        }
+    }
+
+    /// Type of a file with the mode it has in a tree
+    pub fn of_file_type(mode: FileType) -> Self {
+        match mode {
+            FileType::ExecutableFile => Self::ExecutableFile,
+            FileType::SymbolicLink => Self::SymbolicLink,
+            _ => Self::RegularFile,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -82,6 +116,41 @@ impl IndexEntry {
         )?;
         Ok(bytes)
     }
+
+    /// Entry for a file with the given contents. Metadata is taken from the work tree
+    /// if the file there has the same contents, otherwise it is zeroed, so it looks changed
+    pub fn for_blob(filepath: &Path, sha1_hash: &str) -> Self {
+        let metadata = std::fs::symlink_metadata(filepath).ok().filter(|_| {
+            utils::get_file_contents_as_blob(filepath)
+                .map(|blob| utils::generate_hash(&blob) == sha1_hash)
+                .unwrap_or(false)
+        });
+        match metadata {
+            Some(metadata) => Self::new(
+                metadata.ctime(),
+                metadata.mtime(),
+                metadata.size(),
+                IndexType::of_metadata(&metadata),
+                filepath.to_path_buf(),
+                sha1_hash.to_string(),
+            ),
+            None => Self::new(
+                0,
+                0,
+                0,
+                IndexType::RegularFile,
+                filepath.to_path_buf(),
+                sha1_hash.to_string(),
+            ),
+        }
+    }
+
+    /// Entry for a file with the given mode and contents, metadata as in `for_blob`
+    pub fn for_entry(filepath: &Path, mode: FileType, sha1_hash: &str) -> Self {
+        let mut entry = Self::for_blob(filepath, sha1_hash);
+        entry.index_type = IndexType::of_file_type(mode);
+        entry
+    }
 }
 
 pub struct Index {
@@ -99,10 +168,67 @@ impl Index {
         self.indices.push(entry);
     }
 
+    #[allow(dead_code)]
     pub fn remove_all(&mut self) {
         self.indices.clear();
     }
 
+    /// Index matching the given files (path -> blob hash), modes are taken from the work tree
+    pub fn from_files(files: &BTreeMap<PathBuf, String>) -> Self {
+        let mut index = Index::new();
+        for (filepath, sha1_hash) in files {
+            index.add(IndexEntry::for_blob(filepath, sha1_hash));
+        }
+        index
+    }
+
+    /// Index of files with their modes (path -> (mode, blob hash)), metadata is zeroed
+    pub fn from_entries(files: &BTreeMap<PathBuf, (FileType, String)>) -> Self {
+        let mut index = Index::new();
+        for (filepath, (mode, sha1_hash)) in files {
+            index.add(IndexEntry::new(
+                0,
+                0,
+                0,
+                IndexType::of_file_type(*mode),
+                filepath.clone(),
+                sha1_hash.clone(),
+            ));
+        }
+        index
+    }
+
+    /// Index of files with their modes (path -> (mode, blob hash)) which are checked out,
+    /// so metadata is taken from the work tree for files with the same contents
+    pub fn from_checkout(files: &BTreeMap<PathBuf, (FileType, String)>) -> Self {
+        let mut index = Index::new();
+        for (filepath, (mode, sha1_hash)) in files {
+            index.add(IndexEntry::for_entry(filepath, *mode, sha1_hash));
+        }
+        index
+    }
+
+    /// path -> blob hash of every entry
+    pub fn files(&self) -> BTreeMap<PathBuf, String> {
+        self.indices
+            .iter()
+            .map(|entry| (entry.filename.clone(), entry.sha1_hash.clone()))
+            .collect()
+    }
+
+    /// path -> (mode, blob hash) of every entry
+    pub fn entries(&self) -> BTreeMap<PathBuf, (FileType, String)> {
+        self.indices
+            .iter()
+            .map(|entry| {
+                (
+                    entry.filename.clone(),
+                    (entry.index_type.file_type(), entry.sha1_hash.clone()),
+                )
+            })
+            .collect()
+    }
+
     pub fn remove(&mut self, filepath: &Path) -> bool {
         let pos = self
             .indices
@@ -174,8 +300,12 @@ impl ObjectDump for Index {
         Ok(bytes)
     }
     fn dump_to_file(&self) -> anyhow::Result<String> {
-        let index_content = self.convert_to_bytes()?;
-        let compressed_content = utils::compress(&index_content)?;
+        // Nothing staged is just an empty file, same as right after init
+        let compressed_content = if self.indices.is_empty() {
+            Vec::new()
+        } else {
+            utils::compress(&self.convert_to_bytes()?)?
+        };
         // let compressed_content = index_content; // TODO: Remove after testing
 
         let path = Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE);
@@ -230,4 +360,36 @@ mod tests {
         println!("Pumped: '{}'", std::str::from_utf8(&index_bytes).unwrap());
         assert_eq!(hash, hash2);
     }
+    #[test]
+    fn entries_from_files_take_metadata_of_matching_files() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let repository = crate::test_utils::TempRepository::new();
+        repository.add("same.txt", "same\n");
+        repository.add("changed.txt", "old\n");
+        repository.add("run.sh", "#!/bin/sh\n");
+        fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+        let files = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE))
+            .unwrap()
+            .files();
+        fs::write("changed.txt", "new\n").unwrap();
+        fs::remove_file("same.txt").unwrap();
+        fs::write("same.txt", "same\n").unwrap();
+
+        let index = Index::from_files(&files);
+        assert_eq!(index.files(), files);
+        let entry = |name: &str| {
+            index
+                .indices
+                .iter()
+                .find(|entry| entry.filename == Path::new(name))
+                .unwrap()
+        };
+        assert_eq!(entry("same.txt").file_size, 5);
+        assert_ne!(entry("same.txt").mtime, 0);
+        // Does not match the blob, so it looks changed to status
+        assert_eq!(entry("changed.txt").mtime, 0);
+        assert_eq!(entry("changed.txt").index_type, IndexType::RegularFile);
+        assert_eq!(entry("run.sh").index_type, IndexType::ExecutableFile);
+    }
 }
@@ -1,20 +1,38 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
-use clap::{Parser, Subcommand, arg, command};
+use clap::{CommandFactory, Parser, Subcommand, arg, command};
 
 use crate::{
-    base::{GILLTTER_INDEX_FILE, GILLTTER_PATH},
-    index::index::Index,
-    objects::ObjectPump,
+    commands::{
+        archive::ArchiveFormat,
+        bisect::Mark,
+        cat_file::CatFileMode,
+        config::ValueType,
+        ls_files::{LsFilesColumns, LsFilesFilter},
+        reset::ResetMode,
+    },
+    config::ConfigScope,
 };
 
+mod alias;
+mod archive;
 mod base;
+mod blame;
 mod commands;
 mod config;
+mod diff;
+mod git;
+mod hooks;
 mod ignore;
 mod index;
+mod merge;
 mod objects;
+mod refs;
+mod remote;
+#[cfg(test)]
+mod test_utils;
 mod utils;
+mod worktree;
 
 #[derive(Debug, Parser)] // requires `derive` feature
 #[command(name = "gilltter")]
@@ -36,6 +54,9 @@ enum Commands {
 
     #[command(arg_required_else_help = true)]
     Commit {
+        // skip the pre-commit and commit-msg hooks
+        #[arg(long)]
+        no_verify: bool,
         message: Option<String>,
     },
 
@@ -43,14 +64,306 @@ enum Commands {
 
     #[command(arg_required_else_help = true)]
     Revert {
-        // go back to a commit and change the work dir
-        commit_hash: PathBuf,
+        // apply the changes to the index and the work tree, but don't commit them
+        #[arg(short, long)]
+        no_commit: bool,
+        // commits to undo: revisions or <from>..<to> ranges
+        #[arg(required = true)]
+        revisions: Vec<String>,
+    },
+
+    #[command(arg_required_else_help = true)]
+    CherryPick {
+        // commit the resolved conflicts and pick the rest
+        #[arg(long = "continue", conflicts_with_all = ["abort", "revisions"])]
+        continue_pick: bool,
+        // go back to where HEAD was before the cherry-pick
+        #[arg(long, conflicts_with = "revisions")]
+        abort: bool,
+        // commits to pick: revisions or <from>..<to> ranges
+        #[arg(required_unless_present_any = ["continue_pick", "abort"])]
+        revisions: Vec<String>,
     },
 
     #[command(arg_required_else_help = true)]
+    Rebase {
+        // edit the list of commits to replay first
+        #[arg(short, long, conflicts_with_all = ["continue_rebase", "skip", "abort"])]
+        interactive: bool,
+        // commit the resolved conflicts and replay the rest
+        #[arg(long = "continue", conflicts_with_all = ["skip", "abort", "upstream"])]
+        continue_rebase: bool,
+        // drop the commit which stopped the rebase and replay the rest
+        #[arg(long, conflicts_with_all = ["abort", "upstream"])]
+        skip: bool,
+        // go back to where HEAD was before the rebase
+        #[arg(long, conflicts_with = "upstream")]
+        abort: bool,
+        // commit to replay the commits of HEAD onto
+        #[arg(required_unless_present_any = ["continue_rebase", "skip", "abort"])]
+        upstream: Option<String>,
+    },
+
     Reset {
-        // go back to a commit and dont change the working dir
-        value: i32, // accepts values like HEAD-[1,2,3,4,5,6]
+        // move HEAD only
+        #[arg(long, group = "mode")]
+        soft: bool,
+        // also make the index match the commit (default)
+        #[arg(long, group = "mode")]
+        mixed: bool,
+        // also make the work tree match the commit
+        #[arg(long, group = "mode")]
+        hard: bool,
+        // commit to go to: hash, branch, HEAD~N, or just N commits back; HEAD if omitted
+        revision: Option<String>,
+        // only unstage these paths, HEAD does not move
+        #[arg(last = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    Tag {
+        // list tags, optionally only those matching a glob pattern
+        #[arg(short, long, conflicts_with_all = ["delete", "verify"])]
+        list: bool,
+        // delete the tags
+        #[arg(short, long, conflicts_with = "verify")]
+        delete: bool,
+        // check the tags point to intact objects
+        #[arg(short, long)]
+        verify: bool,
+        // store a tag object with tagger and message
+        #[arg(short, long)]
+        annotate: bool,
+        // tag message, implies -a
+        #[arg(short, long)]
+        message: Option<String>,
+        // move an existing tag
+        #[arg(short, long)]
+        force: bool,
+        // <name> [<revision>] to create a tag, names to delete or verify, a pattern to list
+        args: Vec<String>,
+    },
+
+    #[command(arg_required_else_help = true)]
+    #[command(group = clap::ArgGroup::new("mode").required(true))]
+    CatFile {
+        // print the object type
+        #[arg(short = 't', group = "mode")]
+        object_type: bool,
+        // print the object size
+        #[arg(short, group = "mode")]
+        size: bool,
+        // print the object contents in a readable form
+        #[arg(short, group = "mode")]
+        pretty: bool,
+        // hash, revision, tag or <revision>:<path>
+        object: String,
+    },
+
+    LsFiles {
+        // print mode and hash of every entry
+        #[arg(short = 's', long)]
+        stage: bool,
+        // print ctime, mtime and size of every entry
+        #[arg(long)]
+        stat: bool,
+        // only files changed in the work tree, deleted ones included
+        #[arg(short, long)]
+        modified: bool,
+        // only files deleted from the work tree
+        #[arg(short, long)]
+        deleted: bool,
+        // untracked files which are not ignored
+        #[arg(short, long)]
+        others: bool,
+        // separate entries with NUL instead of newline
+        #[arg(short = 'z')]
+        null_terminated: bool,
+    },
+
+    LsTree {
+        // list the subtrees as well
+        #[arg(short)]
+        recursive: bool,
+        // separate entries with NUL instead of newline
+        #[arg(short = 'z')]
+        null_terminated: bool,
+        // commit, tag or tree
+        revision: String,
+        // only this path, "dir/" lists what is inside dir
+        path: Option<PathBuf>,
+    },
+
+    HashObject {
+        // store the files as blobs too
+        #[arg(short)]
+        write: bool,
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    WriteTree,
+
+    CommitTree {
+        // the single parent, none for a root commit
+        #[arg(short)]
+        parent: Option<String>,
+        // read from stdin if omitted
+        #[arg(short)]
+        message: Option<String>,
+        // tree, or a commit to take the tree from
+        tree: String,
+    },
+
+    UpdateRef {
+        // delete the ref instead, the value is then the expected old one
+        #[arg(short)]
+        delete: bool,
+        // HEAD, branches/<name>, tags/<name> or a branch name
+        name: String,
+        // new value (old value with -d)
+        value: Option<String>,
+        // update only if the ref is at this value now, 40 zeros if it must not exist
+        old_value: Option<String>,
+    },
+
+    Blame {
+        // only these lines: <start>,<end> or <start>,+<count>
+        #[arg(short = 'L')]
+        lines: Option<String>,
+        // file with revisions (one per line) to look through, e.g. formatting commits
+        #[arg(long)]
+        ignore_revs_file: Option<PathBuf>,
+        path: PathBuf,
+        // HEAD if omitted
+        revision: Option<String>,
+    },
+
+    Show {
+        // revision, tag or <revision>:<path>, HEAD if omitted
+        revision: Option<String>,
+    },
+
+    Bisect {
+        #[command(subcommand)]
+        command: BisectCommands,
+    },
+
+    Clone {
+        // only this many commits of history
+        #[arg(long)]
+        depth: Option<usize>,
+        // blob:none leaves blobs out until something reads them
+        #[arg(long)]
+        filter: Option<String>,
+        // path of the repository (or of its .gilltter directory) or of a bundle, ext::, ssh:// or http:// url
+        url: String,
+        // named after the repository if omitted
+        directory: Option<PathBuf>,
+    },
+
+    Fetch {
+        // origin (or the remote of the current branch) if omitted
+        remote: Option<String>,
+    },
+
+    Push {
+        // push all tags too
+        #[arg(long)]
+        tags: bool,
+        // move remote refs even if they are not fast-forwarded
+        #[arg(short, long)]
+        force: bool,
+        // skip the pre-push hook
+        #[arg(long)]
+        no_verify: bool,
+        // origin (or the remote of the current branch) if omitted
+        remote: Option<String>,
+        // <src>[:<dst>], the current branch if omitted
+        refspecs: Vec<String>,
+    },
+
+    Pull {
+        // diverged histories are always rebased, the flag is kept for scripts
+        #[arg(long, hide = true)]
+        rebase: bool,
+        // origin (or the remote of the current branch) if omitted
+        remote: Option<String>,
+        // the branch the current one tracks if omitted
+        branch: Option<String>,
+    },
+
+    Serve {
+        // repository, the current directory if omitted
+        directory: Option<PathBuf>,
+    },
+
+    HttpServe {
+        // address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+        // repository, the current directory if omitted
+        directory: Option<PathBuf>,
+    },
+
+    ImportGit {
+        // git work tree or bare repository
+        path: PathBuf,
+    },
+
+    ExportGit,
+
+    FastExport {
+        // branches and tags to export, all of them if omitted
+        names: Vec<String>,
+    },
+
+    FastImport,
+
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommands,
+    },
+
+    Archive {
+        // tar, tar.gz or zip, guessed from the output file name if omitted
+        #[arg(long, value_enum)]
+        format: Option<ArchiveFormat>,
+        // put in front of every path, e.g. project-1.0/
+        #[arg(long, default_value = "")]
+        prefix: String,
+        // stdout if omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        revision: String,
+        // only these files and directories
+        paths: Vec<PathBuf>,
+    },
+
+    Stash {
+        // push if omitted
+        #[command(subcommand)]
+        command: Option<StashCommands>,
+    },
+
+    #[command(arg_required_else_help = true)]
+    CheckIgnore {
+        // print the ignore file, line and pattern which matched
+        #[arg(short, long)]
+        verbose: bool,
+        paths: Vec<PathBuf>,
+    },
+
+    #[command(arg_required_else_help = true)]
+    Config {
+        // read and write ~/.gilltterconfig
+        #[arg(long, global = true, conflicts_with = "local")]
+        global: bool,
+        // read and write .gilltter/config (default for set and unset)
+        #[arg(long, global = true)]
+        local: bool,
+        #[command(subcommand)]
+        command: ConfigCommands,
     },
 }
 
@@ -63,15 +376,143 @@ enum AddCommands {
     },
 }
 
+#[derive(Subcommand, Debug, Clone)]
+enum BisectCommands {
+    Start {
+        // the bad revision
+        bad: Option<String>,
+        // good revisions
+        good: Vec<String>,
+    },
+    // mark the revisions (HEAD if none) as good
+    Good {
+        revisions: Vec<String>,
+    },
+    // mark the revisions (HEAD if none) as bad
+    Bad {
+        revisions: Vec<String>,
+    },
+    // mark the revisions (HEAD if none) as untestable
+    Skip {
+        revisions: Vec<String>,
+    },
+    // end the bisect and go back to where HEAD was
+    Reset,
+    // mark commits by the exit code of the command: 0 good, 125 skip, other bad
+    Run {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum BundleCommands {
+    // write refs and the objects they need into a file
+    Create {
+        // every branch and tag, and HEAD
+        #[arg(long)]
+        all: bool,
+        file: PathBuf,
+        // branch, tag or HEAD; <from>..<to> and ^<from> leave out what <from> has
+        revisions: Vec<String>,
+    },
+    // check that this repository has every commit the bundle needs
+    Verify {
+        file: PathBuf,
+    },
+    // store the objects of the bundle and print its refs
+    Unbundle {
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum StashCommands {
+    // save the index and the work tree, then reset to HEAD
+    Push {
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    // apply a stash and drop it
+    Pop {
+        // stash@{N} or N, the latest one if omitted
+        stash: Option<String>,
+    },
+    Apply {
+        stash: Option<String>,
+    },
+    List,
+    Drop {
+        stash: Option<String>,
+    },
+    // files the stash changes
+    Show {
+        stash: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommands {
+    Get {
+        // <Category>.<Name> or <Category>.<subsection>.<Name>, e.g. General.Username
+        key: String,
+        // print every value of a multi-valued key
+        #[arg(long)]
+        all: bool,
+        // check the value and print it in canonical form
+        #[arg(long = "type", value_enum)]
+        value_type: Option<ValueType>,
+    },
+    Set {
+        key: String,
+        value: String,
+    },
+    Add {
+        key: String,
+        value: String,
+    },
+    Unset {
+        key: String,
+    },
+    List,
+}
+
+// Prints why the command failed and exits with 1, so scripts can tell
+macro_rules! fail {
+    ($($arg:tt)*) => {{
+        eprintln!($($arg)*);
+        std::process::exit(1)
+    }};
+}
+
 fn main() {
-    let args = Cli::parse();
+    let builtins: Vec<String> = Cli::command()
+        .get_subcommands()
+        .map(|command| command.get_name().to_string())
+        .collect();
+    let args = match alias::expand_args(std::env::args().collect(), &builtins) {
+        Ok(alias::Expansion::Args(args)) => Cli::parse_from(args),
+        Ok(alias::Expansion::Shell { command, args }) => {
+            match alias::run_shell(&command, &args) {
+                Ok(code) => std::process::exit(code),
+                Err(why) => {
+                    eprintln!("{}", why);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Err(why) => {
+            eprintln!("Could not expand alias: {}", why);
+            std::process::exit(1);
+        }
+    };
     match args.command {
         Commands::Init {} => base::gilltter_init().unwrap(),
         Commands::Add { command } => match command {
             AddCommands::Filename { file } => {
                 // println!("Adding");
                 if let Err(why) = commands::add::add(&file) {
-                    eprintln!(
+                    fail!(
                         "Could not add a file '{}', because: {}",
                         file.to_string_lossy(),
                         why
@@ -82,33 +523,361 @@ fn main() {
                 todo!("Add all");
             }
         },
-        Commands::Commit { message } => {
-            match Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE)) {
-                Ok(index) => {
-                    if let Err(why) =
-                        commands::commit::commit(&index, message.expect("Type a message"))
-                    {
-                        eprintln!("Could not commit: {}", why);
-                    }
-                }
-                Err(why) => {
-                    eprintln!("Could not parse index file: {}", why);
-                }
+        Commands::Commit { no_verify, message } => {
+            if let Err(why) = commands::commit::commit(message.expect("Type a message"), !no_verify)
+            {
+                fail!("Could not commit: {}", why);
             }
         }
         Commands::Status => {
             if let Err(why) = commands::status::gilltter_status() {
-                eprintln!("Status failed: {}", why);
+                fail!("Status failed: {}", why);
+            }
+        }
+        Commands::Revert {
+            no_commit,
+            revisions,
+        } => {
+            if let Err(why) = commands::revert::revert(&revisions, no_commit) {
+                fail!("Revert failed: {}", why);
+            }
+        }
+        Commands::CherryPick {
+            continue_pick,
+            abort,
+            revisions,
+        } => {
+            let result = match (continue_pick, abort) {
+                (true, _) => commands::cherry_pick::cherry_pick_continue(),
+                (_, true) => commands::cherry_pick::cherry_pick_abort(),
+                _ => commands::cherry_pick::cherry_pick(&revisions),
+            };
+            if let Err(why) = result {
+                fail!("Cherry-pick failed: {}", why);
+            }
+        }
+        Commands::Rebase {
+            interactive,
+            continue_rebase,
+            skip,
+            abort,
+            upstream,
+        } => {
+            let result = match (continue_rebase, skip, abort, upstream) {
+                (true, _, _, _) => commands::rebase::rebase_continue(),
+                (_, true, _, _) => commands::rebase::rebase_skip(),
+                (_, _, true, _) => commands::rebase::rebase_abort(),
+                (_, _, _, Some(upstream)) => commands::rebase::rebase(&upstream, interactive),
+                _ => Err(anyhow::anyhow!("Upstream is required")),
+            };
+            if let Err(why) = result {
+                fail!("Rebase failed: {}", why);
+            }
+        }
+        Commands::Reset {
+            soft,
+            mixed: _,
+            hard,
+            revision,
+            paths,
+        } => {
+            let mode = match (soft, hard) {
+                (true, _) => ResetMode::Soft,
+                (_, true) => ResetMode::Hard,
+                _ => ResetMode::Mixed,
+            };
+            if let Err(why) = commands::reset::reset(revision.as_deref(), mode, &paths) {
+                fail!("Reset failed: {}", why);
+            }
+        }
+        Commands::Tag {
+            list,
+            delete,
+            verify,
+            annotate,
+            message,
+            force,
+            args,
+        } => {
+            let result = if delete {
+                commands::tag::delete(&args)
+            } else if verify {
+                commands::tag::verify(&args)
+            } else if list || args.is_empty() {
+                commands::tag::list(args.first().map(|pattern| pattern.as_str()))
+            } else if args.len() > 2 {
+                Err(anyhow::anyhow!("Expected a tag name and a revision"))
+            } else {
+                commands::tag::create(
+                    &args[0],
+                    args.get(1).map(|revision| revision.as_str()),
+                    message.as_deref(),
+                    annotate,
+                    force,
+                )
+            };
+            if let Err(why) = result {
+                fail!("Tag failed: {}", why);
+            }
+        }
+        Commands::CatFile {
+            object_type,
+            size,
+            pretty: _,
+            object,
+        } => {
+            let mode = match (object_type, size) {
+                (true, _) => CatFileMode::Type,
+                (_, true) => CatFileMode::Size,
+                _ => CatFileMode::Pretty,
+            };
+            if let Err(why) = commands::cat_file::cat_file(&object, mode) {
+                fail!("Cat-file failed: {}", why);
+            }
+        }
+        Commands::LsFiles {
+            stage,
+            stat,
+            modified,
+            deleted,
+            others,
+            null_terminated,
+        } => {
+            let filter = LsFilesFilter {
+                modified,
+                deleted,
+                others,
+            };
+            let columns = LsFilesColumns { stage, stat };
+            if let Err(why) = commands::ls_files::ls_files(filter, columns, null_terminated) {
+                fail!("Ls-files failed: {}", why);
+            }
+        }
+        Commands::LsTree {
+            recursive,
+            null_terminated,
+            revision,
+            path,
+        } => {
+            if let Err(why) =
+                commands::ls_tree::ls_tree(&revision, path.as_deref(), recursive, null_terminated)
+            {
+                fail!("Ls-tree failed: {}", why);
+            }
+        }
+        Commands::HashObject { write, paths } => {
+            if let Err(why) = commands::hash_object::hash_object(&paths, write) {
+                fail!("Hash-object failed: {}", why);
+            }
+        }
+        Commands::WriteTree => {
+            if let Err(why) = commands::write_tree::write_tree() {
+                fail!("Write-tree failed: {}", why);
+            }
+        }
+        Commands::CommitTree {
+            parent,
+            message,
+            tree,
+        } => {
+            if let Err(why) =
+                commands::commit_tree::commit_tree(&tree, parent.as_deref(), message.as_deref())
+            {
+                fail!("Commit-tree failed: {}", why);
+            }
+        }
+        Commands::UpdateRef {
+            delete,
+            name,
+            value,
+            old_value,
+        } => {
+            let result = match (delete, value) {
+                (true, old) if old_value.is_none() => {
+                    commands::update_ref::delete_ref(&name, old.as_deref())
+                }
+                (true, _) => Err(anyhow::anyhow!("-d takes at most one value")),
+                (false, Some(new)) => {
+                    commands::update_ref::update_ref(&name, &new, old_value.as_deref())
+                }
+                (false, None) => Err(anyhow::anyhow!("The new value is missing")),
+            };
+            if let Err(why) = result {
+                fail!("Update-ref failed: {}", why);
+            }
+        }
+        Commands::Blame {
+            lines,
+            ignore_revs_file,
+            path,
+            revision,
+        } => {
+            if let Err(why) = commands::blame::blame(
+                &path,
+                revision.as_deref(),
+                lines.as_deref(),
+                ignore_revs_file.as_deref(),
+            ) {
+                fail!("Blame failed: {}", why);
+            }
+        }
+        Commands::Show { revision } => {
+            if let Err(why) = commands::show::show(revision.as_deref()) {
+                fail!("Show failed: {}", why);
+            }
+        }
+        Commands::Bisect { command } => {
+            let result = match command {
+                BisectCommands::Start { bad, good } => {
+                    commands::bisect::start(bad.as_deref(), &good)
+                }
+                BisectCommands::Good { revisions } => {
+                    commands::bisect::mark(Mark::Good, &revisions)
+                }
+                BisectCommands::Bad { revisions } => commands::bisect::mark(Mark::Bad, &revisions),
+                BisectCommands::Skip { revisions } => {
+                    commands::bisect::mark(Mark::Skip, &revisions)
+                }
+                BisectCommands::Reset => commands::bisect::reset(),
+                BisectCommands::Run { command } => commands::bisect::run(&command),
+            };
+            if let Err(why) = result {
+                fail!("Bisect failed: {}", why);
+            }
+        }
+        Commands::Clone {
+            depth,
+            filter,
+            url,
+            directory,
+        } => {
+            if let Err(why) =
+                commands::clone::clone(&url, directory.as_deref(), depth, filter.as_deref())
+            {
+                fail!("Clone failed: {}", why);
+            }
+        }
+        Commands::Fetch { remote } => {
+            if let Err(why) = commands::fetch::fetch(remote.as_deref()) {
+                fail!("Fetch failed: {}", why);
+            }
+        }
+        Commands::Push {
+            tags,
+            force,
+            no_verify,
+            remote,
+            refspecs,
+        } => {
+            if let Err(why) =
+                commands::push::push(remote.as_deref(), &refspecs, tags, force, !no_verify)
+            {
+                fail!("Push failed: {}", why);
+            }
+        }
+        Commands::Pull { remote, branch, .. } => {
+            if let Err(why) = commands::pull::pull(remote.as_deref(), branch.as_deref()) {
+                fail!("Pull failed: {}", why);
+            }
+        }
+        Commands::Serve { directory } => {
+            if let Err(why) = commands::serve::serve(directory.as_deref()) {
+                fail!("Serve failed: {}", why);
+            }
+        }
+        Commands::HttpServe { bind, directory } => {
+            if let Err(why) = commands::http_serve::http_serve(directory.as_deref(), &bind) {
+                fail!("Http-serve failed: {}", why);
+            }
+        }
+        Commands::ImportGit { path } => {
+            if let Err(why) = commands::import_git::import_git(&path) {
+                fail!("Import-git failed: {}", why);
+            }
+        }
+        Commands::ExportGit => {
+            if let Err(why) = commands::fast_export::fast_export(&[]) {
+                fail!("Export-git failed: {}", why);
+            }
+        }
+        Commands::FastExport { names } => {
+            if let Err(why) = commands::fast_export::fast_export(&names) {
+                fail!("Fast-export failed: {}", why);
+            }
+        }
+        Commands::FastImport => {
+            if let Err(why) = commands::fast_import::fast_import() {
+                fail!("Fast-import failed: {}", why);
+            }
+        }
+        Commands::Bundle { command } => {
+            let result = match command {
+                BundleCommands::Create {
+                    all,
+                    file,
+                    revisions,
+                } => commands::bundle::create(&file, &revisions, all),
+                BundleCommands::Verify { file } => commands::bundle::verify(&file),
+                BundleCommands::Unbundle { file } => commands::bundle::unbundle(&file),
+            };
+            if let Err(why) = result {
+                fail!("Bundle failed: {}", why);
+            }
+        }
+        Commands::Archive {
+            format,
+            prefix,
+            output,
+            revision,
+            paths,
+        } => {
+            if let Err(why) =
+                commands::archive::archive(&revision, format, &prefix, output.as_deref(), &paths)
+            {
+                fail!("Archive failed: {}", why);
+            }
+        }
+        Commands::Stash { command } => {
+            let result = match command.unwrap_or(StashCommands::Push { message: None }) {
+                StashCommands::Push { message } => commands::stash::push(message.as_deref()),
+                StashCommands::Pop { stash } => commands::stash::apply(stash.as_deref(), true),
+                StashCommands::Apply { stash } => commands::stash::apply(stash.as_deref(), false),
+                StashCommands::List => commands::stash::list(),
+                StashCommands::Drop { stash } => commands::stash::drop_entry(stash.as_deref()),
+                StashCommands::Show { stash } => commands::stash::show(stash.as_deref()),
+            };
+            if let Err(why) = result {
+                fail!("Stash failed: {}", why);
             }
         }
-        Commands::Revert { commit_hash } => {
-            if let Err(why) = commands::revert::revert(&commit_hash) {
-                eprintln!("Revert failed, i think project is fucked up now: {}", why);
+        Commands::CheckIgnore { verbose, paths } => {
+            if let Err(why) = commands::check_ignore::check_ignore(&paths, verbose) {
+                fail!("Check-ignore failed: {}", why);
             }
         }
-        Commands::Reset { value } => {
-            if let Err(why) = commands::reset::reset(value) {
-                eprintln!("Reset failed: {}", why);
+        Commands::Config {
+            global,
+            local,
+            command,
+        } => {
+            let scope = match (global, local) {
+                (true, _) => Some(ConfigScope::Global),
+                (_, true) => Some(ConfigScope::Local),
+                _ => None,
+            };
+            let result = match command {
+                ConfigCommands::Get {
+                    key,
+                    all,
+                    value_type,
+                } => commands::config::get(&key, all, value_type, scope),
+                ConfigCommands::Add { key, value } => commands::config::add(&key, &value, scope),
+                ConfigCommands::Set { key, value } => commands::config::set(&key, &value, scope),
+                ConfigCommands::Unset { key } => commands::config::unset(&key, scope),
+                ConfigCommands::List => commands::config::list(scope),
+            };
+            if let Err(why) = result {
+                fail!("Config failed: {}", why);
             }
         }
     }
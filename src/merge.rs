@@ -0,0 +1,340 @@
+// Line based three-way merge, used by revert and friends.
+// Both sides are diffed against their common base, see diff.rs.
+// A region changed by one side only takes that side, a region changed by both sides
+// in different ways becomes a conflict:
+/*
+ * <<<<<<< <ours label>
+ * our lines
+ * =======
+ * their lines
+ * >>>>>>> <theirs label>
+ */
+// Whole trees are merged file by file, binary files can't be merged and always conflict.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    diff::{match_lines, split_lines},
+    index::index::Index,
+    objects::{ObjectDump, ObjectPump, blob::Blob, tree::FileType},
+    worktree,
+};
+
+const CONFLICT_OURS_MARKER: &'static str = "<<<<<<<";
+const CONFLICT_SEPARATOR: &'static str = "=======";
+const CONFLICT_THEIRS_MARKER: &'static str = ">>>>>>>";
+
+pub struct TextMerge {
+    pub data: Vec<u8>,
+    pub conflicts: usize,
+}
+
+fn push_lines(data: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        data.extend_from_slice(line);
+    }
+}
+
+// Markers must start on their own line even if the side does not end with '\n'
+fn push_marker(data: &mut Vec<u8>, marker: &str) {
+    if !data.is_empty() && !data.ends_with(b"\n") {
+        data.push(b'\n');
+    }
+    data.extend_from_slice(marker.as_bytes());
+    data.push(b'\n');
+}
+
+/// Merges changes of `ours` and `theirs`, both made on top of `base`
+pub fn merge_text(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> TextMerge {
+    let base = split_lines(base);
+    let ours = split_lines(ours);
+    let theirs = split_lines(theirs);
+    let ours_matches = match_lines(&base, &ours);
+    let theirs_matches = match_lines(&base, &theirs);
+
+    let mut merged = TextMerge {
+        data: Vec::new(),
+        conflicts: 0,
+    };
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // Next base line both sides kept, everything before it was changed by someone
+        let stable = (b..base.len())
+            .find_map(|line| Some((line, ours_matches[line]?, theirs_matches[line]?)));
+        let (b_end, o_end, t_end) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        let base_chunk = &base[b..b_end];
+        let ours_chunk = &ours[o..o_end];
+        let theirs_chunk = &theirs[t..t_end];
+        if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+            push_lines(&mut merged.data, theirs_chunk);
+        } else if theirs_chunk == base_chunk {
+            push_lines(&mut merged.data, ours_chunk);
+        } else {
+            merged.conflicts += 1;
+            push_marker(
+                &mut merged.data,
+                &format!("{} {}", CONFLICT_OURS_MARKER, ours_label),
+            );
+            push_lines(&mut merged.data, ours_chunk);
+            push_marker(&mut merged.data, CONFLICT_SEPARATOR);
+            push_lines(&mut merged.data, theirs_chunk);
+            push_marker(
+                &mut merged.data,
+                &format!("{} {}", CONFLICT_THEIRS_MARKER, theirs_label),
+            );
+        }
+
+        match stable {
+            Some((b_stable, o_stable, t_stable)) => {
+                merged.data.extend_from_slice(base[b_stable]);
+                (b, o, t) = (b_stable + 1, o_stable + 1, t_stable + 1);
+            }
+            None => break,
+        }
+    }
+    merged
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictKind {
+    Content,       // both sides changed the same lines
+    DeletedByUs,   // we deleted the file, they changed it
+    DeletedByThem, // we changed the file, they deleted it
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Content => write!(f, "content"),
+            Self::DeletedByUs => write!(f, "deleted by us"),
+            Self::DeletedByThem => write!(f, "deleted by them"),
+        }
+    }
+}
+
+pub struct TreeMerge {
+    pub files: BTreeMap<PathBuf, (FileType, String)>, // what goes to the index, conflicted files stay as ours
+    pub worktree: BTreeMap<PathBuf, (FileType, String)>, // what goes to the work tree, conflict markers included
+    pub conflicts: Vec<(PathBuf, ConflictKind)>,
+}
+
+fn read_blob(sha: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(Blob::from_file(&object_path(sha))?.get_data())
+}
+
+fn write_blob(data: &[u8]) -> anyhow::Result<String> {
+    let mut blob = Blob::new();
+    blob.set_data(data);
+    blob.dump_to_file()
+}
+
+// Modes are merged like contents, but when both sides change the mode ours wins
+fn merge_mode(base: Option<FileType>, ours: FileType, theirs: FileType) -> FileType {
+    if base == Some(ours) { theirs } else { ours }
+}
+
+/// Merges file lists (path -> (mode, blob hash)) of `ours` and `theirs` made on top of `base`.
+/// Merged contents are stored as blobs right away
+pub fn merge_trees(
+    base: &BTreeMap<PathBuf, (FileType, String)>,
+    ours: &BTreeMap<PathBuf, (FileType, String)>,
+    theirs: &BTreeMap<PathBuf, (FileType, String)>,
+    ours_label: &str,
+    theirs_label: &str,
+) -> anyhow::Result<TreeMerge> {
+    let mut merged = TreeMerge {
+        files: BTreeMap::new(),
+        worktree: BTreeMap::new(),
+        conflicts: Vec::new(),
+    };
+
+    let paths: BTreeSet<&PathBuf> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    for path in paths {
+        let (base_entry, ours_entry, theirs_entry) =
+            (base.get(path), ours.get(path), theirs.get(path));
+        let (b, o, t) = (
+            base_entry.map(|(_, sha)| sha),
+            ours_entry.map(|(_, sha)| sha),
+            theirs_entry.map(|(_, sha)| sha),
+        );
+        let mode = match (ours_entry, theirs_entry) {
+            (Some((ours_mode, _)), Some((theirs_mode, _))) => {
+                merge_mode(base_entry.map(|(mode, _)| *mode), *ours_mode, *theirs_mode)
+            }
+            (Some((mode, _)), None) | (None, Some((mode, _))) => *mode,
+            (None, None) => continue, // deleted by both
+        };
+
+        let result = if o == t || t == b {
+            o
+        } else if o == b {
+            t
+        } else {
+            match (ours_entry, theirs_entry) {
+                (Some(ours_entry), None) => {
+                    merged
+                        .conflicts
+                        .push((path.clone(), ConflictKind::DeletedByThem));
+                    merged.files.insert(path.clone(), ours_entry.clone());
+                    merged.worktree.insert(path.clone(), ours_entry.clone());
+                    continue;
+                }
+                (None, Some(theirs_entry)) => {
+                    merged
+                        .conflicts
+                        .push((path.clone(), ConflictKind::DeletedByUs));
+                    merged.worktree.insert(path.clone(), theirs_entry.clone());
+                    continue;
+                }
+                (Some((ours_mode, o)), Some((_, t))) => {
+                    let base_data = match b {
+                        Some(b) => read_blob(b)?,
+                        None => Vec::new(), // added by both sides
+                    };
+                    let (ours_data, theirs_data) = (read_blob(o)?, read_blob(t)?);
+                    let is_binary = [&base_data, &ours_data, &theirs_data]
+                        .iter()
+                        .any(|data| data.contains(&0));
+
+                    let text = merge_text(
+                        &base_data,
+                        &ours_data,
+                        &theirs_data,
+                        ours_label,
+                        theirs_label,
+                    );
+                    if is_binary || text.conflicts > 0 {
+                        merged.conflicts.push((path.clone(), ConflictKind::Content));
+                        merged.files.insert(path.clone(), (*ours_mode, o.clone()));
+                        let shown = if is_binary {
+                            o.clone()
+                        } else {
+                            write_blob(&text.data)?
+                        };
+                        merged.worktree.insert(path.clone(), (mode, shown));
+                        continue;
+                    }
+                    let sha = write_blob(&text.data)?;
+                    merged.files.insert(path.clone(), (mode, sha.clone()));
+                    merged.worktree.insert(path.clone(), (mode, sha));
+                    continue;
+                }
+                (None, None) => unreachable!("o == t covers both missing"),
+            }
+        };
+        if let Some(sha) = result {
+            merged.files.insert(path.clone(), (mode, sha.clone()));
+            merged.worktree.insert(path.clone(), (mode, sha.clone()));
+        }
+    }
+    Ok(merged)
+}
+
+/// Writes the merge result to the work tree and the index. Only files the merge
+/// changes are touched, and nothing is written if any of them has local changes
+pub fn apply(merged: &TreeMerge, index: &Index) -> anyhow::Result<()> {
+    let current = index.entries();
+    let changed: Vec<&PathBuf> = current
+        .keys()
+        .chain(merged.worktree.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|path| current.get(*path) != merged.worktree.get(*path))
+        .collect();
+
+    for path in &changed {
+        if worktree::file_hash(path).as_ref() != current.get(*path).map(|(_, sha)| sha) {
+            return Err(anyhow!(
+                "Local changes to '{}' would be overwritten, commit or reset them first",
+                path.to_string_lossy()
+            ));
+        }
+    }
+
+    let mut written = BTreeMap::new();
+    for path in changed {
+        match merged.worktree.get(path) {
+            Some(entry) => {
+                worktree::write_file(path, &entry.1)?;
+                written.insert(path.clone(), entry.clone());
+            }
+            None => worktree::remove_file(path)?,
+        }
+    }
+    worktree::apply_modes(&written)?;
+    Index::from_checkout(&merged.files).dump_to_file()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> (String, usize) {
+        let merged = merge_text(
+            base.as_bytes(),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+            "ours",
+            "theirs",
+        );
+        (String::from_utf8(merged.data).unwrap(), merged.conflicts)
+    }
+
+    #[test]
+    fn changes_of_both_sides_are_combined() {
+        let base = "1\n2\n3\n4\n5\n";
+        let (merged, conflicts) = merge(base, "one\n2\n3\n4\n5\n", "1\n2\n3\n4\nfive\n");
+        assert_eq!(conflicts, 0);
+        assert_eq!(merged, "one\n2\n3\n4\nfive\n");
+    }
+
+    #[test]
+    fn same_change_on_both_sides_is_not_a_conflict() {
+        let (merged, conflicts) = merge("a\nb\n", "a\nc\n", "a\nc\n");
+        assert_eq!(conflicts, 0);
+        assert_eq!(merged, "a\nc\n");
+    }
+
+    #[test]
+    fn deletions_and_insertions() {
+        let (merged, conflicts) = merge("a\nb\nc\n", "a\nc\n", "a\nb\nc\nd\n");
+        assert_eq!(conflicts, 0);
+        assert_eq!(merged, "a\nc\nd\n");
+    }
+
+    #[test]
+    fn conflicting_changes_get_markers() {
+        let (merged, conflicts) = merge("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+    }
+
+    #[test]
+    fn markers_go_on_their_own_lines() {
+        let (merged, conflicts) = merge("a", "b", "c");
+        assert_eq!(conflicts, 1);
+        assert_eq!(merged, "<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n");
+    }
+}
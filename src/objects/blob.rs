@@ -11,7 +11,7 @@ const BLOB_TYPE_STRING: &'static [u8] = b"blob";
 use crate::{
     base::{GILLTER_OBJECTS_DIR, GILLTTER_PATH},
     objects::{ObjectDump, ObjectPump},
-    utils,
+    remote, utils,
 };
 
 #[derive(Clone)]
@@ -40,6 +40,12 @@ impl Blob {
 
 impl ObjectPump for Blob {
     fn from_file(filepath: &Path) -> anyhow::Result<Self> {
+        // A partial clone gets blobs when they are first read
+        if !filepath.exists()
+            && let Some(sha) = filepath.file_name()
+        {
+            remote::fetch_promised(&[sha.to_string_lossy().to_string()])?;
+        }
         match fs::File::open(filepath) {
             Ok(mut file) => {
                 let mut file_contents = Vec::new();
@@ -1,17 +1,18 @@
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::anyhow;
 
 use crate::{
-    base::{GILLTER_OBJECTS_DIR, GILLTTER_PATH},
+    base::{GILLTER_OBJECTS_DIR, GILLTTER_PATH, object_path},
     objects::{
         ObjectDump, ObjectPump,
-        tree::{self, TREE_TYPE_STRING},
+        tree::{self, FileType, TREE_TYPE_STRING},
     },
     utils,
 };
@@ -89,6 +90,15 @@ impl Commit {
     pub fn get_message(&self) -> Option<String> {
         self.message.clone()
     }
+
+    pub fn set_secs_since_epoch(&mut self, secs: Option<u64>) -> &mut Self {
+        self.secs_since_epoch = secs;
+        self
+    }
+
+    pub fn get_secs_since_epoch(&self) -> Option<u64> {
+        self.secs_since_epoch
+    }
 }
 
 impl ObjectDump for Commit {
@@ -155,6 +165,26 @@ impl ObjectDump for Commit {
     }
 }
 
+pub fn read_commit(sha: &str) -> anyhow::Result<Commit> {
+    Commit::from_file(&object_path(sha)).map_err(|why| anyhow!("Bad commit {}: {}", sha, why))
+}
+
+/// Every file of the commit's tree: path -> blob hash
+pub fn read_commit_files(sha: &str) -> anyhow::Result<BTreeMap<PathBuf, String>> {
+    let tree_sha = read_commit(sha)?
+        .get_tree_sha()
+        .ok_or(anyhow!("Commit {} has no tree", sha))?;
+    tree::read_tree_files(&tree_sha)
+}
+
+/// Every file of a commit with its mode: path -> (mode, blob hash)
+pub fn read_commit_entries(sha: &str) -> anyhow::Result<BTreeMap<PathBuf, (FileType, String)>> {
+    let tree_sha = read_commit(sha)?
+        .get_tree_sha()
+        .ok_or(anyhow!("Commit {} has no tree", sha))?;
+    tree::read_tree_entries(&tree_sha)
+}
+
 impl ObjectPump for Commit {
     fn from_raw_data(data: &[u8]) -> anyhow::Result<Self> {
         let mut commit = Commit::new();
@@ -1,7 +1,19 @@
 use std::path::Path;
 
+use anyhow::anyhow;
+
+use crate::{
+    objects::{
+        commit::Commit,
+        tag::Tag,
+        tree::{Tree, TreeObject},
+    },
+    remote,
+};
+
 pub mod blob;
 pub mod commit;
+pub mod tag;
 pub mod tree;
 
 pub trait ObjectDump {
@@ -13,3 +25,46 @@ pub trait ObjectPump: Sized {
     fn from_file(filepath: &Path) -> anyhow::Result<Self>;
     fn from_raw_data(data: &[u8]) -> anyhow::Result<Self>; // raw uncompressed data
 }
+
+/// Type from the header (blob, tree, commit or tag) and contents after it of a stored object
+pub fn read_object(sha: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    let mut data = remote::read_local(sha)?;
+    // Trees end their header with '\n', everything else with '\0'
+    let header_end = data
+        .iter()
+        .position(|byte| *byte == 0 || *byte == b'\n')
+        .ok_or(anyhow!("Object {} has no header", sha))?;
+    let header = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let (obj_type, _) = header
+        .split_once(' ')
+        .ok_or(anyhow!("Object {} has a broken header", sha))?;
+    Ok((obj_type.to_string(), data.split_off(header_end + 1)))
+}
+
+/// Type from the object header: blob, tree, commit or tag
+pub fn object_type(sha: &str) -> anyhow::Result<String> {
+    Ok(read_object(sha)?.0)
+}
+
+/// Hashes of the objects an object refers to: tree and parent of a commit,
+/// entries of a tree, target of a tag. `data` is uncompressed, header included
+pub fn object_links(data: &[u8]) -> anyhow::Result<Vec<String>> {
+    let mut links = Vec::new();
+    if data.starts_with(b"commit ") {
+        let commit = Commit::from_raw_data(data)?;
+        links.extend(commit.get_tree_sha());
+        links.extend(commit.get_parent_commit_sha());
+    } else if data.starts_with(b"tree ") {
+        for object in Tree::from_raw_data(data)?.objects.values() {
+            links.push(match object {
+                TreeObject::Blob(_, sha) => sha.clone(),
+                TreeObject::Tree(subtree) => subtree.get_hash()?,
+            });
+        }
+    } else if data.starts_with(b"tag ") {
+        links.extend(Tag::from_raw_data(data)?.get_target_sha());
+    } else if !data.starts_with(b"blob ") {
+        return Err(anyhow!("Unknown object type"));
+    }
+    Ok(links)
+}
@@ -0,0 +1,270 @@
+// Annotated tag object:
+/*
+ * tag <size>\0object <target-sha>
+ * type <target-type>
+ * tag <name>
+ * tagger <username> <email> <secs-since-epoch>
+ *
+ * <message>
+ */
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTER_OBJECTS_DIR, GILLTTER_PATH},
+    objects::{ObjectDump, ObjectPump},
+    utils,
+};
+
+pub const TAG_TYPE_STRING: &'static str = "tag";
+
+pub struct Tag {
+    target_sha: Option<String>,
+    target_type: Option<String>,
+    name: Option<String>,
+    username: Option<String>,
+    email: Option<String>,
+    message: Option<String>,
+    secs_since_epoch: Option<u64>,
+}
+
+impl Tag {
+    pub fn new() -> Self {
+        Self {
+            target_sha: None,
+            target_type: None,
+            name: None,
+            username: None,
+            email: None,
+            message: None,
+            secs_since_epoch: None,
+        }
+    }
+
+    pub fn set_target(&mut self, sha: impl Into<String>, obj_type: impl Into<String>) -> &mut Self {
+        self.target_sha = Some(sha.into());
+        self.target_type = Some(obj_type.into());
+        self
+    }
+
+    pub fn get_target_sha(&self) -> Option<String> {
+        self.target_sha.clone()
+    }
+
+    pub fn get_target_type(&self) -> Option<String> {
+        self.target_type.clone()
+    }
+
+    pub fn set_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn get_name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    pub fn set_username(&mut self, username: impl Into<String>) -> &mut Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    pub fn get_username(&self) -> Option<String> {
+        self.username.clone()
+    }
+
+    pub fn set_email(&mut self, email: impl Into<String>) -> &mut Self {
+        self.email = Some(email.into());
+        self
+    }
+
+    pub fn get_email(&self) -> Option<String> {
+        self.email.clone()
+    }
+
+    pub fn set_message(&mut self, message: impl Into<String>) -> &mut Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn get_message(&self) -> Option<String> {
+        self.message.clone()
+    }
+
+    pub fn set_secs_since_epoch(&mut self, secs: Option<u64>) -> &mut Self {
+        self.secs_since_epoch = secs;
+        self
+    }
+
+    pub fn get_secs_since_epoch(&self) -> Option<u64> {
+        self.secs_since_epoch
+    }
+}
+
+impl ObjectDump for Tag {
+    fn convert_to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let target_sha = self
+            .target_sha
+            .as_ref()
+            .ok_or(anyhow!("target_sha is not set"))?;
+        let target_type = self
+            .target_type
+            .as_ref()
+            .ok_or(anyhow!("target_type is not set"))?;
+        let name = self.name.as_ref().ok_or(anyhow!("name is not set"))?;
+        let username = self
+            .username
+            .as_ref()
+            .ok_or(anyhow!("username is not set"))?;
+        let email = self.email.as_ref().ok_or(anyhow!("email is not set"))?;
+        let message = self.message.as_ref().ok_or(anyhow!("message is not set"))?;
+        let seconds_since_epoch = self.secs_since_epoch.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        });
+
+        let mut bytes = Vec::new();
+        writeln!(&mut bytes, "object {}", target_sha)?;
+        writeln!(&mut bytes, "type {}", target_type)?;
+        writeln!(&mut bytes, "tag {}", name)?;
+        writeln!(
+            &mut bytes,
+            "tagger {} {} {}",
+            username, email, seconds_since_epoch
+        )?;
+        write!(&mut bytes, "\n{}", message)?;
+
+        let mut v = Vec::new();
+        write!(&mut v, "{} {}\0", TAG_TYPE_STRING, bytes.len())?;
+        v.extend(bytes.iter());
+        Ok(v)
+    }
+
+    fn dump_to_file(&self) -> anyhow::Result<String> {
+        let tag_content = self.convert_to_bytes()?;
+        let filename = utils::generate_hash(&tag_content);
+        let filedata = utils::compress(&tag_content)?;
+
+        let path = Path::new(GILLTTER_PATH)
+            .join(GILLTER_OBJECTS_DIR)
+            .join(filename.as_str());
+        let mut file = File::create(path)?;
+        file.write_all(&filedata)?;
+        file.flush()?;
+
+        Ok(filename)
+    }
+}
+
+impl ObjectPump for Tag {
+    fn from_raw_data(data: &[u8]) -> anyhow::Result<Self> {
+        let null_pos = data
+            .iter()
+            .position(|elem| *elem == 0)
+            .ok_or(anyhow!("No null terminator"))?;
+        let header = String::from_utf8_lossy(&data[..null_pos]);
+        let (obj_type, size) = header
+            .split_once(' ')
+            .ok_or(anyhow!("Invalid tag header"))?;
+        if obj_type != TAG_TYPE_STRING {
+            return Err(anyhow!("Object type is incorrect"));
+        }
+        let content = &data[null_pos + 1..];
+        if size.parse::<usize>()? != content.len() {
+            return Err(anyhow!(
+                "Tag size does not match {} != {}",
+                size,
+                content.len()
+            ));
+        }
+
+        let content = String::from_utf8_lossy(content);
+        let (headers, message) = content
+            .split_once("\n\n")
+            .ok_or(anyhow!("No message in tag"))?;
+
+        let mut tag = Tag::new();
+        let mut target_sha = None;
+        let mut target_type = None;
+        for line in headers.lines() {
+            let (field, value) = line
+                .split_once(' ')
+                .ok_or(anyhow!("Invalid tag line '{}'", line))?;
+            match field {
+                "object" => target_sha = Some(value.to_string()),
+                "type" => target_type = Some(value.to_string()),
+                "tag" => {
+                    tag.set_name(value);
+                }
+                "tagger" => {
+                    let mut parts = value.rsplitn(3, ' ');
+                    let secs = parts.next().ok_or(anyhow!("No time in tagger"))?;
+                    let email = parts.next().ok_or(anyhow!("No email in tagger"))?;
+                    let username = parts.next().ok_or(anyhow!("No username in tagger"))?;
+                    tag.set_username(username).set_email(email);
+                    tag.secs_since_epoch = Some(secs.parse::<u64>()?);
+                }
+                _ => return Err(anyhow!("Unknown tag field '{}'", field)),
+            }
+        }
+        tag.set_target(
+            target_sha.ok_or(anyhow!("No object in tag"))?,
+            target_type.ok_or(anyhow!("No type in tag"))?,
+        )
+        .set_message(message);
+        Ok(tag)
+    }
+
+    fn from_file(filepath: &Path) -> anyhow::Result<Self> {
+        let mut file = File::open(filepath)
+            .map_err(|why| anyhow!("Could not open tag {}: {}", filepath.to_string_lossy(), why))?;
+        let mut file_contents = Vec::new();
+        file.read_to_end(&mut file_contents)?;
+
+        let data = utils::decompress(&file_contents)?;
+        Tag::from_raw_data(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag() -> Tag {
+        let mut tag = Tag::new();
+        tag.set_target(String::from_utf8_lossy(&[87u8; 40]), "commit")
+            .set_name("v1.0")
+            .set_username("Pencil")
+            .set_email("pedosia@gmail.com")
+            .set_message("First release\n\nWith a body");
+        tag
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_on_unset_fields() {
+        Tag::new().convert_to_bytes().unwrap();
+    }
+
+    #[test]
+    fn dump_and_pump() {
+        let tag_bytes = tag().convert_to_bytes().unwrap();
+        let hash = utils::generate_hash(&tag_bytes);
+
+        let tag = Tag::from_raw_data(&tag_bytes).unwrap();
+        assert_eq!(tag.get_name().unwrap(), "v1.0");
+        assert_eq!(tag.get_target_type().unwrap(), "commit");
+        assert_eq!(tag.get_message().unwrap(), "First release\n\nWith a body");
+        let hash2 = utils::generate_hash(&tag.convert_to_bytes().unwrap());
+        assert_eq!(hash, hash2);
+    }
+}
@@ -2,18 +2,18 @@ use std::{
     collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufRead, BufReader, Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use anyhow::anyhow;
 
 use crate::{
-    base::{GILLTER_OBJECTS_DIR, GILLTTER_PATH},
+    base::{GILLTER_OBJECTS_DIR, GILLTTER_PATH, object_path},
     objects::{ObjectDump, ObjectPump},
     utils,
 };
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FileType {
     RegularFile,
     ExecutableFile,
@@ -24,7 +24,7 @@ pub enum FileType {
 pub const TREE_TYPE_STRING: &'static str = "tree";
 
 impl FileType {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(self) -> Vec<u8> {
         match self {
             Self::RegularFile => b"100644".to_vec(),
             Self::ExecutableFile => b"100755".to_vec(),
@@ -43,6 +43,19 @@ impl FileType {
     }
 }
 
+/// Names of tree entries become paths of the work tree, so they must stay inside it
+pub fn check_entry_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name == GILLTTER_PATH
+        || name.contains(['/', '\0'])
+    {
+        return Err(anyhow!("'{}' can't be a name in a tree", name));
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct Object {
@@ -64,7 +77,7 @@ impl Object {
 #[derive(Clone, Debug)]
 pub enum TreeObject {
     Tree(Tree),
-    Blob(String), // sha1-hash
+    Blob(FileType, String), // mode, sha1-hash
 }
 
 #[derive(Clone, Debug)]
@@ -143,8 +156,8 @@ impl ObjectDump for Tree {
         for (path, value) in &self.objects {
             let mut type_bytes = Vec::new();
             match value {
-                TreeObject::Blob(_) => {
-                    type_bytes = FileType::RegularFile.to_bytes();
+                TreeObject::Blob(mode, _) => {
+                    type_bytes = mode.to_bytes();
                 }
                 TreeObject::Tree(_) => {
                     type_bytes = FileType::Directory.to_bytes();
@@ -153,9 +166,13 @@ impl ObjectDump for Tree {
 
             let mut obj_hash = String::new();
             match value {
-                TreeObject::Blob(hash) => {
+                TreeObject::Blob(_, hash) => {
                     obj_hash = hash.to_string();
                 }
+                // A loaded subtree is only known by its hash
+                TreeObject::Tree(tree) if tree.objects.is_empty() && !tree.sha1_hash.is_empty() => {
+                    obj_hash = tree.sha1_hash.clone();
+                }
                 TreeObject::Tree(tree) => {
                     let tree_hash = utils::generate_hash(&tree.convert_to_bytes()?);
                     obj_hash = tree_hash;
@@ -206,6 +223,58 @@ pub fn dump_tree_recursive(tree: &Tree) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn read_tree_files_impl(
+    files: &mut BTreeMap<PathBuf, (FileType, String)>,
+    current_path: &Path,
+    tree: &Tree,
+) -> anyhow::Result<()> {
+    for (path, object) in &tree.objects {
+        match object {
+            TreeObject::Blob(mode, blob_hash) => {
+                files.insert(current_path.join(path), (*mode, blob_hash.to_string()));
+            }
+            TreeObject::Tree(subtree) => {
+                let subtree = Tree::from_file(&object_path(&subtree.get_hash()?))
+                    .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
+                read_tree_files_impl(files, &current_path.join(path), &subtree)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every file of a stored tree and its subtrees: path -> (mode, blob hash)
+pub fn read_tree_entries(tree_sha: &str) -> anyhow::Result<BTreeMap<PathBuf, (FileType, String)>> {
+    let tree = Tree::from_file(&object_path(tree_sha))
+        .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
+    let mut files = BTreeMap::new();
+    read_tree_files_impl(&mut files, Path::new(""), &tree)?;
+    Ok(files)
+}
+
+/// Every file of a stored tree and its subtrees: path -> blob hash
+pub fn read_tree_files(tree_sha: &str) -> anyhow::Result<BTreeMap<PathBuf, String>> {
+    Ok(read_tree_entries(tree_sha)?
+        .into_iter()
+        .map(|(path, (_, sha))| (path, sha))
+        .collect())
+}
+
+/// Hash of the blob or tree at path inside a stored tree, None if there is nothing there
+pub fn find_path(tree_sha: &str, path: &Path) -> anyhow::Result<Option<String>> {
+    let mut sha = tree_sha.to_string();
+    for component in path.iter() {
+        let tree = Tree::from_file(&object_path(&sha))
+            .map_err(|why| anyhow!("Such tree does not exist: {}", why))?;
+        sha = match tree.get_object(&component.to_string_lossy()) {
+            Some(TreeObject::Blob(_, blob_hash)) => blob_hash.to_string(),
+            Some(TreeObject::Tree(subtree)) => subtree.get_hash()?,
+            None => return Ok(None),
+        };
+    }
+    Ok(Some(sha))
+}
+
 impl ObjectPump for Tree {
     fn from_raw_data(data: &[u8]) -> anyhow::Result<Self> {
         let mut tree = Tree::new();
@@ -239,12 +308,16 @@ impl ObjectPump for Tree {
             let obj_type_str = line_parts[0];
             let obj_path_str = line_parts[1];
             let obj_hash_str = line_parts[2];
+            check_entry_name(obj_path_str)?;
 
             let obj_type = FileType::from_bytes(obj_type_str.as_bytes())
                 .ok_or(anyhow!("Invalid object type"))?;
             match obj_type {
                 FileType::RegularFile | FileType::ExecutableFile | FileType::SymbolicLink => {
-                    tree.add_object(obj_path_str, TreeObject::Blob(obj_hash_str.to_string()));
+                    tree.add_object(
+                        obj_path_str,
+                        TreeObject::Blob(obj_type, obj_hash_str.to_string()),
+                    );
                 }
                 FileType::Directory => {
                     let mut to_be_loaded_tree = Tree::new();
@@ -294,7 +367,10 @@ mod tests {
         let mut tree = Tree::new();
         tree.add_object(
             "ddd.txt",
-            TreeObject::Blob(String::from_utf8_lossy(&[87u8; 40]).to_string()),
+            TreeObject::Blob(
+                FileType::RegularFile,
+                String::from_utf8_lossy(&[87u8; 40]).to_string(),
+            ),
         );
         tree.convert_to_bytes().unwrap();
     }
@@ -302,14 +378,20 @@ mod tests {
     #[test]
     fn add_if_not_exists() {
         let mut tree = Tree::new();
-        let obj = TreeObject::Blob(String::from_utf8_lossy(&[87u8; 40]).to_string());
+        let obj = TreeObject::Blob(
+            FileType::RegularFile,
+            String::from_utf8_lossy(&[87u8; 40]).to_string(),
+        );
         tree.add_object("ddd.txt", obj);
 
-        let obj = TreeObject::Blob(String::from_utf8_lossy(&[89u8; 40]).to_string());
+        let obj = TreeObject::Blob(
+            FileType::RegularFile,
+            String::from_utf8_lossy(&[89u8; 40]).to_string(),
+        );
         tree.add_object_if_not_exists("ddd.txt", || obj);
 
         let obj = tree.get_object("ddd.txt").unwrap();
-        if let TreeObject::Blob(data) = obj {
+        if let TreeObject::Blob(_, data) = obj {
             let a = String::from_utf8_lossy(&[87u8; 40]).to_string();
             let b = data.to_string();
             assert!(a == b)
@@ -319,13 +401,22 @@ mod tests {
     #[test]
     fn dump_and_pump() {
         let mut tree = Tree::new();
-        let obj = TreeObject::Blob(String::from_utf8_lossy(&[87u8; 40]).to_string());
+        let obj = TreeObject::Blob(
+            FileType::RegularFile,
+            String::from_utf8_lossy(&[87u8; 40]).to_string(),
+        );
         tree.add_object("ddd.txt", obj);
 
-        let obj = TreeObject::Blob(String::from_utf8_lossy(&[89u8; 40]).to_string());
+        let obj = TreeObject::Blob(
+            FileType::RegularFile,
+            String::from_utf8_lossy(&[89u8; 40]).to_string(),
+        );
         tree.add_object("ttt.txt", obj);
 
-        let obj = TreeObject::Blob(String::from_utf8_lossy(&[84u8; 40]).to_string());
+        let obj = TreeObject::Blob(
+            FileType::RegularFile,
+            String::from_utf8_lossy(&[84u8; 40]).to_string(),
+        );
         tree.add_object("zz.txt", obj);
 
         let tree_bytes = tree.convert_to_bytes().unwrap();
@@ -336,4 +427,19 @@ mod tests {
         let hash_pumped = utils::generate_hash(&tree_bytes);
         assert_eq!(hash_dumped, hash_pumped)
     }
+
+    #[test]
+    fn names_outside_the_work_tree_are_refused() {
+        let sha = "a".repeat(40);
+        for name in ["a.txt", "..", ".", ".gilltter"] {
+            let line = format!("100644 {} {}\n", name, sha);
+            let data = format!("tree {}\n{}", line.len(), line);
+            assert_eq!(
+                Tree::from_raw_data(data.as_bytes()).is_ok(),
+                name == "a.txt",
+                "{}",
+                name
+            );
+        }
+    }
 }
@@ -0,0 +1,608 @@
+// 'head' holds either a commit hash (detached), nothing (no commits yet)
+// or a branch it is attached to:
+/*
+ * ref: branches/<name>
+ */
+// and .gilltter/branches/<name> holds the commit hash of the branch.
+// .gilltter/tags/<name> holds a commit hash (lightweight tag) or a tag object hash (annotated tag).
+// .gilltter/remotes/<remote>/<branch> holds the commit hash of the branch of the remote as last fetched.
+// .gilltter/stash holds the stash commits, one per line, the newest (stash@{0}) first.
+// Revisions look like: HEAD, <branch>, <tag>, <remote>/<branch>, stash@{N}, <hash>, <hash-prefix>,
+// followed by any number of ~N, ~ or ^
+
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{
+        GILLTER_BRANCHES_DIR, GILLTER_HEAD_FILE, GILLTER_OBJECTS_DIR, GILLTTER_PATH,
+        GILLTTER_REMOTES_DIR, GILLTTER_STASH_FILE, GILLTTER_TAGS_DIR, object_path,
+    },
+    objects::{
+        ObjectPump,
+        commit::{Commit, read_commit},
+        object_type,
+        tag::Tag,
+        tree,
+    },
+    remote,
+};
+
+pub const HEAD_REF_PREFIX: &'static str = "ref: ";
+const MIN_HASH_PREFIX_LEN: usize = 4;
+const SHORT_HASH_LEN: usize = 7;
+const HASH_LEN: usize = 40;
+
+/// Abbreviated hash for messages
+pub fn short_hash(sha: &str) -> &str {
+    &sha[..SHORT_HASH_LEN.min(sha.len())]
+}
+
+/// Contents of a ref file, empty string if the file is empty or does not exist
+pub fn read_trimmed(path: &Path) -> anyhow::Result<String> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).trim().to_string()),
+        Err(why) if why.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(why) => Err(anyhow!(
+            "Could not read '{}': {}",
+            path.to_string_lossy(),
+            why
+        )),
+    }
+}
+
+fn write_truncated(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn branch_path(name: &str) -> PathBuf {
+    Path::new(GILLTTER_PATH)
+        .join(GILLTER_BRANCHES_DIR)
+        .join(name)
+}
+
+/// Commit the branch points to, None if there is no such branch (or it has no commits yet)
+pub fn branch_commit(name: &str) -> anyhow::Result<Option<String>> {
+    let sha = read_trimmed(&branch_path(name))?;
+    Ok(if sha.is_empty() { None } else { Some(sha) })
+}
+
+pub fn update_branch(name: &str, commit_sha: &str) -> anyhow::Result<()> {
+    write_truncated(&branch_path(name), commit_sha)
+}
+
+/// Names of every branch, sorted
+pub fn branch_names() -> anyhow::Result<Vec<String>> {
+    let dir = Path::new(GILLTTER_PATH).join(GILLTER_BRANCHES_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if !name.ends_with(".lock") {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub fn remote_branch_path(remote: &str, branch: &str) -> PathBuf {
+    Path::new(GILLTTER_PATH)
+        .join(GILLTTER_REMOTES_DIR)
+        .join(remote)
+        .join(branch)
+}
+
+/// Commit the branch of the remote was at when last fetched, None if it was not fetched
+pub fn remote_branch_commit(remote: &str, branch: &str) -> anyhow::Result<Option<String>> {
+    let sha = read_trimmed(&remote_branch_path(remote, branch))?;
+    Ok(if sha.is_empty() { None } else { Some(sha) })
+}
+
+pub fn update_remote_branch(remote: &str, branch: &str, commit_sha: &str) -> anyhow::Result<()> {
+    check_ref_name(branch)?;
+    let path = remote_branch_path(remote, branch);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_truncated(&path, commit_sha)
+}
+
+/// Branch and tag names can't be confused with revision expressions or paths
+pub fn check_ref_name(name: &str) -> anyhow::Result<()> {
+    let bad_char = |ch: char| ch.is_whitespace() || ch.is_control() || "/\\~^:?*[{}@".contains(ch);
+    if name.is_empty()
+        || name.starts_with('-')
+        || name.starts_with('.')
+        || name.contains("..")
+        || name.chars().any(bad_char)
+    {
+        return Err(anyhow!("'{}' is not a valid name", name));
+    }
+    Ok(())
+}
+
+/// 40 lowercase hex digits, as object names are
+pub fn is_hash(sha: &str) -> bool {
+    sha.len() == 40
+        && sha
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Hashes coming from elsewhere end up in paths, so nothing but a hash is let through
+pub fn check_hash(sha: &str) -> anyhow::Result<()> {
+    if !is_hash(sha) {
+        return Err(anyhow!("'{}' is not an object hash", sha));
+    }
+    Ok(())
+}
+
+/// Names are checked here so that no tag lookup can reach outside the tags directory
+pub fn tag_path(name: &str) -> anyhow::Result<PathBuf> {
+    check_ref_name(name)?;
+    Ok(Path::new(GILLTTER_PATH).join(GILLTTER_TAGS_DIR).join(name))
+}
+
+/// Hash the tag holds (tag object or commit), None if there is no such tag
+pub fn tag_object(name: &str) -> anyhow::Result<Option<String>> {
+    // Revisions are looked up as tags too, and no tag can have an invalid name
+    let Ok(path) = tag_path(name) else {
+        return Ok(None);
+    };
+    let sha = read_trimmed(&path)?;
+    Ok(if sha.is_empty() { None } else { Some(sha) })
+}
+
+pub fn update_tag(name: &str, sha: &str) -> anyhow::Result<()> {
+    let path = tag_path(name)?;
+    fs::create_dir_all(Path::new(GILLTTER_PATH).join(GILLTTER_TAGS_DIR))?;
+    write_truncated(&path, sha)
+}
+
+pub fn delete_tag(name: &str) -> anyhow::Result<()> {
+    fs::remove_file(tag_path(name)?)
+        .map_err(|why| anyhow!("Could not delete tag '{}': {}", name, why))
+}
+
+/// Names of every tag, sorted
+pub fn tag_names() -> anyhow::Result<Vec<String>> {
+    let dir = Path::new(GILLTTER_PATH).join(GILLTTER_TAGS_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        names.push(entry?.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Follows tag objects down to the object they tag
+pub fn peel(sha: &str) -> anyhow::Result<String> {
+    let mut sha = sha.to_string();
+    while object_type(&sha)? == "tag" {
+        sha = Tag::from_file(&object_path(&sha))?
+            .get_target_sha()
+            .ok_or(anyhow!("Tag {} has no target", sha))?;
+    }
+    Ok(sha)
+}
+
+/// Name of the branch head is attached to, None if head is detached
+pub fn current_branch() -> anyhow::Result<Option<String>> {
+    let head = read_trimmed(&Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE))?;
+    Ok(head
+        .strip_prefix(HEAD_REF_PREFIX)
+        .and_then(|target| target.strip_prefix(GILLTER_BRANCHES_DIR))
+        .map(|name| name.trim_start_matches('/').to_string()))
+}
+
+/// Commit head points to, None if there are no commits yet
+pub fn head_commit() -> anyhow::Result<Option<String>> {
+    if let Some(branch) = current_branch()? {
+        return branch_commit(&branch);
+    }
+    let head = read_trimmed(&Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE))?;
+    Ok(if head.is_empty() { None } else { Some(head) })
+}
+
+/// Moves the branch head is attached to, or head itself if it is detached
+pub fn update_head(commit_sha: &str) -> anyhow::Result<()> {
+    match current_branch()? {
+        Some(branch) => update_branch(&branch, commit_sha),
+        None => write_truncated(
+            &Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE),
+            commit_sha,
+        ),
+    }
+}
+
+/// What 'head' holds as is: a commit hash, "ref: branches/<name>" or nothing
+pub fn read_head() -> anyhow::Result<String> {
+    read_trimmed(&Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE))
+}
+
+/// Overwrites 'head', e.g. to detach it at a commit or to put back what read_head returned
+pub fn write_head(contents: &str) -> anyhow::Result<()> {
+    write_truncated(&Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE), contents)
+}
+
+/// File behind a ref name: HEAD (or the branch it is attached to), branches/<name>,
+/// tags/<name> or just a branch name
+pub fn ref_file(name: &str) -> anyhow::Result<PathBuf> {
+    if name == "HEAD" {
+        return Ok(match current_branch()? {
+            Some(branch) => branch_path(&branch),
+            None => Path::new(GILLTTER_PATH).join(GILLTER_HEAD_FILE),
+        });
+    }
+    let (dir, short_name) = match name.split_once('/') {
+        Some((dir, short_name)) if dir == GILLTER_BRANCHES_DIR || dir == GILLTTER_TAGS_DIR => {
+            (dir, short_name)
+        }
+        _ => (GILLTER_BRANCHES_DIR, name),
+    };
+    check_ref_name(short_name)?;
+    Ok(Path::new(GILLTTER_PATH).join(dir).join(short_name))
+}
+
+/// Points the ref file to `new` (deletes it if None), but only if it still holds `expected`:
+/// Some("") means the ref must not exist, None skips the check. The ref is locked
+/// with a "<ref>.lock" file meanwhile, so concurrent updates fail instead of racing
+pub fn compare_and_swap(
+    path: &Path,
+    expected: Option<&str>,
+    new: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut lock_name = path.as_os_str().to_owned();
+    lock_name.push(".lock");
+    let lock_path = PathBuf::from(lock_name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut lock = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock_path)
+        .map_err(|why| anyhow!("Could not lock '{}': {}", path.to_string_lossy(), why))?;
+
+    let mut swap = || -> anyhow::Result<()> {
+        let current = read_trimmed(path)?;
+        if let Some(expected) = expected
+            && current != expected
+        {
+            if expected.is_empty() {
+                return Err(anyhow!("'{}' already exists", path.to_string_lossy()));
+            }
+            return Err(anyhow!(
+                "'{}' is at '{}', not at '{}'",
+                path.to_string_lossy(),
+                current,
+                expected
+            ));
+        }
+        match new {
+            Some(new) => {
+                lock.write_all(new.as_bytes())?;
+                lock.flush()?;
+                fs::rename(&lock_path, path)?;
+            }
+            None if !current.is_empty() => fs::remove_file(path)?,
+            None => {}
+        }
+        Ok(())
+    };
+    let result = swap();
+    if lock_path.exists() {
+        fs::remove_file(&lock_path)?;
+    }
+    result
+}
+
+/// Stash commits, stash@{0} first
+pub fn stash_list() -> anyhow::Result<Vec<String>> {
+    Ok(
+        read_trimmed(&Path::new(GILLTTER_PATH).join(GILLTTER_STASH_FILE))?
+            .lines()
+            .map(|line| line.to_string())
+            .collect(),
+    )
+}
+
+pub fn write_stash_list(stashes: &[String]) -> anyhow::Result<()> {
+    write_truncated(
+        &Path::new(GILLTTER_PATH).join(GILLTTER_STASH_FILE),
+        &stashes.join("\n"),
+    )
+}
+
+/// Position in the stash list of 'stash@{N}', 'stash' or just 'N'
+pub fn parse_stash_ref(stash: &str) -> Option<usize> {
+    if stash == "stash" {
+        return Some(0);
+    }
+    stash
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(stash)
+        .parse()
+        .ok()
+}
+
+// Full hash of the only object starting with prefix
+fn expand_hash_prefix(prefix: &str) -> anyhow::Result<Option<String>> {
+    if prefix.len() < MIN_HASH_PREFIX_LEN || !prefix.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    if prefix.len() == HASH_LEN {
+        // A partial clone may have to fetch it first
+        remote::fetch_promised(&[prefix.to_string()])?;
+    }
+    if object_path(prefix).is_file() {
+        return Ok(Some(prefix.to_string()));
+    }
+
+    let mut found: Option<String> = None;
+    for entry in fs::read_dir(Path::new(GILLTTER_PATH).join(GILLTER_OBJECTS_DIR))? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name.starts_with(prefix) {
+            if found.is_some() {
+                return Err(anyhow!("Hash prefix '{}' is ambiguous", prefix));
+            }
+            found = Some(name);
+        }
+    }
+    Ok(found)
+}
+
+fn resolve_base(base: &str) -> anyhow::Result<String> {
+    if base == "HEAD" || base == "@" {
+        return head_commit()?.ok_or(anyhow!("HEAD does not point to a commit yet"));
+    }
+    if let Some(sha) = branch_commit(base)? {
+        return Ok(sha);
+    }
+    if let Some(sha) = tag_object(base)? {
+        return peel(&sha);
+    }
+    if let Some((remote, branch)) = base.split_once('/')
+        && let Some(sha) = remote_branch_commit(remote, branch)?
+    {
+        return Ok(sha);
+    }
+    if base.starts_with("stash")
+        && let Some(pos) = parse_stash_ref(base)
+    {
+        return stash_list()?
+            .get(pos)
+            .cloned()
+            .ok_or(anyhow!("There is no stash@{{{}}}", pos));
+    }
+    if let Some(sha) = expand_hash_prefix(base)? {
+        return Ok(sha);
+    }
+    Err(anyhow!("Unknown revision '{}'", base))
+}
+
+/// Resolves a revision expression to a full object hash
+pub fn resolve_revision(revision: &str) -> anyhow::Result<String> {
+    let suffix_start = revision.find(['~', '^']).unwrap_or(revision.len());
+    let (base, mut suffix) = revision.split_at(suffix_start);
+    let mut sha = resolve_base(base)?;
+    let shallow = shallow_commits();
+
+    while !suffix.is_empty() {
+        let digits_end = suffix[1..]
+            .find(|ch: char| !ch.is_ascii_digit())
+            .map(|pos| pos + 1)
+            .unwrap_or(suffix.len());
+        let steps = match (&suffix[..1], &suffix[1..digits_end]) {
+            (_, "") => 1,
+            ("~", digits) => digits.parse::<usize>()?,
+            // Commits have a single parent, so ^1 is the only parent there is
+            (_, "1") => 1,
+            (_, _) => {
+                return Err(anyhow!(
+                    "'{}' names a parent other than the first, commits have only one",
+                    revision
+                ));
+            }
+        };
+        for _ in 0..steps {
+            sha = history_parent(&sha, &read_commit(&sha)?, &shallow)
+                .ok_or(anyhow!("'{}' goes past the first commit", revision))?;
+        }
+        suffix = &suffix[digits_end..];
+    }
+    Ok(sha)
+}
+
+/// Commits of this repository stored without their parents (clone --depth)
+pub fn shallow_commits() -> BTreeSet<String> {
+    remote::shallow_commits(Path::new(GILLTTER_PATH))
+}
+
+/// Parent of the commit as far as history goes here: parents of shallow commits
+/// were never fetched, so history ends at them as if they were root commits
+pub fn history_parent(sha: &str, commit: &Commit, shallow: &BTreeSet<String>) -> Option<String> {
+    if shallow.contains(sha) {
+        return None;
+    }
+    commit.get_parent_commit_sha()
+}
+
+/// The commit followed by its first parents, down to the root (or a shallow) commit
+pub fn first_parent_chain(sha: &str) -> anyhow::Result<Vec<String>> {
+    let shallow = shallow_commits();
+    let mut chain = vec![sha.to_string()];
+    loop {
+        let last = chain.last().unwrap();
+        let Some(parent) = history_parent(last, &read_commit(last)?, &shallow) else {
+            break;
+        };
+        chain.push(parent);
+    }
+    Ok(chain)
+}
+
+/// Resolves revisions and `<from>..<to>` ranges (commits reachable from `to`
+/// but not from `from`, first parents only) to commits, oldest first within a range
+pub fn resolve_commits(revisions: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut commits = Vec::new();
+    for revision in revisions {
+        let Some((from, to)) = revision.split_once("..") else {
+            let sha = resolve_revision(revision)?;
+            read_commit(&sha)?; // make sure it is a commit
+            commits.push(sha);
+            continue;
+        };
+        let from = resolve_revision(if from.is_empty() { "HEAD" } else { from })?;
+        let to = resolve_revision(if to.is_empty() { "HEAD" } else { to })?;
+
+        let excluded = first_parent_chain(&from)?;
+        let mut range: Vec<String> = first_parent_chain(&to)?
+            .into_iter()
+            .take_while(|sha| !excluded.contains(sha))
+            .collect();
+        range.reverse();
+        commits.extend(range);
+    }
+    Ok(commits)
+}
+
+/// Object a name refers to, tags are not peeled: <revision>, <tag> or <revision>:<path>
+pub fn resolve_object(name: &str) -> anyhow::Result<String> {
+    if let Some((revision, path)) = name.split_once(':') {
+        let commit = resolve_revision(if revision.is_empty() {
+            "HEAD"
+        } else {
+            revision
+        })?;
+        let tree_sha = read_commit(&commit)?
+            .get_tree_sha()
+            .ok_or(anyhow!("Commit {} has no tree", commit))?;
+        let path = crate::ignore::normalize_path(Path::new(path.trim_start_matches('/')));
+        return tree::find_path(&tree_sha, &path)?.ok_or(anyhow!(
+            "Path '{}' does not exist in '{}'",
+            path.to_string_lossy(),
+            revision
+        ));
+    }
+    if branch_commit(name)?.is_none()
+        && let Some(sha) = tag_object(name)?
+    {
+        return Ok(sha);
+    }
+    resolve_revision(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    #[test]
+    fn compare_and_swap_checks_the_old_value() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+        let path = branch_path("main");
+
+        assert!(compare_and_swap(&path, Some(&first), Some(&first)).is_err());
+        assert_eq!(branch_commit("main").unwrap(), Some(second.clone()));
+        assert!(compare_and_swap(&path, Some(""), Some(&first)).is_err());
+        assert_eq!(branch_commit("main").unwrap(), Some(second.clone()));
+
+        compare_and_swap(&path, Some(&second), Some(&first)).unwrap();
+        assert_eq!(branch_commit("main").unwrap(), Some(first.clone()));
+        compare_and_swap(&branch_path("new"), Some(""), Some(&first)).unwrap();
+        assert_eq!(branch_commit("new").unwrap(), Some(first.clone()));
+        compare_and_swap(&branch_path("new"), Some(&first), None).unwrap();
+        assert_eq!(branch_commit("new").unwrap(), None);
+        assert!(
+            !Path::new(GILLTTER_PATH)
+                .join(GILLTER_BRANCHES_DIR)
+                .join("new.lock")
+                .exists()
+        );
+    }
+
+    #[test]
+    fn locked_refs_are_not_updated() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+
+        let lock = Path::new(GILLTTER_PATH)
+            .join(GILLTER_BRANCHES_DIR)
+            .join("main.lock");
+        fs::write(&lock, "").unwrap();
+        assert!(compare_and_swap(&branch_path("main"), None, Some(&first)).is_err());
+        assert_eq!(branch_commit("main").unwrap(), Some(second));
+        // The lock belongs to someone else, so it is left alone
+        assert!(lock.exists());
+    }
+
+    #[test]
+    fn caret_names_only_the_first_parent() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+        repository.add("a.txt", "three\n");
+        repository.commit("third");
+
+        assert_eq!(resolve_revision("HEAD^").unwrap(), second);
+        assert_eq!(resolve_revision("HEAD^1").unwrap(), second);
+        assert_eq!(resolve_revision("HEAD^^").unwrap(), first);
+        assert_eq!(resolve_revision("HEAD^1~1").unwrap(), first);
+        assert_eq!(resolve_revision("HEAD~2").unwrap(), first);
+        assert!(resolve_revision("HEAD^2").is_err());
+        assert!(resolve_revision("HEAD~3").is_err());
+    }
+
+    #[test]
+    fn history_ends_at_shallow_commits() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "one\n");
+        repository.commit("first");
+        repository.add("a.txt", "two\n");
+        let second = repository.commit("second");
+        repository.add("a.txt", "three\n");
+        let third = repository.commit("third");
+
+        remote::add_shallow_commits(std::slice::from_ref(&second)).unwrap();
+        assert_eq!(first_parent_chain(&third).unwrap(), [third, second.clone()]);
+        assert_eq!(resolve_revision("HEAD~1").unwrap(), second);
+        assert!(resolve_revision("HEAD~2").is_err());
+        // The stored commit keeps its parent
+        assert!(
+            read_commit(&second)
+                .unwrap()
+                .get_parent_commit_sha()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+// A bundle is a file holding refs and the objects they need, for moving history
+// without a connection:
+/*
+ * # gilltter bundle v1
+ * -<hash>                      prerequisite: a commit the receiver must have already
+ * <hash> branches/<name>       refs, named like remotes name them (see mod.rs)
+ * ref: branches/<name> HEAD
+ *
+ * <pack, see pack.rs>
+ */
+// The pack holds what the refs need beyond the prerequisites. A bundle works as a remote:
+// its path can be cloned and fetched from, but not pushed to.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    remote::{FetchFilter, Fetched, RefUpdate, Transport, pack},
+};
+
+const BUNDLE_SIGNATURE: &'static str = "# gilltter bundle v1";
+const PREREQUISITE_PREFIX: &'static str = "-";
+
+pub struct Bundle {
+    pub path: PathBuf,
+    /// Commits the receiver must have
+    pub prerequisites: Vec<String>,
+    /// name -> hash, "HEAD" -> what the head of the bundled repository held
+    pub refs: BTreeMap<String, String>,
+}
+
+/// Whether the file at path is a bundle
+pub fn is_bundle(path: &Path) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut start = vec![0; BUNDLE_SIGNATURE.len()];
+    file.read_exact(&mut start).is_ok() && start == BUNDLE_SIGNATURE.as_bytes()
+}
+
+fn write_header(
+    output: &mut impl Write,
+    refs: &BTreeMap<String, String>,
+    prerequisites: &[String],
+) -> anyhow::Result<()> {
+    writeln!(output, "{}", BUNDLE_SIGNATURE)?;
+    for sha in prerequisites {
+        writeln!(output, "{}{}", PREREQUISITE_PREFIX, sha)?;
+    }
+    for (name, value) in refs {
+        writeln!(output, "{} {}", value, name)?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+// Prerequisites and refs, the input is left at the pack
+fn read_header(
+    input: &mut impl BufRead,
+) -> anyhow::Result<(Vec<String>, BTreeMap<String, String>)> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        if lines.is_empty() && line.trim_end() != BUNDLE_SIGNATURE {
+            return Err(anyhow!("Not a bundle"));
+        }
+        match line.strip_suffix('\n') {
+            Some("") => break,
+            Some(line) => lines.push(line.to_string()),
+            None => return Err(anyhow!("The bundle ends before its objects")),
+        }
+    }
+
+    let mut prerequisites = Vec::new();
+    let mut refs = BTreeMap::new();
+    for line in &lines[1..] {
+        if let Some(sha) = line.strip_prefix(PREREQUISITE_PREFIX) {
+            prerequisites.push(sha.to_string());
+        } else {
+            let (value, name) = line
+                .rsplit_once(' ')
+                .ok_or(anyhow!("Broken bundle line '{}'", line))?;
+            refs.insert(name.to_string(), value.to_string());
+        }
+    }
+    Ok((prerequisites, refs))
+}
+
+/// Writes a bundle of the refs, the objects must hold everything the refs need
+/// beyond the prerequisites, referred objects after the objects referring to them
+pub fn write_bundle(
+    path: &Path,
+    refs: &BTreeMap<String, String>,
+    prerequisites: &[String],
+    objects: &[String],
+) -> anyhow::Result<()> {
+    let mut output = BufWriter::new(File::create(path)?);
+    write_header(&mut output, refs, prerequisites)?;
+    let objects: Vec<String> = objects.iter().rev().cloned().collect();
+    pack::write_pack(&objects, &mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+impl Bundle {
+    // The bundle and its file left at the pack
+    fn open_file(path: &Path) -> anyhow::Result<(Self, BufReader<File>)> {
+        let file = File::open(path).map_err(|why| {
+            anyhow!(
+                "Could not open bundle '{}': {}",
+                path.to_string_lossy(),
+                why
+            )
+        })?;
+        let mut input = BufReader::new(file);
+        let (prerequisites, refs) = read_header(&mut input)
+            .map_err(|why| anyhow!("{}: {}", path.to_string_lossy(), why))?;
+        let bundle = Self {
+            path: path.to_path_buf(),
+            prerequisites,
+            refs,
+        };
+        Ok((bundle, input))
+    }
+
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::open_file(path).map(|(bundle, _)| bundle)
+    }
+
+    /// Prerequisites this repository does not have
+    pub fn missing_prerequisites(&self) -> Vec<String> {
+        self.prerequisites
+            .iter()
+            .filter(|sha| !object_path(sha).exists())
+            .cloned()
+            .collect()
+    }
+
+    /// Stores the objects of the bundle, returns their hashes.
+    /// Fails if this repository lacks a prerequisite
+    pub fn unbundle(&self) -> anyhow::Result<Vec<String>> {
+        if let Some(sha) = self.missing_prerequisites().first() {
+            return Err(anyhow!(
+                "The bundle needs commit {} which this repository does not have",
+                sha
+            ));
+        }
+        let (_, mut input) = Self::open_file(&self.path)?;
+        pack::read_pack(&mut input)
+    }
+}
+
+pub struct BundleTransport {
+    bundle: Bundle,
+}
+
+impl BundleTransport {
+    pub fn open(url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            bundle: Bundle::open(Path::new(url))?,
+        })
+    }
+}
+
+impl Transport for BundleTransport {
+    fn advertised_refs(&mut self) -> anyhow::Result<BTreeMap<String, String>> {
+        Ok(self.bundle.refs.clone())
+    }
+
+    // The bundle has what it has, all of it is stored whatever is wanted
+    fn fetch(&mut self, _wants: &[String], _filter: &FetchFilter) -> anyhow::Result<Fetched> {
+        Ok(Fetched {
+            objects: self.bundle.unbundle()?.len(),
+            shallow: Vec::new(),
+        })
+    }
+
+    fn push(&mut self, _updates: &[RefUpdate]) -> anyhow::Result<()> {
+        Err(anyhow!("Can't push to a bundle"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_survives_writing() {
+        let refs = BTreeMap::from([
+            ("HEAD".to_string(), "ref: branches/main".to_string()),
+            ("branches/main".to_string(), "a".repeat(40)),
+        ]);
+        let prerequisites = vec!["b".repeat(40)];
+        let mut data = Vec::new();
+        write_header(&mut data, &refs, &prerequisites).unwrap();
+        data.extend(b"PACK 0\n");
+
+        let mut input = data.as_slice();
+        let (read_prerequisites, read_refs) = read_header(&mut input).unwrap();
+        assert_eq!(read_prerequisites, prerequisites);
+        assert_eq!(read_refs, refs);
+        assert_eq!(input, b"PACK 0\n");
+
+        assert!(read_header(&mut b"PACK 0\n\n".as_slice()).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+// `gilltter http-serve` serves one repository over plain HTTP/1.1:
+/*
+ * GET  /refs               "<ref name> <value>\n" per ref, see remote/mod.rs
+ * GET  /objects/<hash>     the object as it is stored (compressed)
+ * POST /fetch              fetch lines -> shallow lines, an empty line and the pack,
+ *                          see protocol.rs and pack.rs
+ * POST /push               update lines, an empty line and the pack -> "ok"
+ */
+// A url "http://<host>[:<port>][/<prefix>]" puts <prefix> before these paths, so the server
+// may sit behind a proxy. Every request is a separate connection. The server takes
+// request bodies up to MAX_REQUEST_SIZE and drops connections which stall for IO_TIMEOUT.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTTER_PATH, object_path},
+    refs,
+    remote::{self, FetchFilter, Fetched, RefUpdate, Transport, pack, protocol},
+};
+
+pub const HTTP_URL_PREFIX: &'static str = "http://";
+const DEFAULT_PORT: u16 = 80;
+const REFS_PATH: &'static str = "/refs";
+const OBJECTS_PATH: &'static str = "/objects/";
+const FETCH_PATH: &'static str = "/fetch";
+const PUSH_PATH: &'static str = "/push";
+const TEXT_TYPE: &'static str = "text/plain; charset=utf-8";
+const BINARY_TYPE: &'static str = "application/octet-stream";
+const MAX_REQUEST_SIZE: usize = 1 << 30;
+const MAX_LINE_SIZE: u64 = 8 * 1024;
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: u16, message: String) -> Self {
+        Self {
+            status,
+            content_type: TEXT_TYPE,
+            body: message.into_bytes(),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Error",
+    }
+}
+
+// A line of the start or the headers, at most MAX_LINE_SIZE long
+fn read_line(input: &mut impl BufRead) -> anyhow::Result<String> {
+    let mut line = String::new();
+    input.by_ref().take(MAX_LINE_SIZE).read_line(&mut line)?;
+    if line.len() as u64 == MAX_LINE_SIZE && !line.ends_with('\n') {
+        return Err(anyhow!("A header line is over {} bytes", MAX_LINE_SIZE));
+    }
+    Ok(line)
+}
+
+/// Status line, headers and body of a request or a response. Without Content-Length
+/// a request has no body and a response runs until the connection is closed.
+/// The body grows as it arrives, a request body over MAX_REQUEST_SIZE is refused
+fn read_message(input: &mut impl BufRead, is_request: bool) -> anyhow::Result<(String, Vec<u8>)> {
+    let start_line = read_line(input)?.trim_end().to_string();
+    if start_line.is_empty() {
+        return Err(anyhow!("Empty message"));
+    }
+
+    let mut content_length = None;
+    loop {
+        let header = read_line(input)?;
+        if header.is_empty() {
+            return Err(anyhow!("Message ends in the headers"));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let mut body = Vec::new();
+    match content_length {
+        Some(length) if is_request && length > MAX_REQUEST_SIZE => {
+            return Err(anyhow!(
+                "The body of {} bytes is over the limit of {}",
+                length,
+                MAX_REQUEST_SIZE
+            ));
+        }
+        Some(length) => {
+            input.by_ref().take(length as u64).read_to_end(&mut body)?;
+            if body.len() != length {
+                return Err(anyhow!("Message ends in the body"));
+            }
+        }
+        None if !is_request => {
+            input.read_to_end(&mut body)?;
+        }
+        None => {}
+    }
+    Ok((start_line, body))
+}
+
+fn lines_of(body: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Lines up to the first empty one and the data after it
+fn split_body(body: &[u8]) -> anyhow::Result<(Vec<String>, Vec<u8>)> {
+    let mut input = body;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(anyhow!("The body has no data after the lines"));
+        }
+        match line.trim_end() {
+            "" => return Ok((lines, input.to_vec())),
+            line => lines.push(line.to_string()),
+        }
+    }
+}
+
+fn join_body(lines: &[String], data: Vec<u8>) -> Vec<u8> {
+    let mut body = Vec::new();
+    for line in lines {
+        body.extend(line.as_bytes());
+        body.push(b'\n');
+    }
+    body.push(b'\n');
+    body.extend(data);
+    body
+}
+
+fn route(method: &str, path: &str, body: Vec<u8>) -> anyhow::Result<Response> {
+    Ok(match (method, path) {
+        ("GET", REFS_PATH) => {
+            let mut text = String::new();
+            for (name, value) in remote::list_refs(Path::new(GILLTTER_PATH))? {
+                text.push_str(&format!("{} {}\n", name, value));
+            }
+            Response::ok(TEXT_TYPE, text.into_bytes())
+        }
+        ("GET", path) if path.starts_with(OBJECTS_PATH) => {
+            let sha = &path[OBJECTS_PATH.len()..];
+            match refs::is_hash(sha).then(|| std::fs::read(object_path(sha))) {
+                Some(Ok(contents)) => Response::ok(BINARY_TYPE, contents),
+                _ => Response::error(404, format!("There is no object {}", sha)),
+            }
+        }
+        ("POST", FETCH_PATH) => match protocol::answer_fetch(&lines_of(&body)) {
+            Ok((shallow, pack_data)) => Response::ok(BINARY_TYPE, join_body(&shallow, pack_data)),
+            Err(why) => Response::error(400, why.to_string()),
+        },
+        ("POST", PUSH_PATH) => {
+            let (lines, pack_data) = split_body(&body)?;
+            match protocol::accept_push(&lines, pack_data) {
+                Ok(()) => Response::ok(TEXT_TYPE, b"ok".to_vec()),
+                Err(why) => Response::error(409, why.to_string()),
+            }
+        }
+        (_, REFS_PATH | FETCH_PATH | PUSH_PATH) => {
+            Response::error(405, format!("{} is not allowed here", method))
+        }
+        _ => Response::error(404, format!("There is nothing at {}", path)),
+    })
+}
+
+fn handle(stream: TcpStream) -> anyhow::Result<()> {
+    // A client which stops sending or reading must not hold the server up
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut input = BufReader::new(&stream);
+    let (request_line, body) = read_message(&mut input, true)?;
+    let mut parts = request_line.split(' ');
+    let (method, path) = (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    );
+
+    let response =
+        route(method, path, body).unwrap_or_else(|why| Response::error(400, why.to_string()));
+    println!("{} {} {}", method, path, response.status);
+
+    let mut output = &stream;
+    write!(
+        output,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )?;
+    output.write_all(&response.body)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Serves the repository in the current directory, one request at a time
+pub fn serve(listener: TcpListener) -> anyhow::Result<()> {
+    for stream in listener.incoming() {
+        if let Err(why) = stream.map_err(anyhow::Error::from).and_then(handle) {
+            eprintln!("Request failed: {}", why);
+        }
+    }
+    Ok(())
+}
+
+/// Client of a `gilltter http-serve`
+pub struct HttpTransport {
+    address: String,
+    prefix: String,
+    refs: BTreeMap<String, String>,
+}
+
+impl HttpTransport {
+    /// `url` is "http://<host>[:<port>][/<prefix>]"
+    pub fn connect(url: &str) -> anyhow::Result<Self> {
+        let location = url
+            .strip_prefix(HTTP_URL_PREFIX)
+            .ok_or(anyhow!("'{}' is not an http url", url))?;
+        let (host, prefix) = match location.split_once('/') {
+            Some((host, prefix)) => (host, format!("/{}", prefix)),
+            None => (location, String::new()),
+        };
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, DEFAULT_PORT)
+        };
+
+        let mut transport = Self {
+            address,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            refs: BTreeMap::new(),
+        };
+        for line in lines_of(&transport.request("GET", REFS_PATH, &[])?) {
+            let (name, value) = line.split_once(' ').unwrap_or((&line, ""));
+            transport.refs.insert(name.to_string(), value.to_string());
+        }
+        Ok(transport)
+    }
+
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let stream = TcpStream::connect(&self.address)
+            .map_err(|why| anyhow!("Could not connect to {}: {}", self.address, why))?;
+        let mut output = &stream;
+        write!(
+            output,
+            "{} {}{} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            self.prefix,
+            path,
+            self.address,
+            BINARY_TYPE,
+            body.len()
+        )?;
+        output.write_all(body)?;
+        output.flush()?;
+
+        let (status_line, body) = read_message(&mut BufReader::new(&stream), false)?;
+        let status = status_line.split(' ').nth(1).unwrap_or_default();
+        if status != "200" {
+            return Err(anyhow!(
+                "The server says ({}): {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        Ok(body)
+    }
+}
+
+impl Transport for HttpTransport {
+    fn advertised_refs(&mut self) -> anyhow::Result<BTreeMap<String, String>> {
+        Ok(self.refs.clone())
+    }
+
+    fn fetch(&mut self, wants: &[String], filter: &FetchFilter) -> anyhow::Result<Fetched> {
+        let Some(lines) = protocol::fetch_request(wants, filter)? else {
+            return Ok(Fetched {
+                objects: 0,
+                shallow: Vec::new(),
+            });
+        };
+        let body = self.request("POST", FETCH_PATH, lines.join("\n").as_bytes())?;
+        let (shallow, pack_data) = split_body(&body)?;
+        Ok(Fetched {
+            objects: pack::read_pack(&mut pack_data.as_slice())?.len(),
+            shallow: protocol::shallow_commits(&shallow)?,
+        })
+    }
+
+    fn push(&mut self, updates: &[RefUpdate]) -> anyhow::Result<()> {
+        let (lines, pack_data) = protocol::push_request(updates, &self.refs)?;
+        self.request("POST", PUSH_PATH, &join_body(&lines, pack_data))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    #[test]
+    fn bodies_over_the_limit_are_refused() {
+        let request = format!(
+            "POST /push HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        );
+        assert!(read_message(&mut request.as_bytes(), true).is_err());
+        let request = b"POST /push HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort";
+        assert!(read_message(&mut request.as_slice(), true).is_err());
+        let request = format!(
+            "GET /{} HTTP/1.1\r\n\r\n",
+            "a".repeat(MAX_LINE_SIZE as usize)
+        );
+        assert!(read_message(&mut request.as_bytes(), true).is_err());
+    }
+
+    // Server and client share the repository, which is the current directory
+    #[test]
+    fn fetch_and_push_over_localhost() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let first = repository.commit("first");
+        repository.add("a.txt", "b\n");
+        let second = repository.commit("second");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || serve(listener));
+
+        let mut transport = HttpTransport::connect(&url).unwrap();
+        let refs = transport.list_refs().unwrap();
+        assert_eq!(refs["branches/main"], second);
+
+        // What fetch sends for an object which is not here yet
+        let body = transport
+            .request(
+                "POST",
+                FETCH_PATH,
+                format!("want {}\nonly", second).as_bytes(),
+            )
+            .unwrap();
+        let (shallow, pack_data) = split_body(&body).unwrap();
+        assert!(shallow.is_empty());
+        let objects = pack::read_pack(&mut pack_data.as_slice()).unwrap();
+        assert_eq!(objects, std::slice::from_ref(&second));
+        let unknown = "f".repeat(40);
+        assert!(
+            transport
+                .fetch(&[unknown], &FetchFilter::default())
+                .is_err()
+        );
+
+        let update = RefUpdate {
+            name: "branches/old".to_string(),
+            old: None,
+            new: first.clone(),
+        };
+        transport.push(&[update]).unwrap();
+        assert_eq!(refs::branch_commit("old").unwrap(), Some(first.clone()));
+        // The remote has moved on since the refs were listed
+        let stale = RefUpdate {
+            name: "branches/old".to_string(),
+            old: None,
+            new: second,
+        };
+        assert!(transport.push(&[stale]).is_err());
+        assert_eq!(refs::branch_commit("old").unwrap(), Some(first));
+
+        assert!(HttpTransport::connect(&format!("{}/nothing/here", url)).is_err());
+    }
+}
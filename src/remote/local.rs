@@ -0,0 +1,71 @@
+// Remote repository on a local path (or a mounted share), objects and refs are copied directly
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTER_OBJECTS_DIR, object_path},
+    remote::{self, FetchFilter, Fetched, RefUpdate, Transport},
+    utils,
+};
+
+pub struct LocalTransport {
+    dir: PathBuf, // .gilltter directory of the remote
+}
+
+impl LocalTransport {
+    /// The url is the path of the repository or of its .gilltter directory
+    pub fn open(url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            dir: remote::repository_dir(Path::new(url))?,
+        })
+    }
+
+    fn object_path(&self, sha: &str) -> PathBuf {
+        self.dir.join(GILLTER_OBJECTS_DIR).join(sha)
+    }
+
+    fn read_remote(&self, sha: &str) -> anyhow::Result<Vec<u8>> {
+        let contents = fs::read(self.object_path(sha))
+            .map_err(|why| anyhow!("Remote has no object {}: {}", sha, why))?;
+        utils::decompress(&contents)
+    }
+}
+
+impl Transport for LocalTransport {
+    fn advertised_refs(&mut self) -> anyhow::Result<BTreeMap<String, String>> {
+        remote::list_refs(&self.dir)
+    }
+
+    fn fetch(&mut self, wants: &[String], filter: &FetchFilter) -> anyhow::Result<Fetched> {
+        let (missing, shallow) = remote::objects_to_send(
+            wants,
+            |sha| object_path(sha).exists(),
+            |sha| self.read_remote(sha),
+            &remote::shallow_commits(&self.dir),
+            filter,
+        )?;
+        // Referred objects first, so an interrupted fetch never leaves an object without them
+        for sha in missing.iter().rev() {
+            fs::copy(self.object_path(sha), object_path(sha))?;
+        }
+        Ok(Fetched {
+            objects: missing.len(),
+            shallow,
+        })
+    }
+
+    fn push(&mut self, updates: &[RefUpdate]) -> anyhow::Result<()> {
+        let wants: Vec<String> = updates.iter().map(|update| update.new.clone()).collect();
+        let missing = remote::objects_to_push(&wants, |sha| self.object_path(sha).exists())?;
+        for sha in missing.iter().rev() {
+            fs::copy(object_path(sha), self.object_path(sha))?;
+        }
+        remote::update_refs(&self.dir, updates)
+    }
+}
@@ -0,0 +1,620 @@
+// Remotes are configured like:
+/*
+ * [Remote "origin"]
+ * Url = /mnt/share/project      (a repository or its .gilltter directory)
+ * [Branch "main"]
+ * Remote = origin               (what pull and fetch use on this branch)
+ * Merge = main                  (branch of the remote pull takes)
+ */
+// Refs are exchanged as names relative to .gilltter: "branches/<name>" and "tags/<name>",
+// plus "HEAD" holding whatever the remote's 'head' holds.
+// Objects are sent as they are stored, every object reachable from the wanted ones
+// which the receiving side does not have yet. Having an object means having everything
+// it refers to, so the walk stops there. Two kinds of clones break that rule:
+// - shallow ones (clone --depth) list the commits they have without parents in .gilltter/shallow,
+//   such commits are loaded without a parent so history walks stop at them
+// - partial ones (clone --filter blob:none, Remote.<name>.Filter in the config) have no blobs
+//   until something reads them, then they are fetched from that remote
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{
+        GILLTER_BRANCHES_DIR, GILLTER_HEAD_FILE, GILLTER_OBJECTS_DIR, GILLTTER_PATH,
+        GILLTTER_REMOTES_DIR, GILLTTER_SHALLOW_FILE, GILLTTER_TAGS_DIR, object_path,
+    },
+    config,
+    objects::{
+        self, ObjectPump,
+        tree::{Tree, TreeObject},
+    },
+    refs, utils,
+};
+
+pub mod bundle;
+pub mod http;
+pub mod local;
+pub mod pack;
+pub mod protocol;
+
+pub const CONFIG_REMOTE_SECTION: &'static str = "Remote";
+pub const CONFIG_URL_FIELD: &'static str = "Url";
+pub const CONFIG_BRANCH_SECTION: &'static str = "Branch";
+pub const CONFIG_REMOTE_FIELD: &'static str = "Remote";
+pub const CONFIG_MERGE_FIELD: &'static str = "Merge";
+pub const CONFIG_FILTER_FIELD: &'static str = "Filter";
+pub const BLOB_NONE_FILTER: &'static str = "blob:none";
+pub const DEFAULT_REMOTE: &'static str = "origin";
+pub const HEAD_REF: &'static str = "HEAD";
+
+/// Moves the ref of the remote from `old` (None if it must not exist) to `new`
+#[derive(Clone, Debug)]
+pub struct RefUpdate {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// What a fetch leaves out
+#[derive(Clone, Debug, Default)]
+pub struct FetchFilter {
+    /// Commits of history to take from every wanted commit, the last ones become shallow
+    pub depth: Option<usize>,
+    /// No blobs, they are fetched when something reads them
+    pub no_blobs: bool,
+    /// Only the wanted objects themselves, not what they refer to
+    pub wanted_only: bool,
+}
+
+impl FetchFilter {
+    /// Filter of every fetch from the remote: partial clones keep fetching without blobs
+    pub fn of_remote(name: &str) -> anyhow::Result<Self> {
+        let filter = config::load_layered()?.get(
+            &format!("{}.{}", CONFIG_REMOTE_SECTION, name),
+            CONFIG_FILTER_FIELD,
+        );
+        Ok(Self {
+            no_blobs: match filter.as_deref() {
+                None => false,
+                Some(BLOB_NONE_FILTER) => true,
+                Some(other) => return Err(anyhow!("Unknown filter '{}'", other)),
+            },
+            ..Self::default()
+        })
+    }
+}
+
+pub struct Fetched {
+    /// How many objects were stored
+    pub objects: usize,
+    /// Commits which came without their parents
+    pub shallow: Vec<String>,
+}
+
+pub trait Transport {
+    /// Refs as the remote tells them, name -> hash ("HEAD" -> contents of its head)
+    fn advertised_refs(&mut self) -> anyhow::Result<BTreeMap<String, String>>;
+    /// Stores every object reachable from `wants` which is missing here, minus what the
+    /// filter leaves out
+    fn fetch(&mut self, wants: &[String], filter: &FetchFilter) -> anyhow::Result<Fetched>;
+    /// Sends the objects the remote needs for the updates, then applies them.
+    /// Every ref is compared and swapped, a ref which moved meanwhile fails the push
+    fn push(&mut self, updates: &[RefUpdate]) -> anyhow::Result<()>;
+
+    /// Refs of the remote. Their names and hashes become paths here, so they are checked first
+    fn list_refs(&mut self) -> anyhow::Result<BTreeMap<String, String>> {
+        let refs = self.advertised_refs()?;
+        check_refs(&refs)?;
+        Ok(refs)
+    }
+}
+
+/// Refs a remote may have: branches and tags with valid names holding hashes,
+/// and HEAD which may also be empty or attached to a branch
+pub fn check_refs(refs: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    let branch_prefix = format!("{}/", GILLTER_BRANCHES_DIR);
+    let tag_prefix = format!("{}/", GILLTTER_TAGS_DIR);
+    for (name, value) in refs {
+        let checked = if name == HEAD_REF {
+            match value.strip_prefix(refs::HEAD_REF_PREFIX) {
+                Some(target) => target
+                    .strip_prefix(&branch_prefix)
+                    .ok_or(anyhow!("'{}' is not a branch", target))
+                    .and_then(refs::check_ref_name),
+                None if value.is_empty() => Ok(()),
+                None => refs::check_hash(value),
+            }
+        } else {
+            name.strip_prefix(&branch_prefix)
+                .or_else(|| name.strip_prefix(&tag_prefix))
+                .ok_or(anyhow!("not a branch or a tag"))
+                .and_then(refs::check_ref_name)
+                .and_then(|_| refs::check_hash(value))
+        };
+        checked.map_err(|why| anyhow!("The remote has a broken ref '{}': {}", name, why))?;
+    }
+    Ok(())
+}
+
+/// Url of the configured remote
+pub fn remote_url(name: &str) -> anyhow::Result<String> {
+    config::load_layered()?
+        .get(
+            &format!("{}.{}", CONFIG_REMOTE_SECTION, name),
+            CONFIG_URL_FIELD,
+        )
+        .ok_or(anyhow!(
+            "There is no remote '{}', try: gilltter config set {}.{}.{} <url>",
+            name,
+            CONFIG_REMOTE_SECTION,
+            name,
+            CONFIG_URL_FIELD
+        ))
+}
+
+/// Value of Branch.<branch>.<field>, e.g. the remote the branch is pulled from
+pub fn branch_setting(branch: &str, field: &str) -> anyhow::Result<Option<String>> {
+    Ok(config::load_layered()?.get(&format!("{}.{}", CONFIG_BRANCH_SECTION, branch), field))
+}
+
+/// Urls of commands, ssh and http are kept, paths become absolute so they work from any directory
+pub fn normalize_url(url: &str) -> anyhow::Result<String> {
+    if url.starts_with(protocol::EXT_URL_PREFIX)
+        || url.starts_with(protocol::SSH_URL_PREFIX)
+        || url.starts_with(http::HTTP_URL_PREFIX)
+    {
+        return Ok(url.to_string());
+    }
+    Ok(PathBuf::from(url)
+        .canonicalize()
+        .map_err(|why| anyhow!("Could not find repository '{}': {}", url, why))?
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Transport for the url: "ext::<command>" and "ssh://[<user>@]<host>/<path>" talk to
+/// `gilltter serve`, "http://..." to `gilltter http-serve`, anything else is a local path
+/// of a repository or of a bundle
+pub fn open(url: &str) -> anyhow::Result<Box<dyn Transport>> {
+    if let Some(command) = url.strip_prefix(protocol::EXT_URL_PREFIX) {
+        return Ok(Box::new(protocol::ProtocolTransport::spawn_shell(command)?));
+    }
+    if let Some(location) = url.strip_prefix(protocol::SSH_URL_PREFIX) {
+        return Ok(Box::new(protocol::ProtocolTransport::spawn_ssh(location)?));
+    }
+    if url.starts_with(http::HTTP_URL_PREFIX) {
+        return Ok(Box::new(http::HttpTransport::connect(url)?));
+    }
+    if bundle::is_bundle(Path::new(url)) {
+        return Ok(Box::new(bundle::BundleTransport::open(url)?));
+    }
+    Ok(Box::new(local::LocalTransport::open(url)?))
+}
+
+/// .gilltter directory of the repository at path, the path may name it directly
+pub fn repository_dir(path: &Path) -> anyhow::Result<PathBuf> {
+    let dir = if path.join(GILLTTER_PATH).is_dir() {
+        path.join(GILLTTER_PATH)
+    } else {
+        path.to_path_buf()
+    };
+    if !dir.join(GILLTER_OBJECTS_DIR).is_dir() {
+        return Err(anyhow!(
+            "'{}' is not a gilltter repository",
+            path.to_string_lossy()
+        ));
+    }
+    Ok(dir)
+}
+
+/// Uncompressed object of this repository, fetched first if a partial clone lacks it
+pub fn read_local(sha: &str) -> anyhow::Result<Vec<u8>> {
+    if !object_path(sha).exists() {
+        fetch_promised(&[sha.to_string()])?;
+    }
+    let contents =
+        fs::read(object_path(sha)).map_err(|why| anyhow!("Missing object {}: {}", sha, why))?;
+    utils::decompress(&contents)
+}
+
+/// Fetches objects missing from a partial clone from the remote it was cloned from.
+/// Does nothing outside of partial clones
+pub fn fetch_promised(shas: &[String]) -> anyhow::Result<()> {
+    let missing: Vec<String> = shas
+        .iter()
+        .filter(|sha| !object_path(sha).exists())
+        .cloned()
+        .collect();
+    if missing.is_empty() || !Path::new(GILLTTER_PATH).is_dir() {
+        return Ok(());
+    }
+    let config = config::load_layered()?;
+    let Some(name) = config
+        .subsections(CONFIG_REMOTE_SECTION)
+        .into_iter()
+        .find(|name| {
+            config
+                .get(
+                    &format!("{}.{}", CONFIG_REMOTE_SECTION, name),
+                    CONFIG_FILTER_FIELD,
+                )
+                .is_some()
+        })
+    else {
+        return Ok(());
+    };
+    let filter = FetchFilter {
+        wanted_only: true,
+        ..FetchFilter::default()
+    };
+    open(&remote_url(&name)?)?.fetch(&missing, &filter)?;
+    Ok(())
+}
+
+/// Commits of the repository with the .gilltter directory `dir` which are stored without parents
+pub fn shallow_commits(dir: &Path) -> BTreeSet<String> {
+    fs::read_to_string(dir.join(GILLTTER_SHALLOW_FILE))
+        .map(|text| text.lines().map(|line| line.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Records commits which were fetched without their parents
+pub fn add_shallow_commits(shas: &[String]) -> anyhow::Result<()> {
+    let dir = Path::new(GILLTTER_PATH);
+    let mut shallow = shallow_commits(dir);
+    if shas.iter().all(|sha| shallow.contains(sha)) {
+        return Ok(());
+    }
+    shallow.extend(shas.iter().cloned());
+    let text: String = shallow.iter().map(|sha| format!("{}\n", sha)).collect();
+    fs::write(dir.join(GILLTTER_SHALLOW_FILE), text)?;
+    Ok(())
+}
+
+fn list_kind(dir: &Path, kind: &str, refs: &mut BTreeMap<String, String>) -> anyhow::Result<()> {
+    let dir = dir.join(kind);
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let sha = refs::read_trimmed(&entry.path())?;
+        if !name.ends_with(".lock") && !sha.is_empty() {
+            refs.insert(format!("{}/{}", kind, name), sha);
+        }
+    }
+    Ok(())
+}
+
+/// Refs of the repository with the .gilltter directory `dir`, see the top of the file
+pub fn list_refs(dir: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut refs = BTreeMap::new();
+    refs.insert(
+        HEAD_REF.to_string(),
+        refs::read_trimmed(&dir.join(GILLTER_HEAD_FILE))?,
+    );
+    list_kind(dir, GILLTER_BRANCHES_DIR, &mut refs)?;
+    list_kind(dir, GILLTTER_TAGS_DIR, &mut refs)?;
+    Ok(refs)
+}
+
+/// Applies pushed updates to the repository with the .gilltter directory `dir`.
+/// The branch its HEAD is on is refused, its work tree would not match it anymore
+pub fn update_refs(dir: &Path, updates: &[RefUpdate]) -> anyhow::Result<()> {
+    let head = refs::read_trimmed(&dir.join(GILLTER_HEAD_FILE))?;
+    for update in updates {
+        if head.strip_prefix(refs::HEAD_REF_PREFIX) == Some(update.name.as_str()) {
+            return Err(anyhow!(
+                "'{}' is checked out in the remote repository, it can't be pushed to",
+                update.name
+            ));
+        }
+        let short_name = update
+            .name
+            .strip_prefix(&format!("{}/", GILLTER_BRANCHES_DIR))
+            .or_else(|| update.name.strip_prefix(&format!("{}/", GILLTTER_TAGS_DIR)))
+            .ok_or(anyhow!("'{}' is not a branch or a tag", update.name))?;
+        refs::check_ref_name(short_name)?;
+    }
+    for update in updates {
+        refs::compare_and_swap(
+            &dir.join(&update.name),
+            Some(update.old.as_deref().unwrap_or_default()),
+            Some(&update.new),
+        )?;
+    }
+    Ok(())
+}
+
+/// Hashes of everything this repository has refs to: branches, tags, HEAD and branches of remotes
+pub fn local_tips() -> anyhow::Result<Vec<String>> {
+    let mut tips: Vec<String> = list_refs(Path::new(GILLTTER_PATH))?
+        .into_values()
+        .filter(|value| !value.is_empty() && !value.starts_with(refs::HEAD_REF_PREFIX))
+        .collect();
+    let remotes_dir = Path::new(GILLTTER_PATH).join(GILLTTER_REMOTES_DIR);
+    if remotes_dir.is_dir() {
+        for remote in fs::read_dir(remotes_dir)? {
+            for branch in fs::read_dir(remote?.path())? {
+                tips.push(refs::read_trimmed(&branch?.path())?);
+            }
+        }
+    }
+    tips.sort();
+    tips.dedup();
+    Ok(tips)
+}
+
+/// Objects the other side surely has if it has the `tips` commits: the commits and
+/// their ancestors, and whatever the trees of the tips hold. Tips this side does not know are skipped
+pub fn known_objects(
+    tips: &[String],
+    read: impl Fn(&str) -> anyhow::Result<Vec<u8>>,
+    exists: impl Fn(&str) -> bool,
+) -> anyhow::Result<BTreeSet<String>> {
+    let mut known = BTreeSet::new();
+    let mut tips = tips.to_vec();
+    while let Some(tip) = tips.pop() {
+        if !exists(&tip) || known.contains(&tip) {
+            continue;
+        }
+        let data = read(&tip)?;
+        if data.starts_with(b"tag ") {
+            known.insert(tip);
+            tips.extend(objects::object_links(&data)?);
+            continue;
+        }
+        if !data.starts_with(b"commit ") {
+            continue;
+        }
+        // Everything in the snapshot of the tip
+        let mut stack = objects::object_links(&data)?;
+        stack.truncate(1); // the tree, the parent comes below
+        while let Some(sha) = stack.pop() {
+            if exists(&sha) && known.insert(sha.clone()) {
+                stack.extend(objects::object_links(&read(&sha)?)?);
+            }
+        }
+        // The commits down the history, but not their trees
+        known.insert(tip.clone());
+        let mut commit = objects::object_links(&data)?.into_iter().nth(1);
+        while let Some(sha) = commit.take() {
+            if !exists(&sha) || !known.insert(sha.clone()) {
+                break;
+            }
+            commit = objects::object_links(&read(&sha)?)?.into_iter().nth(1);
+        }
+    }
+    Ok(known)
+}
+
+/// Every object reachable from `wants` which `has` says the receiving side is missing,
+/// `read` gives uncompressed objects of the sending side. Parents of the `shallow` commits
+/// are not followed, neither are blobs with `no_blobs`. Referred objects come after
+/// the objects referring to them
+pub fn missing_objects(
+    wants: &[String],
+    has: impl Fn(&str) -> bool,
+    read: impl Fn(&str) -> anyhow::Result<Vec<u8>>,
+    shallow: &BTreeSet<String>,
+    no_blobs: bool,
+) -> anyhow::Result<Vec<String>> {
+    let mut missing = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut stack: Vec<String> = wants.to_vec();
+    while let Some(sha) = stack.pop() {
+        if !seen.insert(sha.clone()) || has(&sha) {
+            continue;
+        }
+        let data = read(&sha)?;
+        if data.starts_with(b"commit ") && shallow.contains(&sha) {
+            stack.extend(objects::object_links(&data)?.into_iter().take(1));
+        } else if data.starts_with(b"tree ") && no_blobs {
+            for object in Tree::from_raw_data(&data)?.objects.values() {
+                if let TreeObject::Tree(subtree) = object {
+                    stack.push(subtree.get_hash()?);
+                }
+            }
+        } else {
+            stack.extend(objects::object_links(&data)?);
+        }
+        missing.push(sha);
+    }
+    Ok(missing)
+}
+
+/// Objects a fetch sends (see missing_objects) and the commits among them which go without
+/// parents: those `filter.depth` commits down from a wanted one and the `shallow` commits of the sender
+pub fn objects_to_send(
+    wants: &[String],
+    has: impl Fn(&str) -> bool,
+    read: impl Fn(&str) -> anyhow::Result<Vec<u8>>,
+    shallow: &BTreeSet<String>,
+    filter: &FetchFilter,
+) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    if filter.wanted_only {
+        // Whatever the sender does not have is left out, the receiver sees what came
+        let present = wants.iter().filter(|sha| read(sha).is_ok()).cloned();
+        return Ok((present.collect(), Vec::new()));
+    }
+    let mut cut = shallow.clone();
+    if let Some(depth) = filter.depth {
+        for want in wants {
+            let (mut sha, mut data) = (want.clone(), read(want)?);
+            while data.starts_with(b"tag ") {
+                sha = objects::object_links(&data)?
+                    .pop()
+                    .ok_or(anyhow!("Tag {} has no target", sha))?;
+                data = read(&sha)?;
+            }
+            if !data.starts_with(b"commit ") {
+                continue;
+            }
+            for _ in 1..depth {
+                match objects::object_links(&data)?.into_iter().nth(1) {
+                    Some(parent) if !cut.contains(&sha) && !has(&parent) => {
+                        data = read(&parent)?;
+                        sha = parent;
+                    }
+                    _ => break,
+                }
+            }
+            if objects::object_links(&data)?.len() > 1 {
+                cut.insert(sha);
+            }
+        }
+    }
+    let missing = missing_objects(wants, has, read, &cut, filter.no_blobs)?;
+    let shallow = missing
+        .iter()
+        .filter(|sha| cut.contains(*sha))
+        .cloned()
+        .collect();
+    Ok((missing, shallow))
+}
+
+/// Objects of this repository a push sends. History of a shallow clone ends early,
+/// so a push which would need what is beyond is refused
+pub fn objects_to_push(
+    wants: &[String],
+    has: impl Fn(&str) -> bool,
+) -> anyhow::Result<Vec<String>> {
+    let shallow = shallow_commits(Path::new(GILLTTER_PATH));
+    let missing = missing_objects(wants, has, read_local, &shallow, false)?;
+    if let Some(sha) = missing.iter().find(|sha| shallow.contains(*sha)) {
+        return Err(anyhow!(
+            "The remote lacks the history before {}, which this shallow clone does not have",
+            refs::short_hash(sha)
+        ));
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{
+            ObjectDump,
+            blob::Blob,
+            commit::Commit,
+            tree::{FileType, Tree, TreeObject},
+        },
+        utils,
+    };
+
+    // hash -> uncompressed object
+    fn store(objects: &mut BTreeMap<String, Vec<u8>>, data: Vec<u8>) -> String {
+        let sha = utils::generate_hash(&data);
+        objects.insert(sha.clone(), data);
+        sha
+    }
+
+    fn commit(
+        objects: &mut BTreeMap<String, Vec<u8>>,
+        text: &str,
+        parent: Option<String>,
+    ) -> String {
+        let mut blob = Blob::new();
+        blob.set_data(text.as_bytes());
+        let blob_sha = store(objects, blob.convert_to_bytes().unwrap());
+        let mut tree = Tree::new();
+        tree.add_object("file", TreeObject::Blob(FileType::RegularFile, blob_sha));
+        let tree_sha = store(objects, tree.convert_to_bytes().unwrap());
+
+        let mut commit = Commit::new();
+        commit
+            .set_tree_sha(tree_sha)
+            .set_parent_commit_sha(parent)
+            .set_username("Pencil".to_string())
+            .set_email("pedosia@gmail.com".to_string())
+            .set_message(text.to_string())
+            .set_secs_since_epoch(Some(0));
+        store(objects, commit.convert_to_bytes().unwrap())
+    }
+
+    #[test]
+    fn walk_stops_at_objects_the_receiver_has() {
+        let mut objects = BTreeMap::new();
+        let first = commit(&mut objects, "first", None);
+        let second = commit(&mut objects, "second", Some(first.clone()));
+        let read = |sha: &str| Ok(objects[sha].clone());
+
+        let none = BTreeSet::new();
+        let everything =
+            missing_objects(std::slice::from_ref(&second), |_| false, read, &none, false).unwrap();
+        assert_eq!(everything.len(), 6);
+        assert_eq!(everything[0], second);
+
+        let has = |sha: &str| sha == first;
+        let missing =
+            missing_objects(std::slice::from_ref(&second), has, read, &none, false).unwrap();
+        assert_eq!(missing.len(), 3);
+        assert!(!missing.contains(&first));
+    }
+
+    #[test]
+    fn depth_and_blob_filters_cut_the_walk() {
+        let mut objects = BTreeMap::new();
+        let first = commit(&mut objects, "first", None);
+        let second = commit(&mut objects, "second", Some(first.clone()));
+        let third = commit(&mut objects, "third", Some(second.clone()));
+        let read = |sha: &str| Ok(objects[sha].clone());
+        let none = BTreeSet::new();
+        let wants = std::slice::from_ref(&third);
+
+        let filter = FetchFilter {
+            depth: Some(2),
+            ..FetchFilter::default()
+        };
+        let (sent, shallow) = objects_to_send(wants, |_| false, read, &none, &filter).unwrap();
+        assert_eq!(sent.len(), 6);
+        assert!(!sent.contains(&first));
+        assert_eq!(shallow, vec![second]);
+
+        let filter = FetchFilter {
+            no_blobs: true,
+            ..FetchFilter::default()
+        };
+        let (sent, shallow) = objects_to_send(wants, |_| false, read, &none, &filter).unwrap();
+        assert_eq!(sent.len(), 6);
+        assert!(sent.iter().all(|sha| !objects[sha].starts_with(b"blob ")));
+        assert!(shallow.is_empty());
+    }
+
+    #[test]
+    fn advertised_refs_are_checked() {
+        let sha = "a".repeat(40);
+        let refs =
+            |name: &str, value: &str| BTreeMap::from([(name.to_string(), value.to_string())]);
+        check_refs(&refs(HEAD_REF, "ref: branches/main")).unwrap();
+        check_refs(&refs(HEAD_REF, "")).unwrap();
+        check_refs(&refs(HEAD_REF, &sha)).unwrap();
+        check_refs(&refs("branches/main", &sha)).unwrap();
+        check_refs(&refs("tags/v1.0", &sha)).unwrap();
+
+        for (name, value) in [
+            ("branches/../../../../pwned", sha.as_str()),
+            ("tags/a/b", &sha),
+            ("remotes/origin/main", &sha),
+            ("branches/main", "../../objects/x"),
+            (HEAD_REF, "ref: branches/../../x"),
+            (HEAD_REF, "ref: tags/v1.0"),
+            (HEAD_REF, "garbage"),
+        ] {
+            assert!(
+                check_refs(&refs(name, value)).is_err(),
+                "{} {}",
+                name,
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,110 @@
+// Objects are transferred in packs:
+/*
+ * PACK <count>\n
+ * <hash> <size>\n<size bytes of the object as it is stored (compressed)>
+ * ...
+ */
+// Objects are written in the order given, referred objects should come first.
+// A pack may come from anyone, so nothing is allocated before its bytes arrive.
+
+use std::io::{BufRead, Read, Write};
+
+use anyhow::anyhow;
+
+use crate::{base::object_path, utils};
+
+const PACK_MAGIC: &'static str = "PACK";
+// Longer than any header or entry line
+const MAX_LINE_SIZE: u64 = 128;
+
+/// Writes the stored objects into a pack
+pub fn write_pack(shas: &[String], output: &mut impl Write) -> anyhow::Result<()> {
+    writeln!(output, "{} {}", PACK_MAGIC, shas.len())?;
+    for sha in shas {
+        let contents = std::fs::read(object_path(sha))
+            .map_err(|why| anyhow!("Could not read object {}: {}", sha, why))?;
+        writeln!(output, "{} {}", sha, contents.len())?;
+        output.write_all(&contents)?;
+    }
+    Ok(())
+}
+
+fn read_line(input: &mut impl BufRead) -> anyhow::Result<String> {
+    let mut line = String::new();
+    input.by_ref().take(MAX_LINE_SIZE).read_line(&mut line)?;
+    line.strip_suffix('\n')
+        .map(|line| line.to_string())
+        .ok_or(anyhow!("Pack ends too early"))
+}
+
+/// Stores the objects of the pack which are not here yet, every object is checked
+/// against its hash first. Returns the hashes of all objects of the pack
+pub fn read_pack(input: &mut impl BufRead) -> anyhow::Result<Vec<String>> {
+    let header = read_line(input)?;
+    let count = header
+        .strip_prefix(PACK_MAGIC)
+        .and_then(|count| count.trim().parse::<usize>().ok())
+        .ok_or(anyhow!("Not a pack: '{}'", header))?;
+
+    let mut shas = Vec::new();
+    for _ in 0..count {
+        let line = read_line(input)?;
+        let (sha, size) = line
+            .split_once(' ')
+            .ok_or(anyhow!("Broken pack entry '{}'", line))?;
+        let size: u64 = size.parse()?;
+        let mut contents = Vec::new();
+        input.by_ref().take(size).read_to_end(&mut contents)?;
+        if contents.len() as u64 != size {
+            return Err(anyhow!("Pack ends in object {}", sha));
+        }
+
+        if utils::generate_hash(&utils::decompress(&contents)?) != sha {
+            return Err(anyhow!("Object {} in the pack is corrupted", sha));
+        }
+        let path = object_path(sha);
+        if !path.exists() {
+            std::fs::write(path, &contents)?;
+        }
+        shas.push(sha.to_string());
+    }
+    Ok(shas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempRepository;
+
+    #[test]
+    fn objects_survive_a_pack() {
+        let repository = TempRepository::new();
+        repository.add("a.txt", "a\n");
+        let commit = repository.commit("first");
+        let objects =
+            crate::remote::objects_to_push(std::slice::from_ref(&commit), |_| false).unwrap();
+        assert_eq!(objects.len(), 3);
+
+        let mut data = Vec::new();
+        write_pack(&objects, &mut data).unwrap();
+        std::fs::remove_file(object_path(&commit)).unwrap();
+        assert_eq!(read_pack(&mut data.as_slice()).unwrap(), objects);
+        assert!(object_path(&commit).exists());
+
+        // The first object claims to be something else
+        let header_len = "PACK 3\n".len();
+        data[header_len..header_len + 40].copy_from_slice("0".repeat(40).as_bytes());
+        let why = read_pack(&mut data.as_slice()).unwrap_err();
+        assert!(why.to_string().contains("corrupted"));
+    }
+
+    #[test]
+    fn claimed_sizes_are_not_trusted() {
+        let pack = format!("PACK {}\n", usize::MAX);
+        assert!(read_pack(&mut pack.as_bytes()).is_err());
+        let pack = format!("PACK 1\n{} {}\nshort", "a".repeat(40), u64::MAX);
+        assert!(read_pack(&mut pack.as_bytes()).is_err());
+        let pack = format!("PACK 1\n{}", "a".repeat(1000));
+        assert!(read_pack(&mut pack.as_bytes()).is_err());
+    }
+}
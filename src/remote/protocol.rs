@@ -0,0 +1,430 @@
+// `gilltter serve` talks over stdin/stdout in packets: 4 hex digits of length (the digits
+// included) and the data, "0000" is a flush packet which ends a list or a stream of data.
+// Text packets hold one line each. A session goes like:
+/*
+ * server: <ref name> <value>...  flush         ref advertisement, see remote/mod.rs
+ * client: fetch
+ *         want <hash>... have <hash>...      optional: deepen <depth>, filter blob:none, only
+ *         flush
+ * server: ok (or error <message>)
+ *         shallow <hash>...  flush           commits sent without their parents
+ *         <pack>  flush                      pack split into packets, see pack.rs
+ * client: push
+ *         update <ref name> <old hash or -> <new hash>...  flush
+ *         <pack>  flush
+ * server: ok (or error <message>)
+ */
+// and so on until the client closes its end.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Cursor, Read, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{GILLTTER_PATH, object_path},
+    refs,
+    remote::{self, FetchFilter, Fetched, RefUpdate, Transport, pack},
+};
+
+pub const EXT_URL_PREFIX: &'static str = "ext::";
+pub const SSH_URL_PREFIX: &'static str = "ssh://";
+const FLUSH_PACKET: &'static [u8] = b"0000";
+const PACKET_HEADER_LEN: usize = 4;
+const MAX_PACKET_DATA: usize = 0xffff - PACKET_HEADER_LEN;
+const NO_HASH: &'static str = "-";
+
+pub fn write_packet(output: &mut impl Write, data: &[u8]) -> anyhow::Result<()> {
+    write!(output, "{:04x}", data.len() + PACKET_HEADER_LEN)?;
+    output.write_all(data)?;
+    Ok(())
+}
+
+pub fn write_flush(output: &mut impl Write) -> anyhow::Result<()> {
+    output.write_all(FLUSH_PACKET)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Data of the next packet, None for a flush packet
+pub fn read_packet(input: &mut impl Read) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; PACKET_HEADER_LEN];
+    input
+        .read_exact(&mut header)
+        .map_err(|why| anyhow!("Connection closed: {}", why))?;
+    let len = usize::from_str_radix(&String::from_utf8_lossy(&header), 16)
+        .map_err(|_| anyhow!("Broken packet header {:?}", header))?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if len < PACKET_HEADER_LEN {
+        return Err(anyhow!("Broken packet length {}", len));
+    }
+    let mut data = vec![0u8; len - PACKET_HEADER_LEN];
+    input.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+fn write_line(output: &mut impl Write, line: &str) -> anyhow::Result<()> {
+    write_packet(output, line.as_bytes())
+}
+
+/// Text packets up to the next flush
+fn read_lines(input: &mut impl Read) -> anyhow::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    while let Some(data) = read_packet(input)? {
+        lines.push(String::from_utf8_lossy(&data).to_string());
+    }
+    Ok(lines)
+}
+
+fn write_data(output: &mut impl Write, data: &[u8]) -> anyhow::Result<()> {
+    for chunk in data.chunks(MAX_PACKET_DATA) {
+        write_packet(output, chunk)?;
+    }
+    write_flush(output)
+}
+
+fn read_data(input: &mut impl Read) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = read_packet(input)? {
+        data.extend(chunk);
+    }
+    Ok(data)
+}
+
+fn read_status(input: &mut impl Read) -> anyhow::Result<()> {
+    let status = read_packet(input)?.ok_or(anyhow!("The server sent no status"))?;
+    let status = String::from_utf8_lossy(&status);
+    match status.strip_prefix("error ") {
+        Some(message) => Err(anyhow!("The server says: {}", message)),
+        None if status == "ok" => Ok(()),
+        None => Err(anyhow!("Unexpected answer '{}'", status)),
+    }
+}
+
+/// Pack of the objects, referred ones go first
+fn pack_of(mut objects: Vec<String>) -> anyhow::Result<Vec<u8>> {
+    objects.reverse();
+    let mut data = Vec::new();
+    pack::write_pack(&objects, &mut data)?;
+    Ok(data)
+}
+
+/// Lines of a fetch, None if every wanted object is here already
+pub fn fetch_request(
+    wants: &[String],
+    filter: &FetchFilter,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let mut lines: Vec<String> = wants
+        .iter()
+        .filter(|sha| !object_path(sha).exists())
+        .map(|sha| format!("want {}", sha))
+        .collect();
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    if let Some(depth) = filter.depth {
+        lines.push(format!("deepen {}", depth));
+    }
+    if filter.no_blobs {
+        lines.push(format!("filter {}", remote::BLOB_NONE_FILTER));
+    }
+    if filter.wanted_only {
+        lines.push("only".to_string());
+    } else {
+        lines.extend(
+            remote::local_tips()?
+                .into_iter()
+                .map(|sha| format!("have {}", sha)),
+        );
+    }
+    Ok(Some(lines))
+}
+
+/// Lines of a fetch -> shallow lines and the pack
+pub fn answer_fetch(lines: &[String]) -> anyhow::Result<(Vec<String>, Vec<u8>)> {
+    let (mut wants, mut haves) = (Vec::new(), Vec::new());
+    let mut filter = FetchFilter::default();
+    for line in lines {
+        match line.split_once(' ').unwrap_or((line, "")) {
+            ("want", sha) => {
+                refs::check_hash(sha)?;
+                wants.push(sha.to_string());
+            }
+            ("have", sha) => {
+                refs::check_hash(sha)?;
+                haves.push(sha.to_string());
+            }
+            ("deepen", depth) => filter.depth = Some(depth.parse()?),
+            ("filter", remote::BLOB_NONE_FILTER) => filter.no_blobs = true,
+            ("only", "") => filter.wanted_only = true,
+            _ => return Err(anyhow!("Unexpected line '{}'", line)),
+        }
+    }
+    let mut known =
+        remote::known_objects(&haves, remote::read_local, |sha| object_path(sha).exists())?;
+    // A partial clone may want what its commits refer to
+    for sha in &wants {
+        known.remove(sha);
+    }
+    let (objects, shallow) = remote::objects_to_send(
+        &wants,
+        |sha| known.contains(sha),
+        remote::read_local,
+        &remote::shallow_commits(Path::new(GILLTTER_PATH)),
+        &filter,
+    )?;
+    let shallow = shallow
+        .into_iter()
+        .map(|sha| format!("shallow {}", sha))
+        .collect();
+    Ok((shallow, pack_of(objects)?))
+}
+
+/// Commits from the shallow lines of a fetch answer
+pub fn shallow_commits(lines: &[String]) -> anyhow::Result<Vec<String>> {
+    lines
+        .iter()
+        .map(|line| {
+            line.strip_prefix("shallow ")
+                .map(|sha| sha.to_string())
+                .ok_or(anyhow!("Unexpected line '{}'", line))
+        })
+        .collect()
+}
+
+/// update lines of a push and the pack with the objects the remote is missing,
+/// judging by the refs it advertised
+pub fn push_request(
+    updates: &[RefUpdate],
+    advertised: &BTreeMap<String, String>,
+) -> anyhow::Result<(Vec<String>, Vec<u8>)> {
+    let tips: Vec<String> = advertised.values().cloned().collect();
+    let known = remote::known_objects(&tips, remote::read_local, |sha| object_path(sha).exists())?;
+    let wants: Vec<String> = updates.iter().map(|update| update.new.clone()).collect();
+    let objects = remote::objects_to_push(&wants, |sha| known.contains(sha))?;
+    let lines = updates
+        .iter()
+        .map(|update| {
+            format!(
+                "update {} {} {}",
+                update.name,
+                update.old.as_deref().unwrap_or(NO_HASH),
+                update.new
+            )
+        })
+        .collect();
+    Ok((lines, pack_of(objects)?))
+}
+
+/// Stores the pushed objects and applies the update lines
+pub fn accept_push(lines: &[String], pack_data: Vec<u8>) -> anyhow::Result<()> {
+    let mut updates = Vec::new();
+    for line in lines {
+        let mut parts = line.splitn(4, ' ');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("update"), Some(name), Some(old), Some(new)) => {
+                if old != NO_HASH {
+                    refs::check_hash(old)?;
+                }
+                refs::check_hash(new)?;
+                updates.push(RefUpdate {
+                    name: name.to_string(),
+                    old: (old != NO_HASH).then(|| old.to_string()),
+                    new: new.to_string(),
+                });
+            }
+            _ => return Err(anyhow!("Unexpected line '{}'", line)),
+        }
+    }
+    pack::read_pack(&mut Cursor::new(pack_data))?;
+    remote::update_refs(Path::new(GILLTTER_PATH), &updates)
+}
+
+/// Serves the repository in the current directory until the client closes the input
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> anyhow::Result<()> {
+    for (name, value) in remote::list_refs(Path::new(GILLTTER_PATH))? {
+        write_line(&mut output, &format!("{} {}", name, value))?;
+    }
+    write_flush(&mut output)?;
+
+    while !input.fill_buf()?.is_empty() {
+        let command = read_packet(&mut input)?.unwrap_or_default();
+        match command.as_slice() {
+            b"fetch" => match read_lines(&mut input).and_then(|lines| answer_fetch(&lines)) {
+                Ok((shallow, pack_data)) => {
+                    write_line(&mut output, "ok")?;
+                    for line in shallow {
+                        write_line(&mut output, &line)?;
+                    }
+                    write_flush(&mut output)?;
+                    write_data(&mut output, &pack_data)?;
+                }
+                Err(why) => write_line(&mut output, &format!("error {}", why))?,
+            },
+            b"push" => match read_lines(&mut input).and_then(|lines| {
+                let pack_data = read_data(&mut input)?;
+                accept_push(&lines, pack_data)
+            }) {
+                Ok(()) => write_line(&mut output, "ok")?,
+                Err(why) => write_line(&mut output, &format!("error {}", why))?,
+            },
+            _ => {
+                return Err(anyhow!(
+                    "Unknown command '{}'",
+                    String::from_utf8_lossy(&command)
+                ));
+            }
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Client end of a `gilltter serve` running in a child process
+pub struct ProtocolTransport {
+    child: Child,
+    input: BufReader<ChildStdout>,
+    output: Option<ChildStdin>,
+    refs: BTreeMap<String, String>,
+}
+
+impl ProtocolTransport {
+    pub fn spawn(mut command: Command) -> anyhow::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|why| anyhow!("Could not start the server: {}", why))?;
+        let mut input = BufReader::new(child.stdout.take().ok_or(anyhow!("No server output"))?);
+        let output = child.stdin.take();
+
+        let mut refs = BTreeMap::new();
+        for line in read_lines(&mut input)? {
+            let (name, value) = line.split_once(' ').unwrap_or((&line, ""));
+            refs.insert(name.to_string(), value.to_string());
+        }
+        Ok(Self {
+            child,
+            input,
+            output,
+            refs,
+        })
+    }
+
+    /// Runs the command through the shell, it should start `gilltter serve`
+    pub fn spawn_shell(command: &str) -> anyhow::Result<Self> {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        Self::spawn(shell)
+    }
+
+    /// "[<user>@]<host>/<path>", runs `gilltter serve <path>` on the host
+    pub fn spawn_ssh(location: &str) -> anyhow::Result<Self> {
+        let (host, path) = location
+            .split_once('/')
+            .ok_or(anyhow!("ssh url should look like ssh://<host>/<path>"))?;
+        let mut ssh = Command::new("ssh");
+        ssh.arg(host)
+            .arg(format!("gilltter serve '/{}'", path.replace('\'', "'\\''")));
+        Self::spawn(ssh)
+    }
+
+    fn output(&mut self) -> anyhow::Result<&mut ChildStdin> {
+        self.output.as_mut().ok_or(anyhow!("Connection closed"))
+    }
+}
+
+impl Transport for ProtocolTransport {
+    fn advertised_refs(&mut self) -> anyhow::Result<BTreeMap<String, String>> {
+        Ok(self.refs.clone())
+    }
+
+    fn fetch(&mut self, wants: &[String], filter: &FetchFilter) -> anyhow::Result<Fetched> {
+        let Some(lines) = fetch_request(wants, filter)? else {
+            return Ok(Fetched {
+                objects: 0,
+                shallow: Vec::new(),
+            });
+        };
+        let output = self.output()?;
+        write_line(output, "fetch")?;
+        for line in lines {
+            write_line(output, &line)?;
+        }
+        write_flush(output)?;
+
+        read_status(&mut self.input)?;
+        let shallow = shallow_commits(&read_lines(&mut self.input)?)?;
+        let pack_data = read_data(&mut self.input)?;
+        Ok(Fetched {
+            objects: pack::read_pack(&mut Cursor::new(pack_data))?.len(),
+            shallow,
+        })
+    }
+
+    fn push(&mut self, updates: &[RefUpdate]) -> anyhow::Result<()> {
+        let (lines, pack_data) = push_request(updates, &self.refs)?;
+        let output = self.output()?;
+        write_line(output, "push")?;
+        for line in lines {
+            write_line(output, &line)?;
+        }
+        write_flush(output)?;
+        write_data(output, &pack_data)?;
+        read_status(&mut self.input)
+    }
+}
+
+impl Drop for ProtocolTransport {
+    fn drop(&mut self) {
+        // Closing the input ends the session
+        self.output.take();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_survives_packets() {
+        let data: Vec<u8> = (0..MAX_PACKET_DATA * 2 + 10).map(|i| i as u8).collect();
+        let mut stream = Vec::new();
+        write_line(&mut stream, "fetch").unwrap();
+        write_data(&mut stream, &data).unwrap();
+        write_data(&mut stream, &[]).unwrap();
+
+        let mut input = Cursor::new(stream);
+        assert_eq!(read_packet(&mut input).unwrap(), Some(b"fetch".to_vec()));
+        assert_eq!(read_data(&mut input).unwrap(), data);
+        assert_eq!(read_packet(&mut input).unwrap(), None);
+        assert!(read_packet(&mut input).is_err());
+    }
+
+    #[test]
+    fn only_hashes_are_wanted_and_pushed() {
+        let sha = "a".repeat(40);
+        let escape = format!("../../../secret/.gilltter/objects/{}", sha);
+        for lines in [
+            vec![format!("want {}", escape), "only".to_string()],
+            vec![format!("want {}", sha), format!("have {}", escape)],
+            vec![format!("want {}", sha.to_uppercase())],
+        ] {
+            let err = answer_fetch(&lines).err().unwrap();
+            assert!(err.to_string().contains("not an object hash"), "{}", err);
+        }
+        for line in [
+            format!("update branches/main - {}", escape),
+            format!("update branches/main {} {}", escape, sha),
+        ] {
+            assert!(accept_push(&[line], Vec::new()).is_err());
+        }
+    }
+}
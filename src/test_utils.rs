@@ -0,0 +1,113 @@
+// Repositories for tests. Commands find the repository through the current directory,
+// so tests which need one take turns: each gets a fresh repository as the current
+// directory, and the directory before is restored when it is dropped.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, MutexGuard,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    base::{
+        self, GILLTER_BRANCHES_DIR, GILLTER_CONFIG_FILE, GILLTTER_HOOKS_DIR, GILLTTER_INDEX_FILE,
+        GILLTTER_PATH,
+    },
+    commands::commit,
+    config::{self, Config},
+    index::index::Index,
+    objects::{ObjectDump, ObjectPump},
+    refs,
+};
+
+static CURRENT_DIR_LOCK: Mutex<()> = Mutex::new(());
+static REPOSITORIES: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TempRepository {
+    pub path: PathBuf,
+    previous_dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TempRepository {
+    /// An empty repository on branch main with a configured user, entered
+    pub fn new() -> Self {
+        // A test which failed while holding the lock leaves it poisoned, the directory is fine
+        let lock = CURRENT_DIR_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let path = std::env::temp_dir().join(format!(
+            "gilltter-test-{}-{}",
+            std::process::id(),
+            REPOSITORIES.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        let previous_dir = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&path).unwrap();
+
+        base::gilltter_init().unwrap();
+        refs::write_head(&format!(
+            "{}{}/main",
+            refs::HEAD_REF_PREFIX,
+            GILLTER_BRANCHES_DIR
+        ))
+        .unwrap();
+        let mut config = Config::load(&Path::new(GILLTTER_PATH).join(GILLTER_CONFIG_FILE)).unwrap();
+        config.set(
+            config::CONFIG_GENERAL_SECTION,
+            config::CONFIG_USERNAME_FIELD,
+            "tester",
+        );
+        config.set(
+            config::CONFIG_GENERAL_SECTION,
+            config::CONFIG_EMAIL_FIELD,
+            "tester@example.com",
+        );
+        config.dump_to_file().unwrap();
+
+        Self {
+            path,
+            previous_dir,
+            _lock: lock,
+        }
+    }
+
+    /// Writes a file of the work tree and stages it
+    pub fn add(&self, path: &str, contents: &str) {
+        let path = Path::new(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+        crate::commands::add::add(path).unwrap();
+    }
+
+    /// Writes an executable shell script hook
+    #[cfg(unix)]
+    pub fn hook(&self, name: &str, script: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = Path::new(GILLTTER_PATH).join(GILLTTER_HOOKS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Commits the index without hooks, returns the commit
+    pub fn commit(&self, message: &str) -> String {
+        let index = Index::from_file(&Path::new(GILLTTER_PATH).join(GILLTTER_INDEX_FILE)).unwrap();
+        commit::commit_as(&index, message.to_string(), None).unwrap()
+    }
+}
+
+impl Drop for TempRepository {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.previous_dir);
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
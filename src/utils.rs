@@ -41,6 +41,41 @@ pub const fn get_separator() -> &'static str {
     "\\"
 }
 
+/// UTC (year, month, day, hour, minute, second) of a time
+pub fn civil_time(secs_since_epoch: u64) -> (i64, u64, u64, u64, u64, u64) {
+    let days = (secs_since_epoch / 86400) as i64;
+    let secs_of_day = secs_since_epoch % 86400;
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month as u64,
+        day as u64,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+    )
+}
+
+/// UTC time like "2025-01-31 23:59:59 +0000"
+pub fn format_timestamp(secs_since_epoch: u64) -> String {
+    let (year, month, day, hour, minute, second) = civil_time(secs_since_epoch);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000",
+        year, month, day, hour, minute, second
+    )
+}
+
 pub fn generate_hash(content: &[u8]) -> String {
     // uncompressed (raw) bytes
     let mut hasher = Sha1::new();
@@ -65,10 +100,22 @@ pub fn decompress(contents: &[u8]) -> anyhow::Result<Vec<u8>> {
     Ok(buf)
 }
 
-pub fn get_file_contents_as_blob(path: &Path) -> anyhow::Result<Vec<u8>> {
+/// What is stored for a file: its contents, or the path it points to for a symbolic link
+pub fn read_file_data(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if path.symlink_metadata()?.is_symlink() {
+        return Ok(std::fs::read_link(path)?
+            .to_string_lossy()
+            .as_bytes()
+            .to_vec());
+    }
     let mut file = std::fs::File::open(path)?;
     let mut bytes = Vec::new();
     file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn get_file_contents_as_blob(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let bytes = read_file_data(path)?;
     let mut blob = Blob::new();
     blob.set_data(&bytes);
 
@@ -0,0 +1,126 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    base::object_path,
+    objects::{ObjectPump, blob::Blob, tree::FileType},
+    remote, utils,
+};
+
+/// Writes the contents of a stored blob to path, creating parent dirs
+pub fn write_file(path: &Path, blob_sha: &str) -> anyhow::Result<()> {
+    let blob = Blob::from_file(&object_path(blob_sha))?;
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    // Written as a file, a symbolic link becomes one again in apply_modes
+    if path
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.is_symlink())
+    {
+        fs::remove_file(path)?;
+    }
+    let mut file = fs::File::create(path)
+        .map_err(|why| anyhow!("Could not write '{}': {}", path.to_string_lossy(), why))?;
+    file.write_all(&blob.get_data())?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Removes a file, then its parent dirs while they are empty
+pub fn remove_file(path: &Path) -> anyhow::Result<()> {
+    if path.exists() {
+        fs::remove_file(path)
+            .map_err(|why| anyhow!("Could not delete '{}': {}", path.to_string_lossy(), why))?;
+    }
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+/// Hash of the file in the work tree as if it was stored as a blob, None if there is no such file
+pub fn file_hash(path: &Path) -> Option<String> {
+    if !path
+        .symlink_metadata()
+        .is_ok_and(|metadata| !metadata.is_dir())
+    {
+        return None;
+    }
+    utils::get_file_contents_as_blob(path)
+        .ok()
+        .map(|blob| utils::generate_hash(&blob))
+}
+
+/// Makes tracked files of the work tree look like `to`: files from `tracked`
+/// which are not in `to` are removed, every file which differs is rewritten.
+/// Untracked files are left alone
+pub fn checkout_files(
+    tracked: &BTreeMap<PathBuf, String>,
+    to: &BTreeMap<PathBuf, String>,
+) -> anyhow::Result<()> {
+    for path in tracked.keys() {
+        if !to.contains_key(path) {
+            remove_file(path)?;
+        }
+    }
+    let changed: Vec<(&PathBuf, &String)> = to
+        .iter()
+        .filter(|(path, sha)| file_hash(path).as_ref() != Some(*sha))
+        .collect();
+    // A partial clone fetches the blobs it lacks at once rather than one by one
+    let shas: Vec<String> = changed.iter().map(|(_, sha)| (*sha).clone()).collect();
+    remote::fetch_promised(&shas)?;
+    for (path, sha) in changed {
+        write_file(path, sha)?;
+    }
+    Ok(())
+}
+
+/// Gives the files of the work tree the modes of `files` (path -> (mode, blob hash)):
+/// executable files get their x bits, regular ones lose them, symbolic links are made
+/// from the path they hold
+pub fn apply_modes(files: &BTreeMap<PathBuf, (FileType, String)>) -> anyhow::Result<()> {
+    for (path, (mode, sha)) in files {
+        let Ok(metadata) = path.symlink_metadata() else {
+            continue;
+        };
+        match mode {
+            FileType::ExecutableFile if !metadata.is_symlink() => {
+                let mut permissions = metadata.permissions();
+                permissions.set_mode(permissions.mode() | 0o111);
+                fs::set_permissions(path, permissions)?;
+            }
+            FileType::RegularFile
+                if !metadata.is_symlink() && metadata.permissions().mode() & 0o111 != 0 =>
+            {
+                let mut permissions = metadata.permissions();
+                permissions.set_mode(permissions.mode() & !0o111);
+                fs::set_permissions(path, permissions)?;
+            }
+            FileType::SymbolicLink if !metadata.is_symlink() => {
+                let target = Blob::from_file(&object_path(sha))?.get_data();
+                fs::remove_file(path)?;
+                std::os::unix::fs::symlink(String::from_utf8_lossy(&target).as_ref(), path)
+                    .map_err(|why| {
+                        anyhow!("Could not link '{}': {}", path.to_string_lossy(), why)
+                    })?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}